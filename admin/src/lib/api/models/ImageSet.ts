@@ -4,8 +4,14 @@
 
 export type ImageSet = {
 	created_at: string;
+	/**
+	 * The file extension, e.g. `webp`. Empty on images resized by the admin before uploading.
+	 */
+	format?: string | null;
+	height?: number | null;
 	id: string;
 	path: string;
 	size: string;
 	updated_at: string;
+	width?: number | null;
 };
@@ -2,8 +2,8 @@ import type { ImageSet } from './api';
 
 export function srcset(image: any) {
 	let srcset = '';
-	let images = image.image_set.sort(
-		(a: any, b: any) => sizeFromString(a.size).width - sizeFromString(b.size).width
+	let images = preferredSet(image.image_set).sort(
+		(a: any, b: any) => imageWidth(a) - imageWidth(b)
 	);
 	let srcsets = images.map(buildSrc);
 	srcset = srcsets.join(', ');
@@ -15,10 +15,21 @@ export function sizeFromString(size: string): { width: number; height: number }
 	return { width: parseInt(width), height: parseInt(height) };
 }
 
+// Images resized by the admin before uploading only have their size as `WxH`.
+export function imageWidth(image_set: ImageSet): number {
+	return image_set.width ?? sizeFromString(image_set.size).width;
+}
+
+// The api generates every size in the original format and in webp, webp is preferred.
+export function preferredSet(image_set: ImageSet[]): ImageSet[] {
+	let webp = image_set.filter((image) => image.format === 'webp');
+	return webp.length > 0 ? webp : image_set;
+}
+
 export function buildSrc(image_set: ImageSet) {
 	return `${image_set.path}`;
 }
 
 export function buildSrcset(image_set: ImageSet) {
-	return `${image_set.path} ${sizeFromString(image_set.size).width}w`;
+	return `${image_set.path} ${imageWidth(image_set)}w`;
 }
@@ -1,13 +1,19 @@
 use aide::axum::ApiRouter;
 use aide::{openapi::OpenApi, transform::TransformOpenApi};
+use axum::http::{header, HeaderName, Method};
 use axum::routing::get;
-use axum::Router;
 use axum::{debug_handler, extract::State, http::StatusCode, Extension};
+use axum::{middleware, Router};
+use shared::dns::{DnsResolver, DohResolver};
+use shared::mail::{FileTransport, LogTransport, MailTransport, Mailer, PostmarkTransport};
+use shared::rate_limit::{MemoryBackend, PostgresBackend, RateLimiter};
 use shared::{database_pool, AppState, OpendalUploader};
 use sqlx::PgPool;
-use std::{net::SocketAddr, sync::Arc};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
+use accounts::RateLimitGroup;
 use opendal::{services, Operator};
+use tower_http::cors::{AllowOrigin, CorsLayer};
 use tracing::info;
 use tracing_subscriber::layer::SubscriberExt;
 
@@ -86,44 +92,85 @@ async fn main() -> Result<(), axum::BoxError> {
     let pool = database_pool(&db_str).await;
     sqlx::migrate!("../migrations").run(&pool).await?;
 
+    accounts::configure_argon2().await?;
+
     let opendal = opendal_operator()?;
 
     let app_state = AppState {
         db_pool: pool,
         opendal: OpendalUploader(Arc::new(opendal)),
+        mailer: Mailer(mail_transport()?),
+        dns: DnsResolver(Arc::new(dns_resolver())),
     };
 
+    // `api worker` only runs the job runner, so it can be scaled apart from the http server.
+    if std::env::args().nth(1).as_deref() == Some("worker") {
+        job_runner(app_state).run().await;
+        return Ok(());
+    }
+
+    if std::env::var("DISABLE_EMBEDDED_WORKERS").is_err() {
+        job_runner(app_state.clone()).spawn();
+    }
+
+    register_feature_flags();
+
     aide::gen::extract_schemas(true);
 
     let mut api = OpenApi::default();
     let mut public_api = OpenApi::default();
 
+    let rate_limiter = rate_limiter(&app_state.db_pool);
+    let limited = |group| {
+        middleware::from_fn_with_state(
+            RateLimitGroup::new(group, app_state.db_pool.clone(), rate_limiter.clone()),
+            accounts::rate_limit,
+        )
+    };
+
     let admin = ApiRouter::new()
         .nest("/accounts", accounts::routes())
         .nest("/blogs", blogs::routes())
         .nest("/clippings", clippings::routes())
-        .nest("/media", media::routes());
+        .nest("/media", media::routes())
+        .nest("/leads", leads::routes())
+        .nest("/pages", pages::routes())
+        .nest("/workers", workers::routes())
+        .nest("/audit", audit::routes())
+        .layer(limited("admin"));
 
     // Prefixes all paths with /admin and generate the private docs.
     let admin = ApiRouter::new()
         .nest("/admin", admin)
         .nest("/admin/docs", docs_routes())        
         .finish_api_with(&mut api, api_docs)
-        .layer(Extension(Arc::new(api)));
+        .layer(Extension(Arc::new(api)))
+        .layer(admin_cors());
 
     aide::gen::extract_schemas(true);
 
     // Public routes without a prefix, then generate the public docs.
-    let public = ApiRouter::new()
+    let content = ApiRouter::new()
         .nest("/blogs", blogs::public_routes())
         .nest("/media", media::public_routes())
         .nest("/clippings", clippings::public_routes())
-        .nest("/analytics", analytics::public_routes())
+        .nest("/leads", leads::public_routes())
+        .nest("/pages", pages::public_routes())
+        .layer(limited("public"));
+    let public = ApiRouter::new()
+        .merge(content)
+        .nest(
+            "/analytics",
+            analytics::public_routes().layer(limited("analytics")),
+        )
         .nest("/docs", public_docs())
         .finish_api_with(&mut public_api, public_api_docs)
-        .layer(Extension(Arc::new(public_api)));
+        .layer(Extension(Arc::new(public_api)))
+        .layer(middleware::from_fn_with_state(
+            app_state.db_pool.clone(),
+            accounts::public_cors,
+        ));
 
-    // TODO: set up rate limiting, cors, etc.
     let app = Router::new()
         .merge(admin)
         .merge(public)
@@ -135,12 +182,25 @@ async fn main() -> Result<(), axum::BoxError> {
     info!("Listening on {}", address);
 
     axum::Server::bind(&address)
-        .serve(app.into_make_service())
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
     Ok(())
 }
 
+/// Jobs are registered here, every app exposing jobs adds them to the runner.
+fn job_runner(state: AppState) -> workers::Runner {
+    workers::Runner::new(state)
+        .register::<media::GenerateImageVariants>()
+        .register::<media::CollectOrphanedImages>()
+}
+
+/// Feature flags are registered here, every app gating its routes with a flag adds it,
+/// so accounts can change it in their settings.
+fn register_feature_flags() {
+    accounts::register_feature_flag::<clippings::Clippings>();
+}
+
 fn init_tracing() -> Result<(), axum::BoxError> {
     use tracing_subscriber::filter::EnvFilter;
 
@@ -158,6 +218,74 @@ fn init_tracing() -> Result<(), axum::BoxError> {
     Ok(())
 }
 
+/// `MAIL_TRANSPORT=postmark` sends the emails with Postmark (`POSTMARK_SERVER_TOKEN`, from
+/// `MAIL_FROM`), `file` writes them to `MAIL_DIR` (./tmp/mails by default) and `log` only logs
+/// who they're for.
+/// The api doesn't start without one, password resets and invitations would never arrive.
+fn mail_transport() -> Result<Arc<dyn MailTransport>, axum::BoxError> {
+    let env = |name: &str| std::env::var(name).map_err(|_| format!("{name} is not set"));
+    let transport: Arc<dyn MailTransport> = match env("MAIL_TRANSPORT")?.as_str() {
+        "postmark" => Arc::new(PostmarkTransport::new(
+            env("POSTMARK_SERVER_TOKEN")?,
+            env("MAIL_FROM")?,
+        )),
+        "file" => Arc::new(FileTransport {
+            dir: std::env::var("MAIL_DIR")
+                .unwrap_or_else(|_| "./tmp/mails".into())
+                .into(),
+        }),
+        "log" => Arc::new(LogTransport),
+        transport => return Err(format!("MAIL_TRANSPORT {transport} is not supported").into()),
+    };
+    Ok(transport)
+}
+
+/// The admin is only called from the origins in `ADMIN_ALLOWED_ORIGINS` (comma separated),
+/// none by default. The public routes allow the origins of each account, see `accounts::public_cors`.
+fn admin_cors() -> CorsLayer {
+    let origins = std::env::var("ADMIN_ALLOWED_ORIGINS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .filter_map(|origin| origin.parse().ok())
+        .collect::<Vec<_>>();
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            HeaderName::from_static("x-api-key"),
+        ])
+        .max_age(Duration::from_secs(3600))
+}
+
+/// `RATE_LIMIT_BACKEND=postgres` shares the rate limits between the instances of the api,
+/// otherwise each instance keeps them in memory.
+fn rate_limiter(pool: &PgPool) -> RateLimiter {
+    match std::env::var("RATE_LIMIT_BACKEND").as_deref() {
+        Ok("postgres") => RateLimiter(Arc::new(PostgresBackend::new(pool.clone()))),
+        _ => RateLimiter(Arc::new(MemoryBackend::default())),
+    }
+}
+
+/// TXT records (custom domain verification) are looked up with DNS over HTTPS,
+/// at `DNS_RESOLVER_URL` when set.
+fn dns_resolver() -> DohResolver {
+    match std::env::var("DNS_RESOLVER_URL") {
+        Ok(url) => DohResolver::new(url),
+        Err(_) => DohResolver::default(),
+    }
+}
+
 fn opendal_operator() -> Result<opendal::Operator, opendal::Error> {
     // let mut builder = services::Fs::default();
     // builder.root("./tmp");
@@ -11,6 +11,9 @@ use sqlx::PgConnection;
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::api_keys::{create_default_api_key, CreatedApiKey};
+use crate::authorization::Role;
+use crate::feature_flags::AccountFeatureFlags;
 use crate::users::CreateUserWithPasswordInput;
 
 /// Account represents a single account.
@@ -20,21 +23,18 @@ pub struct Account {
     pub name: String,
     pub subdomain: String,
     pub origin: Option<String>,
+    /// Origins allowed by CORS on the public api besides `origin`, e.g. `https://*.customer.com`.
+    pub allowed_origins: Vec<String>,
     #[schemars(with = "AccountFeatureFlags")]
     pub feature_flags: sqlx::types::Json<AccountFeatureFlags>,
     pub updated_at: NaiveDateTime,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
-pub struct AccountFeatureFlags {
-    #[serde(default)]
-    pub clipping_enabled: bool,
-}
-
 /// AccountKey represents a single key for an account.
 /// We use this to authenticate requests to the API.
-/// An account can have multiple keys.
-#[derive(Debug, Clone, Serialize)]
+/// An account can have multiple keys, the newest valid one signs the access tokens
+/// while the others keep verifying the tokens they signed until they expire.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct AccountKey {
     pub id: Uuid,
     #[serde(skip)]
@@ -42,6 +42,10 @@ pub struct AccountKey {
     #[serde(skip)]
     pub keypair: Vec<u8>,
     pub expires_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    /// Whether the key is neither revoked nor expired, by the clock of the database.
+    pub is_valid: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -61,7 +65,7 @@ pub async fn create_account(
         r#"
         INSERT INTO accounts.accounts (name, subdomain, origin)
         VALUES ($1, $2, $3)
-        RETURNING id, name, subdomain, origin, 
+        RETURNING id, name, subdomain, origin, allowed_origins, 
         feature_flags as "feature_flags!: sqlx::types::Json<AccountFeatureFlags>",
         updated_at
         "#,
@@ -82,7 +86,8 @@ pub async fn create_account_key(
         r#"
         INSERT INTO accounts.account_keys (account_id, keypair)
         VALUES ($1, $2)
-        RETURNING id, account_id, keypair, expires_at
+        RETURNING id, account_id, keypair, expires_at, revoked_at, created_at,
+        (revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())) as "is_valid!"
         "#,
         account_id,
         &keypair.as_ref()
@@ -91,6 +96,107 @@ pub async fn create_account_key(
     .await
 }
 
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct ExpireAccountKeyInput {
+    /// Minutes the key keeps verifying tokens (and working as an api key) before it expires,
+    /// so clients have time to move to the new key. Default is a day.
+    #[validate(range(min = 0, max = 43200))]
+    #[serde(default = "default_overlap_minutes")]
+    pub overlap_minutes: i32,
+}
+
+fn default_overlap_minutes() -> i32 {
+    24 * 60
+}
+
+/// Locks the keys of the account until the transaction ends, so concurrent expiries and
+/// revocations check for another valid key one after the other.
+pub async fn lock_account_keys(
+    conn: &mut PgConnection, account_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT id FROM accounts.account_keys
+        WHERE account_id = $1
+        ORDER BY id
+        FOR UPDATE
+        "#,
+        account_id,
+    )
+    .fetch_all(conn)
+    .await?;
+    Ok(())
+}
+
+/// Whether the account has another valid key that outlives the given number of minutes,
+/// an account must always keep a key to sign access tokens.
+/// The keys must be locked with [`lock_account_keys`] before checking.
+pub async fn has_other_valid_account_key(
+    conn: &mut PgConnection, account_id: Uuid, account_key_id: Uuid, minutes: i32,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM accounts.account_keys
+            WHERE account_id = $1
+            AND id <> $2
+            AND revoked_at IS NULL
+            AND (expires_at IS NULL OR expires_at > NOW() + make_interval(mins => $3))
+        ) as "exists!"
+        "#,
+        account_id,
+        account_key_id,
+        minutes,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Schedules the expiry of a key, it can only be brought forward.
+pub async fn expire_account_key(
+    conn: &mut PgConnection, account_id: Uuid, account_key_id: Uuid, overlap_minutes: i32,
+) -> Result<AccountKey, sqlx::Error> {
+    sqlx::query_as!(
+        AccountKey,
+        r#"
+        UPDATE accounts.account_keys
+        SET expires_at = LEAST(expires_at, NOW() + make_interval(mins => $3))
+        WHERE id = $2
+        AND account_id = $1
+        AND revoked_at IS NULL
+        RETURNING id, account_id, keypair, expires_at, revoked_at, created_at,
+        (revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())) as "is_valid!"
+        "#,
+        account_id,
+        account_key_id,
+        overlap_minutes,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Revokes a key right away, tokens it signed stop working.
+pub async fn revoke_account_key(
+    conn: &mut PgConnection, account_id: Uuid, account_key_id: Uuid,
+) -> Result<AccountKey, sqlx::Error> {
+    sqlx::query_as!(
+        AccountKey,
+        r#"
+        UPDATE accounts.account_keys
+        SET revoked_at = NOW()
+        WHERE id = $2
+        AND account_id = $1
+        AND revoked_at IS NULL
+        RETURNING id, account_id, keypair, expires_at, revoked_at, created_at,
+        (revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())) as "is_valid!"
+        "#,
+        account_id,
+        account_key_id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
 /// CreatePublicAccountInput is used to create a new account with a user.
 /// This is used when a user signs up for a new account.
 #[derive(Debug, Deserialize, Validate)]
@@ -104,9 +210,17 @@ pub struct CreatePublicAccountInput {
     pub origin: Option<String>,
 }
 
+/// A new account, with the api key of its frontend which is only returned here.
+#[derive(Debug, Serialize)]
+pub struct CreatedAccount {
+    #[serde(flatten)]
+    pub account: Account,
+    pub api_key: CreatedApiKey,
+}
+
 pub async fn create_account_with_user(
     pool: &sqlx::PgPool, input: CreatePublicAccountInput,
-) -> Result<Account, anyhow::Error> {
+) -> Result<CreatedAccount, anyhow::Error> {
     let account_input = CreateAccountInput {
         name: input.name,
         subdomain: input.subdomain,
@@ -122,13 +236,15 @@ pub async fn create_account_with_user(
         account_id: account.id,
         email: input.email,
         password: input.password,
+        role: Role::Owner,
     };
 
-    crate::users::create_user_with_password(&mut tx, user_input).await?;
+    let user = crate::users::create_user_with_password(&mut tx, user_input).await?;
+    let api_key = create_default_api_key(&mut tx, account.id, user.id).await?;
 
     tx.commit().await?;
 
-    Ok(account)
+    Ok(CreatedAccount { account, api_key })
 }
 
 pub async fn get_account_by_subdomain(
@@ -137,7 +253,7 @@ pub async fn get_account_by_subdomain(
     sqlx::query_as!(
         Account,
         r#"
-        SELECT id, name, subdomain, origin,
+        SELECT id, name, subdomain, origin, allowed_origins,
         feature_flags as "feature_flags!: sqlx::types::Json<AccountFeatureFlags>",
         updated_at
         FROM accounts.accounts
@@ -149,23 +265,35 @@ pub async fn get_account_by_subdomain(
     .await
 }
 
-pub async fn get_account_by_x_api_key(
-    conn: &sqlx::PgPool, x_api_key: Uuid,
-) -> Result<Account, sqlx::Error> {
+pub async fn get_account_by_id(conn: &sqlx::PgPool, id: Uuid) -> Result<Account, sqlx::Error> {
     sqlx::query_as!(
         Account,
         r#"
-        SELECT id, name, subdomain, origin,
+        SELECT id, name, subdomain, origin, allowed_origins,
         feature_flags as "feature_flags!: sqlx::types::Json<AccountFeatureFlags>",
         updated_at
         FROM accounts.accounts
-        WHERE id = (
-            SELECT account_id
-            FROM accounts.account_keys
-            WHERE id = $1 and (expires_at is null or expires_at > now())
-        )
+        WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Reads the account and locks it until the transaction ends, for a change based on it.
+pub async fn lock_account(conn: &mut PgConnection, id: Uuid) -> Result<Account, sqlx::Error> {
+    sqlx::query_as!(
+        Account,
+        r#"
+        SELECT id, name, subdomain, origin, allowed_origins,
+        feature_flags as "feature_flags!: sqlx::types::Json<AccountFeatureFlags>",
+        updated_at
+        FROM accounts.accounts
+        WHERE id = $1
+        FOR UPDATE
         "#,
-        x_api_key
+        id
     )
     .fetch_one(conn)
     .await
@@ -177,11 +305,13 @@ pub async fn get_valid_account_key(
     sqlx::query_as!(
         AccountKey,
         r#"
-        SELECT id, account_id, keypair, expires_at
+        SELECT id, account_id, keypair, expires_at, revoked_at, created_at,
+        (revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())) as "is_valid!"
         FROM accounts.account_keys
         WHERE account_id = $1
          AND (expires_at is null OR expires_at > now())
-        ORDER BY expires_at DESC
+         AND revoked_at IS NULL
+        ORDER BY created_at DESC, id DESC
         "#,
         account_id,
     )
@@ -195,7 +325,8 @@ pub async fn get_account_key_by_id(
     sqlx::query_as!(
         AccountKey,
         r#"
-        SELECT id, account_id, keypair, expires_at
+        SELECT id, account_id, keypair, expires_at, revoked_at, created_at,
+        (revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())) as "is_valid!"
         FROM accounts.account_keys        
         WHERE id = $1
         "#,
@@ -211,10 +342,11 @@ pub async fn get_account_keys(
     sqlx::query_as!(
         AccountKey,
         r#"
-        SELECT id, account_id, keypair, expires_at
+        SELECT id, account_id, keypair, expires_at, revoked_at, created_at,
+        (revoked_at IS NULL AND (expires_at IS NULL OR expires_at > NOW())) as "is_valid!"
         FROM accounts.account_keys
         WHERE account_id = $1
-        ORDER BY expires_at DESC
+        ORDER BY created_at DESC, id DESC
         "#,
         account_id,
     )
@@ -0,0 +1,225 @@
+use chrono::NaiveDateTime;
+use rand::{distributions::Alphanumeric, Rng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+use uuid::Uuid;
+use validator::Validate;
+
+/// What an api key can be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum ApiKeyScope {
+    /// Read published content, safe to ship in a frontend.
+    #[serde(rename = "public:read")]
+    PublicRead,
+    /// Send page views and events.
+    #[serde(rename = "analytics:write")]
+    AnalyticsWrite,
+    /// Submit leads from contact forms.
+    #[serde(rename = "leads:write")]
+    LeadsWrite,
+    /// Call the admin api as the user who created the key, with the user's role.
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl ApiKeyScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiKeyScope::PublicRead => "public:read",
+            ApiKeyScope::AnalyticsWrite => "analytics:write",
+            ApiKeyScope::LeadsWrite => "leads:write",
+            ApiKeyScope::Admin => "admin",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ApiKey {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub account_id: Uuid,
+    /// The user who created the key, keys with the admin scope act as this user.
+    pub user_id: Option<Uuid>,
+    pub name: String,
+    /// The beginning of the key, enough to identify it.
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+impl ApiKey {
+    pub fn has_scope(&self, scope: ApiKeyScope) -> bool {
+        self.scopes.iter().any(|s| s == scope.as_str())
+    }
+}
+
+/// The key is only returned when it's created, afterwards only its prefix is known.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct CreateApiKeyInput {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[validate(length(min = 1))]
+    pub scopes: Vec<ApiKeyScope>,
+}
+
+pub(crate) fn hash_api_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .collect()
+}
+
+pub async fn create_api_key(
+    conn: &mut PgConnection, account_id: Uuid, user_id: Uuid, input: CreateApiKeyInput,
+) -> Result<CreatedApiKey, sqlx::Error> {
+    let prefix = format!("csk_{}", random_string(8));
+    let key = format!("{prefix}_{}", random_string(32));
+    let scopes: Vec<String> = input
+        .scopes
+        .iter()
+        .map(|s| s.as_str().to_string())
+        .collect();
+
+    let api_key = sqlx::query_as!(
+        ApiKey,
+        r#"
+        INSERT INTO accounts.api_keys (account_id, user_id, name, prefix, key_hash, scopes)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, account_id, user_id, name, prefix, scopes, last_used_at, revoked_at,
+            created_at
+        "#,
+        account_id,
+        user_id,
+        input.name,
+        prefix,
+        hash_api_key(&key),
+        &scopes,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(CreatedApiKey { api_key, key })
+}
+
+/// The key of the frontend of a new account, for its public content, analytics and leads.
+pub async fn create_default_api_key(
+    conn: &mut PgConnection, account_id: Uuid, user_id: Uuid,
+) -> Result<CreatedApiKey, sqlx::Error> {
+    let input = CreateApiKeyInput {
+        name: "Default key".into(),
+        scopes: vec![
+            ApiKeyScope::PublicRead,
+            ApiKeyScope::AnalyticsWrite,
+            ApiKeyScope::LeadsWrite,
+        ],
+    };
+    create_api_key(conn, account_id, user_id, input).await
+}
+
+pub async fn list_api_keys(
+    conn: &mut PgConnection, account_id: Uuid,
+) -> Result<Vec<ApiKey>, sqlx::Error> {
+    sqlx::query_as!(
+        ApiKey,
+        r#"
+        SELECT id, account_id, user_id, name, prefix, scopes, last_used_at, revoked_at,
+            created_at
+        FROM accounts.api_keys
+        WHERE account_id = $1
+        ORDER BY created_at DESC, id DESC
+        "#,
+        account_id,
+    )
+    .fetch_all(conn)
+    .await
+}
+
+pub async fn revoke_api_key(
+    conn: &mut PgConnection, account_id: Uuid, api_key_id: Uuid,
+) -> Result<ApiKey, sqlx::Error> {
+    sqlx::query_as!(
+        ApiKey,
+        r#"
+        UPDATE accounts.api_keys
+        SET revoked_at = NOW()
+        WHERE id = $2
+        AND account_id = $1
+        AND revoked_at IS NULL
+        RETURNING id, account_id, user_id, name, prefix, scopes, last_used_at, revoked_at,
+            created_at
+        "#,
+        account_id,
+        api_key_id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Finds a key that isn't revoked and records its usage,
+/// last_used_at is updated at most once a minute to spare writes on busy keys.
+pub async fn get_active_api_key(conn: &sqlx::PgPool, key: &str) -> Result<ApiKey, sqlx::Error> {
+    sqlx::query_as!(
+        ApiKey,
+        r#"
+        WITH api_key AS (
+            SELECT id, account_id, user_id, name, prefix, scopes, last_used_at, revoked_at,
+                created_at
+            FROM accounts.api_keys
+            WHERE key_hash = $1
+            AND revoked_at IS NULL
+        ), touched AS (
+            UPDATE accounts.api_keys
+            SET last_used_at = NOW()
+            WHERE id = (SELECT id FROM api_key)
+            AND (last_used_at IS NULL OR last_used_at < NOW() - INTERVAL '1 minute')
+        )
+        SELECT id as "id!", account_id as "account_id!", user_id, name as "name!",
+            prefix as "prefix!", scopes as "scopes!", last_used_at, revoked_at,
+            created_at as "created_at!"
+        FROM api_key
+        "#,
+        hash_api_key(key),
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Implemented by the marker types in [`scopes`], for [`crate::ScopedAccount`].
+pub trait RequiredScope: Send + Sync {
+    const SCOPE: ApiKeyScope;
+}
+
+/// Marker types for [`crate::ScopedAccount`], one per [`ApiKeyScope`] of the public api.
+pub mod scopes {
+    use super::{ApiKeyScope, RequiredScope};
+
+    macro_rules! required_scope {
+        ($($name:ident),* $(,)?) => {
+            $(
+                #[derive(Debug)]
+                pub struct $name;
+
+                impl RequiredScope for $name {
+                    const SCOPE: ApiKeyScope = ApiKeyScope::$name;
+                }
+            )*
+        };
+    }
+
+    required_scope!(PublicRead, AnalyticsWrite, LeadsWrite);
+}
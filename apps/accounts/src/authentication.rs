@@ -1,33 +1,81 @@
-use crate::passwords::verify_password;
+use crate::authorization::Role;
+use crate::login_attempts::{forget_login_attempt, record_login_attempt, throttle_login_attempt};
+use crate::passwords::{
+    create_password_reset, get_password_hash, needs_rehash, update_password, use_password_reset,
+    verify_dummy_password, verify_password, PASSWORD_RESET_DURATION_MINUTES,
+};
+use crate::sessions::{
+    create_session, generate_refresh_token, is_session_active,
+    revoke_session_by_reused_refresh_token, revoke_user_sessions, rotate_session, Session,
+};
+use crate::two_factor::{
+    attempt_login_challenge, complete_login_challenge, create_login_challenge, is_totp_enabled,
+    verify_second_factor, LoginChallenge,
+};
+use crate::users::{get_user_by_email, get_user_by_id};
+use aide::OperationIo;
 use anyhow::anyhow;
-use axum::{http::Request, middleware::Next, response::Response};
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use ed25519_compact::KeyPair;
 use jwt_compact::{alg::Ed25519, prelude::*, Algorithm};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use shared::AppError;
+use shared::{
+    audit::Actor,
+    client::ClientInfo,
+    mail::{Mail, Mailer},
+    AppError,
+};
+use sqlx::PgConnection;
 use uuid::Uuid;
 
-use crate::accounts::{get_account_key_by_id, get_valid_account_key};
+use crate::accounts::{get_account_by_id, get_account_key_by_id, get_valid_account_key};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, OperationIo)]
 pub struct ConsubClaims {
     pub user_id: Uuid,
     pub account_id: Uuid,
+    /// The role when the token was issued, tokens issued before roles existed are viewers.
+    #[serde(default)]
+    pub role: Role,
+    /// The session the token was issued for, revoking the session invalidates the token.
+    #[serde(default)]
+    pub session_id: Option<Uuid>,
+}
+
+impl ConsubClaims {
+    /// The user of the token as the author of the changes recorded in the audit log.
+    pub fn actor(&self, client: ClientInfo) -> Actor {
+        Actor {
+            account_id: self.account_id,
+            user_id: Some(self.user_id),
+            client,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct AccessToken {
     pub token: String,
     pub expires_at: chrono::DateTime<chrono::Utc>,
+    /// Exchanged for a new access token, each refresh token can only be used once.
+    pub refresh_token: String,
+    pub refresh_token_expires_at: NaiveDateTime,
+    pub session_id: Uuid,
 }
 
+/// Access tokens are short lived, clients keep their session with the refresh token.
+const ACCESS_TOKEN_DURATION_MINUTES: i64 = 15;
+
+/// Clock drift tolerated when checking the expiration and not before claims.
+const TOKEN_LEEWAY_SECONDS: i64 = 60;
+
 type EdSigningKey = <Ed25519 as Algorithm>::SigningKey;
 type EdVerifyingKey = <Ed25519 as Algorithm>::VerifyingKey;
 
 pub fn create_access_token(
-    user_id: Uuid, account_id: Uuid, account_key_id: Uuid, secret_key: Vec<u8>,
+    user_id: Uuid, account_id: Uuid, role: Role, session_id: Option<Uuid>, account_key_id: Uuid,
+    secret_key: Vec<u8>,
 ) -> Result<(String, DateTime<Utc>), anyhow::Error> {
     let header = Header::default().with_key_id(account_key_id.to_string());
     let key = EdSigningKey::from_slice(&secret_key).unwrap();
@@ -35,12 +83,15 @@ pub fn create_access_token(
     let claims = ConsubClaims {
         user_id,
         account_id,
+        role,
+        session_id,
     };
 
     let time_options = TimeOptions::default();
-    let claims = Claims::new(claims)
-        .set_not_before(Utc::now())
-        .set_duration(&time_options, chrono::Duration::days(7));
+    let claims = Claims::new(claims).set_not_before(Utc::now()).set_duration(
+        &time_options,
+        chrono::Duration::minutes(ACCESS_TOKEN_DURATION_MINUTES),
+    );
 
     let token = Ed25519::with_specific_name()
         .token(header, &claims, &key)
@@ -49,36 +100,285 @@ pub fn create_access_token(
     Ok((token, claims.expiration.unwrap()))
 }
 
-pub async fn authenticate_user_with_password(
-    conn: &sqlx::PgPool, account_id: Uuid, email: String, password: String,
+/// Signs an access token for the session, returned along with the session's refresh token.
+async fn issue_access_token(
+    conn: &sqlx::PgPool, role: Role, session: Session, refresh_token: String,
 ) -> Result<AccessToken, anyhow::Error> {
+    let account_key = get_valid_account_key(conn, session.account_id).await?;
+
+    let (token, expires_at) = create_access_token(
+        session.user_id,
+        session.account_id,
+        role,
+        Some(session.id),
+        account_key.id,
+        account_key.keypair,
+    )?;
+
+    Ok(AccessToken {
+        token,
+        expires_at,
+        refresh_token,
+        refresh_token_expires_at: session.expires_at,
+        session_id: session.id,
+    })
+}
+
+/// The outcome of a password login, users with two-factor get a challenge to complete.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum PasswordLogin {
+    AccessToken(AccessToken),
+    Challenge(LoginChallenge),
+}
+
+/// Checks the user's password and starts a new session.
+/// Every attempt is recorded, repeated failures for an email or ip address are throttled
+/// and an unknown email or wrong password get the same response.
+pub async fn create_session_with_password(
+    conn: &sqlx::PgPool, account_id: Uuid, email: String, password: String, client: ClientInfo,
+) -> Result<PasswordLogin, AppError> {
+    let email = email.to_lowercase();
+
+    // The attempt is recorded as failed while the locks are held, so concurrent attempts see it
+    // and are throttled. The password is then checked without holding a connection.
+    let mut db = conn.begin().await?;
+    throttle_login_attempt(&mut db, account_id, &email, &client).await?;
+
     let row = sqlx::query!(
         r#"
-        SELECT u.id as user_id, u.account_id, p.hash_password
+        SELECT u.id as user_id, u.account_id, u.role as "role: Role", p.hash_password
         FROM accounts.passwords p
         INNER JOIN accounts.users u ON (u.id = p.user_id)
         WHERE u.email = $1 and u.account_id = $2
+        AND u.removed_at IS NULL
         "#,
         email,
         account_id,
     )
-    .fetch_one(conn)
+    .fetch_optional(&mut *db)
     .await?;
 
-    verify_password(password, row.hash_password)?;
+    let attempt_id = record_login_attempt(&mut db, account_id, &email, &client, false).await?;
+    db.commit().await?;
+
+    let verified = match &row {
+        Some(row) => verify_password(password.clone(), row.hash_password.clone())
+            .await
+            .is_ok(),
+        None => {
+            let _ = verify_dummy_password(password.clone()).await;
+            false
+        }
+    };
+
+    let row = match row {
+        Some(row) if verified => row,
+        _ => return Err(AppError::Unauthorized("Invalid credentials".into())),
+    };
+
+    let mut db = conn.begin().await?;
+    forget_login_attempt(&mut db, attempt_id).await?;
+    if needs_rehash(&row.hash_password) {
+        update_password(&mut db, row.user_id, password).await?;
+    }
+    db.commit().await?;
+
+    start_login(conn, account_id, row.user_id, &email, row.role, client).await
+}
+
+/// Starts the session of a user whose first factor was checked, users with two-factor
+/// get a challenge instead. Shared by the password and OpenID Connect logins.
+pub(crate) async fn start_login(
+    conn: &sqlx::PgPool, account_id: Uuid, user_id: Uuid, email: &str, role: Role,
+    client: ClientInfo,
+) -> Result<PasswordLogin, AppError> {
+    let mut db = conn.acquire().await?;
+
+    // The attempt is recorded once the challenge is completed, or failed.
+    if is_totp_enabled(&mut db, user_id).await? {
+        let challenge = create_login_challenge(&mut db, account_id, user_id).await?;
+        return Ok(PasswordLogin::Challenge(challenge));
+    }
+
+    record_login_attempt(&mut db, account_id, email, &client, true).await?;
+    drop(db);
+
+    let access_token = start_session(conn, account_id, user_id, role, client).await?;
+    Ok(PasswordLogin::AccessToken(access_token))
+}
 
-    let account_key = get_valid_account_key(conn, account_id).await?;
+/// Completes the login of a user with two-factor, with a TOTP or recovery code.
+pub async fn complete_login_challenge_with_code(
+    conn: &sqlx::PgPool, challenge_token: String, code: String, client: ClientInfo,
+) -> Result<AccessToken, AppError> {
+    let mut db = conn.acquire().await?;
+    let challenge = attempt_login_challenge(&mut db, &challenge_token)
+        .await?
+        .ok_or(AppError::Unauthorized(
+            "The login challenge is not valid or has expired".into(),
+        ))?;
+
+    let user = get_user_by_id(conn, challenge.user_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::Unauthorized("Invalid credentials".into()),
+            e => e.into(),
+        })?;
 
-    let (token, expires_at) =
-        create_access_token(row.user_id, account_id, account_key.id, account_key.keypair)?;
+    let verified = verify_second_factor(&mut db, user.id, &code).await?;
+    record_login_attempt(
+        &mut db,
+        challenge.account_id,
+        &user.email,
+        &client,
+        verified,
+    )
+    .await?;
+    if !verified {
+        return Err(AppError::Unauthorized("Invalid code".into()));
+    }
 
-    Ok(AccessToken { token, expires_at })
+    complete_login_challenge(&mut db, challenge.id).await?;
+    drop(db);
+
+    start_session(conn, challenge.account_id, user.id, user.role, client).await
 }
 
+async fn start_session(
+    conn: &sqlx::PgPool, account_id: Uuid, user_id: Uuid, role: Role, client: ClientInfo,
+) -> Result<AccessToken, AppError> {
+    let refresh_token = generate_refresh_token();
+    let session = {
+        let mut db = conn.acquire().await?;
+        create_session(&mut db, account_id, user_id, &refresh_token, client).await?
+    };
+
+    Ok(issue_access_token(conn, role, session, refresh_token).await?)
+}
+
+/// Same as [`create_session_with_password`], for callers without a request (e.g. tests).
+/// Users with two-factor can't be authenticated this way.
+pub async fn authenticate_user_with_password(
+    conn: &sqlx::PgPool, account_id: Uuid, email: String, password: String,
+) -> Result<AccessToken, AppError> {
+    match create_session_with_password(conn, account_id, email, password, ClientInfo::default())
+        .await?
+    {
+        PasswordLogin::AccessToken(access_token) => Ok(access_token),
+        PasswordLogin::Challenge(_) => Err(AppError::Unauthorized(
+            "The user has two-factor authentication enabled".into(),
+        )),
+    }
+}
+
+/// Exchanges a refresh token for a new access token, rotating the refresh token.
+pub async fn refresh_access_token(
+    conn: &sqlx::PgPool, refresh_token: String, client: ClientInfo,
+) -> Result<AccessToken, AppError> {
+    let new_refresh_token = generate_refresh_token();
+    let session = {
+        let mut db = conn.acquire().await?;
+        match rotate_session(&mut db, &refresh_token, &new_refresh_token, client).await? {
+            Some(session) => session,
+            None => {
+                revoke_session_by_reused_refresh_token(&mut db, &refresh_token).await?;
+                return Err(AppError::Unauthorized(
+                    "The refresh token is not valid".into(),
+                ));
+            }
+        }
+    };
+
+    let user = get_user_by_id(conn, session.user_id).await?;
+
+    Ok(issue_access_token(conn, user.role, session, new_refresh_token).await?)
+}
+
+/// Changes the password of the user after checking the current one,
+/// the other sessions of the user are revoked.
+pub async fn change_password(
+    conn: &mut PgConnection, claims: &ConsubClaims, current_password: String, new_password: String,
+) -> Result<(), AppError> {
+    let hash = get_password_hash(&mut *conn, claims.user_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => {
+                AppError::BadRequest("The user doesn't have a password".into())
+            }
+            e => e.into(),
+        })?;
+    verify_password(current_password, hash)
+        .await
+        .map_err(|_| AppError::BadRequest("The current password is wrong".into()))?;
+
+    update_password(&mut *conn, claims.user_id, new_password).await?;
+    revoke_user_sessions(conn, claims.user_id, claims.session_id).await?;
+    Ok(())
+}
+
+/// Emails a reset token to the user, unknown emails are ignored so the
+/// response doesn't tell which emails have an account.
+pub async fn request_password_reset(
+    conn: &sqlx::PgPool, mailer: &Mailer, account_id: Uuid, email: String,
+) -> Result<(), anyhow::Error> {
+    let user = match get_user_by_email(conn, account_id, &email).await {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let account = get_account_by_id(conn, account_id).await?;
+
+    let token = {
+        let mut db = conn.acquire().await?;
+        create_password_reset(&mut db, user.id).await?
+    };
+
+    let instructions = match account.origin {
+        Some(origin) => format!(
+            "Follow this link to choose a new password: {}/reset-password?token={token}",
+            origin.trim_end_matches('/')
+        ),
+        None => format!("Use this token to choose a new password: {token}"),
+    };
+
+    mailer
+        .send(Mail {
+            to: user.email,
+            subject: format!("Reset your {} password", account.name),
+            body: format!(
+                "Someone asked to reset your password.\n\n{instructions}\n\n\
+                It expires in {PASSWORD_RESET_DURATION_MINUTES} minutes, \
+                ignore this email if you didn't ask for it."
+            ),
+        })
+        .await
+}
+
+/// Sets a new password with a reset token, every session of the user is revoked.
+pub async fn reset_password(
+    conn: &sqlx::PgPool, token: String, new_password: String,
+) -> Result<(), AppError> {
+    let mut tx = conn.begin().await?;
+    let user_id = use_password_reset(&mut tx, &token)
+        .await?
+        .ok_or(AppError::BadRequest(
+            "The reset token is not valid or has expired".into(),
+        ))?;
+
+    update_password(&mut tx, user_id, new_password).await?;
+    revoke_user_sessions(&mut tx, user_id, None).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Validates the signature and the time claims of an access token,
+/// the token is only accepted while the account key that signed it is valid.
 pub async fn get_claims_from_bearer_token(
     conn: &sqlx::PgPool, token: String,
 ) -> Result<ConsubClaims, AppError> {
-    let token = UntrustedToken::new(&token)?;
+    let token = UntrustedToken::new(&token)
+        .map_err(|_| AppError::Unauthorized("The access token is malformed".into()))?;
     let account_key_id = token
         .header()
         .key_id
@@ -90,7 +390,21 @@ pub async fn get_claims_from_bearer_token(
     let account_key_id = Uuid::parse_str(account_key_id)
         .map_err(|_| AppError::BadRequest("Access key id is not in the expected format".into()))?;
 
-    let account_key = get_account_key_by_id(conn, account_key_id).await?;
+    let account_key = get_account_key_by_id(conn, account_key_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::Unauthorized(
+                "The access token was signed with an unknown account key".into(),
+            ),
+            e => e.into(),
+        })?;
+
+    if !account_key.is_valid {
+        return Err(AppError::Unauthorized(
+            "The access token was signed with an expired or revoked account key".into(),
+        ));
+    }
+
     let keypair = KeyPair::from_slice(&account_key.keypair).map_err(|_| {
         anyhow!(format!(
             "Unable to retrieve a valid keypair for the account key id {account_key_id}"
@@ -108,13 +422,28 @@ pub async fn get_claims_from_bearer_token(
             AppError::Unauthorized("Unable to validate the integrity of the access token".into())
         })?;
 
+    let time_options = TimeOptions::from_leeway(chrono::Duration::seconds(TOKEN_LEEWAY_SECONDS));
+    token
+        .claims()
+        .validate_expiration(&time_options)
+        .and_then(|claims| claims.validate_maturity(&time_options))
+        .map_err(|e| AppError::Unauthorized(format!("The access token is not valid: {e}")))?;
+
     let claims = &token.claims().custom;
-    Ok(claims.to_owned())
-}
+    if claims.account_id != account_key.account_id {
+        return Err(AppError::Unauthorized(
+            "The access token doesn't belong to the account of its key".into(),
+        ));
+    }
 
-pub async fn authorization_layer<B>(
-    _claims: ConsubClaims, request: Request<B>, next: Next<B>,
-) -> Result<Response, AppError> {
-    let response = next.run(request).await;
-    Ok(response)
+    if let Some(session_id) = claims.session_id {
+        let mut db = conn.acquire().await?;
+        if !is_session_active(&mut db, session_id).await? {
+            return Err(AppError::Unauthorized(
+                "The session of the access token has ended".into(),
+            ));
+        }
+    }
+
+    Ok(claims.to_owned())
 }
@@ -0,0 +1,162 @@
+use std::{marker::PhantomData, ops::Deref};
+
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use shared::AppError;
+use sqlx::PgPool;
+
+use crate::User;
+
+/// Role of a user within its account, stored in `accounts.users.role`.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type,
+)]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Owner,
+    Admin,
+    Editor,
+    Author,
+    #[default]
+    Viewer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// List and get anything in the admin.
+    ReadContent,
+    /// Create and change posts, pages, clippings and categories.
+    WriteContent,
+    /// Delete posts, pages, clippings and categories.
+    DeleteContent,
+    /// Upload and change images.
+    ManageMedia,
+    /// Follow up on leads: status, assignees and notes.
+    ManageLeads,
+    /// Invite, remove and change the role of users.
+    ManageUsers,
+    /// Account keys, settings and background jobs.
+    ManageAccount,
+    /// Read the audit log of the changes made in the account.
+    ReadAuditLog,
+    /// Add and delete the OpenID Connect providers, their logins are linked to any user.
+    ManageOidcProviders,
+}
+
+impl Role {
+    pub fn permissions(&self) -> &'static [Permission] {
+        use Permission::*;
+        match self {
+            Role::Owner => &[
+                ReadContent,
+                WriteContent,
+                DeleteContent,
+                ManageMedia,
+                ManageLeads,
+                ManageUsers,
+                ManageAccount,
+                ReadAuditLog,
+                ManageOidcProviders,
+            ],
+            Role::Admin => &[
+                ReadContent,
+                WriteContent,
+                DeleteContent,
+                ManageMedia,
+                ManageLeads,
+                ManageUsers,
+                ManageAccount,
+            ],
+            Role::Editor => &[
+                ReadContent,
+                WriteContent,
+                DeleteContent,
+                ManageMedia,
+                ManageLeads,
+            ],
+            Role::Author => &[ReadContent, WriteContent, ManageMedia],
+            Role::Viewer => &[ReadContent],
+        }
+    }
+
+    pub fn has_permission(&self, permission: Permission) -> bool {
+        self.permissions().contains(&permission)
+    }
+}
+
+/// Implemented by the marker types in [`permissions`], so a route declares
+/// the permission it requires in its signature, e.g. `user: Authorized<WriteContent>`.
+pub trait RequiredPermission: Send + Sync {
+    const PERMISSION: Permission;
+}
+
+/// Marker types for [`Authorized`], one per [`Permission`].
+pub mod permissions {
+    use super::{Permission, RequiredPermission};
+
+    macro_rules! required_permission {
+        ($($name:ident),* $(,)?) => {
+            $(
+                #[derive(Debug)]
+                pub struct $name;
+
+                impl RequiredPermission for $name {
+                    const PERMISSION: Permission = Permission::$name;
+                }
+            )*
+        };
+    }
+
+    required_permission!(
+        ReadContent,
+        WriteContent,
+        DeleteContent,
+        ManageMedia,
+        ManageLeads,
+        ManageUsers,
+        ManageAccount,
+        ReadAuditLog,
+        ManageOidcProviders,
+    );
+}
+
+/// The authenticated user, only extracted when its role grants the permission `P`.
+/// The role is read from the database, so a role change applies to tokens already issued.
+#[derive(Debug)]
+pub struct Authorized<P: RequiredPermission>(pub User, PhantomData<fn() -> P>);
+
+impl<P: RequiredPermission> Deref for Authorized<P> {
+    type Target = User;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<P: RequiredPermission> aide::OperationInput for Authorized<P> {}
+
+#[axum::async_trait]
+impl<S, P> FromRequestParts<S> for Authorized<P>
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+    P: RequiredPermission,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = User::from_request_parts(parts, state).await?;
+        if !user.role.has_permission(P::PERMISSION) {
+            return Err(AppError::Forbidden(format!(
+                "The {:?} permission is required",
+                P::PERMISSION
+            )));
+        }
+        Ok(Authorized(user, PhantomData))
+    }
+}
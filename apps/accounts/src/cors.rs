@@ -0,0 +1,159 @@
+use axum::{
+    extract::{FromRequestParts, State},
+    http::{header, HeaderValue, Method, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use shared::AppError;
+use sqlx::PgPool;
+
+use crate::{accounts::get_account_by_id, extractors::AccountID, Account};
+
+static ORIGIN_PATTERN_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(\*|https?://(\*\.)?[a-z0-9\-]+(\.[a-z0-9\-]+)*(:[0-9]{1,5})?)$").unwrap()
+});
+
+const ALLOWED_METHODS: &str = "GET, POST, PUT, PATCH, DELETE";
+const ALLOWED_HEADERS: &str = "content-type, x-api-key";
+const PREFLIGHT_MAX_AGE_SECONDS: &str = "3600";
+
+/// `scheme://host[:port]`, lowercase. The host can start with `*.` to allow its subdomains,
+/// `*` allows any origin.
+pub(crate) fn is_valid_origin_pattern(pattern: &str) -> bool {
+    ORIGIN_PATTERN_REGEX.is_match(pattern)
+}
+
+/// The `scheme://host[:port]` of an url, e.g. `https://consub.io` for `https://consub.io/blog`.
+fn origin_of(url: &str) -> Option<&str> {
+    let (scheme, rest) = url.split_once("://")?;
+    let host = rest.split(['/', '?', '#']).next()?;
+    Some(&url[..scheme.len() + "://".len() + host.len()])
+}
+
+fn origin_matches(pattern: &str, origin: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    match pattern.split_once("://*.") {
+        Some((scheme, domain)) => origin
+            .strip_prefix(scheme)
+            .and_then(|origin| origin.strip_prefix("://"))
+            .and_then(|host| host.strip_suffix(domain))
+            .map_or(false, |subdomain| {
+                subdomain.len() > 1 && subdomain.ends_with('.')
+            }),
+        None => pattern == origin,
+    }
+}
+
+/// Whether a browser on the origin can call the public api of the account:
+/// the account origin or one of its allowed origins.
+fn is_origin_allowed(account: &Account, origin: &str) -> bool {
+    let origin = origin.to_lowercase();
+    account
+        .origin
+        .as_deref()
+        .and_then(origin_of)
+        .map_or(false, |account_origin| {
+            account_origin.eq_ignore_ascii_case(&origin)
+        })
+        || account
+            .allowed_origins
+            .iter()
+            .any(|pattern| origin_matches(pattern, &origin))
+}
+
+/// Requests from the host itself don't need CORS.
+fn is_same_origin<B>(request: &Request<B>, origin: &str) -> bool {
+    let host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    match (origin.split_once("://"), host) {
+        (Some((_, origin_host)), Some(host)) => origin_host.eq_ignore_ascii_case(host),
+        _ => false,
+    }
+}
+
+/// The account of the request, as [`AccountID`] resolves it: the api key, then the host.
+async fn request_account(
+    parts: &mut axum::http::request::Parts, pool: &PgPool,
+) -> Result<Option<Account>, AppError> {
+    match AccountID::from_request_parts(parts, pool).await {
+        Ok(AccountID(account_id)) => Ok(Some(get_account_by_id(pool, account_id).await?)),
+        Err(AppError::InternalServerError(e)) => Err(AppError::InternalServerError(e)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// CORS of the public routes, only the origins of the account of the request are allowed.
+/// Requests from other origins are forbidden before reaching the handler.
+///
+/// Browsers don't send the api key with preflight requests, so they are checked against
+/// the account of the host (custom domain or subdomain) when there's one, otherwise
+/// they're answered and the actual request is checked.
+pub async fn public_cors<B: Send>(
+    State(pool): State<PgPool>, request: Request<B>, next: Next<B>,
+) -> Result<Response, AppError> {
+    let origin = match request
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|origin| origin.to_str().ok())
+    {
+        Some(origin) if !is_same_origin(&request, origin) => origin.to_string(),
+        _ => return Ok(next.run(request).await),
+    };
+    let origin_value = HeaderValue::from_str(&origin)
+        .map_err(|_| AppError::BadRequest("Invalid origin".into()))?;
+    let is_preflight = request.method() == Method::OPTIONS
+        && request
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+
+    let (mut parts, body) = request.into_parts();
+    let account = request_account(&mut parts, &pool).await?;
+    let request = Request::from_parts(parts, body);
+
+    if let Some(account) = &account {
+        if !is_origin_allowed(account, &origin) {
+            return Err(AppError::Forbidden(format!(
+                "The origin {origin} is not allowed"
+            )));
+        }
+    }
+
+    if is_preflight {
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin_value);
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            HeaderValue::from_static(ALLOWED_METHODS),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            HeaderValue::from_static(ALLOWED_HEADERS),
+        );
+        headers.insert(
+            header::ACCESS_CONTROL_MAX_AGE,
+            HeaderValue::from_static(PREFLIGHT_MAX_AGE_SECONDS),
+        );
+        headers.insert(
+            header::VARY,
+            HeaderValue::from_static(
+                "origin, access-control-request-method, access-control-request-headers",
+            ),
+        );
+        return Ok(response);
+    }
+
+    let mut response = next.run(request).await;
+    if account.is_some() {
+        let headers = response.headers_mut();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin_value);
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+    }
+    Ok(response)
+}
@@ -0,0 +1,290 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use addr::parse_domain_name;
+use chrono::NaiveDateTime;
+use once_cell::sync::Lazy;
+use rand::{distributions::Alphanumeric, Rng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use shared::{dns::DnsResolver, AppError};
+use sqlx::PgConnection;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::accounts::get_account_by_subdomain;
+
+/// The TXT record holding the token is looked up at `_consub-verification.<domain>`.
+const VERIFICATION_RECORD_PREFIX: &str = "_consub-verification";
+
+/// How long a host stays resolved to its account (or to none) in memory.
+const HOST_CACHE_DURATION: Duration = Duration::from_secs(60);
+
+/// Past this many hosts, expired entries are dropped, then everything if it's still full.
+const HOST_CACHE_CAPACITY: usize = 10_000;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CustomDomain {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub account_id: Uuid,
+    pub domain: String,
+    /// Where to create the TXT record, its value is the verification token.
+    pub verification_record: String,
+    pub verification_token: String,
+    pub verified_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct CreateCustomDomainInput {
+    #[validate(length(min = 1, max = 253))]
+    pub domain: String,
+}
+
+fn generate_verification_token() -> String {
+    let token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .collect();
+    format!("consub-verification={token}")
+}
+
+/// Lowercases the domain, it must be a registrable domain (e.g. not `com`).
+fn normalize_domain(domain: &str) -> Option<String> {
+    let domain = domain.trim().trim_end_matches('.').to_lowercase();
+    let name = parse_domain_name(&domain).ok()?;
+    (name.has_known_suffix() && name.root().is_some()).then_some(domain)
+}
+
+pub async fn create_custom_domain(
+    conn: &mut PgConnection, account_id: Uuid, input: CreateCustomDomainInput,
+) -> Result<CustomDomain, AppError> {
+    let domain = normalize_domain(&input.domain)
+        .ok_or_else(|| AppError::BadRequest("The domain is not valid".into()))?;
+
+    let custom_domain = sqlx::query_as!(
+        CustomDomain,
+        r#"
+        INSERT INTO accounts.custom_domains (account_id, domain, verification_token)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (account_id, domain) DO NOTHING
+        RETURNING id, account_id, domain, $4 || '.' || domain as "verification_record!",
+            verification_token, verified_at, created_at
+        "#,
+        account_id,
+        domain,
+        generate_verification_token(),
+        VERIFICATION_RECORD_PREFIX,
+    )
+    .fetch_optional(conn)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("The domain was already added".into()))?;
+
+    Ok(custom_domain)
+}
+
+pub async fn list_custom_domains(
+    conn: &mut PgConnection, account_id: Uuid,
+) -> Result<Vec<CustomDomain>, sqlx::Error> {
+    sqlx::query_as!(
+        CustomDomain,
+        r#"
+        SELECT id, account_id, domain, $2 || '.' || domain as "verification_record!",
+            verification_token, verified_at, created_at
+        FROM accounts.custom_domains
+        WHERE account_id = $1
+        ORDER BY domain
+        "#,
+        account_id,
+        VERIFICATION_RECORD_PREFIX,
+    )
+    .fetch_all(conn)
+    .await
+}
+
+pub async fn get_custom_domain(
+    conn: &mut PgConnection, account_id: Uuid, custom_domain_id: Uuid,
+) -> Result<CustomDomain, sqlx::Error> {
+    sqlx::query_as!(
+        CustomDomain,
+        r#"
+        SELECT id, account_id, domain, $3 || '.' || domain as "verification_record!",
+            verification_token, verified_at, created_at
+        FROM accounts.custom_domains
+        WHERE id = $2
+        AND account_id = $1
+        "#,
+        account_id,
+        custom_domain_id,
+        VERIFICATION_RECORD_PREFIX,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn delete_custom_domain(
+    conn: &mut PgConnection, account_id: Uuid, custom_domain_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let domain = sqlx::query_scalar!(
+        r#"
+        DELETE FROM accounts.custom_domains
+        WHERE id = $2
+        AND account_id = $1
+        RETURNING domain
+        "#,
+        account_id,
+        custom_domain_id,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    forget_host(&domain);
+    Ok(())
+}
+
+/// Checks the TXT record of the domain holds its token, then starts serving the
+/// account on it. A domain can only be verified by one account.
+pub async fn verify_custom_domain(
+    conn: &mut PgConnection, dns: &DnsResolver, account_id: Uuid, custom_domain_id: Uuid,
+) -> Result<CustomDomain, AppError> {
+    let custom_domain = get_custom_domain(conn, account_id, custom_domain_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("Custom domain not found".into()),
+            e => e.into(),
+        })?;
+    if custom_domain.verified_at.is_some() {
+        return Ok(custom_domain);
+    }
+
+    let records = dns
+        .txt_records(&custom_domain.verification_record)
+        .await
+        .map_err(|e| {
+            AppError::BadRequest(format!(
+                "Unable to look up the TXT records of {}: {e}",
+                custom_domain.verification_record
+            ))
+        })?;
+    if !records.contains(&custom_domain.verification_token) {
+        return Err(AppError::BadRequest(format!(
+            "The TXT record {} doesn't hold the verification token yet",
+            custom_domain.verification_record
+        )));
+    }
+
+    let verified = sqlx::query_as!(
+        CustomDomain,
+        r#"
+        UPDATE accounts.custom_domains
+        SET verified_at = NOW()
+        WHERE id = $1
+        RETURNING id, account_id, domain, $2 || '.' || domain as "verification_record!",
+            verification_token, verified_at, created_at
+        "#,
+        custom_domain.id,
+        VERIFICATION_RECORD_PREFIX,
+    )
+    .fetch_one(conn)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db)
+            if db.constraint() == Some("custom_domains_verified_domain_idx") =>
+        {
+            AppError::BadRequest("The domain is used by another account".into())
+        }
+        e => e.into(),
+    })?;
+
+    forget_host(&verified.domain);
+    Ok(verified)
+}
+
+async fn get_account_id_by_verified_domain(
+    pool: &sqlx::PgPool, domain: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT account_id
+        FROM accounts.custom_domains
+        WHERE domain = $1
+        AND verified_at IS NOT NULL
+        "#,
+        domain,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+struct CachedHost {
+    account_id: Option<Uuid>,
+    cached_at: Instant,
+}
+
+static HOST_CACHE: Lazy<RwLock<HashMap<String, CachedHost>>> = Lazy::new(Default::default);
+
+fn cached_host(host: &str) -> Option<Option<Uuid>> {
+    let cache = HOST_CACHE.read().unwrap();
+    let cached = cache.get(host)?;
+    (cached.cached_at.elapsed() < HOST_CACHE_DURATION).then_some(cached.account_id)
+}
+
+fn cache_host(host: String, account_id: Option<Uuid>) {
+    let mut cache = HOST_CACHE.write().unwrap();
+    if cache.len() >= HOST_CACHE_CAPACITY {
+        cache.retain(|_, cached| cached.cached_at.elapsed() < HOST_CACHE_DURATION);
+        if cache.len() >= HOST_CACHE_CAPACITY {
+            cache.clear();
+        }
+    }
+    cache.insert(
+        host,
+        CachedHost {
+            account_id,
+            cached_at: Instant::now(),
+        },
+    );
+}
+
+fn forget_host(host: &str) {
+    HOST_CACHE.write().unwrap().remove(host);
+}
+
+/// The account served on a host: a verified custom domain first, then a subdomain
+/// (`<subdomain>.consub.io`). Hosts are cached for a minute, whether they match or not.
+pub async fn get_account_id_by_host(
+    pool: &sqlx::PgPool, host: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let host = host
+        .rsplit_once(':')
+        .map_or(host, |(host, _port)| host)
+        .trim_end_matches('.')
+        .to_lowercase();
+    if let Some(account_id) = cached_host(&host) {
+        return Ok(account_id);
+    }
+
+    let account_id = match get_account_id_by_verified_domain(pool, &host).await? {
+        Some(account_id) => Some(account_id),
+        None => {
+            let prefix = parse_domain_name(&host)
+                .ok()
+                .and_then(|domain| domain.prefix());
+            match prefix {
+                Some(prefix) => match get_account_by_subdomain(pool, prefix.to_string()).await {
+                    Ok(account) => Some(account.id),
+                    Err(sqlx::Error::RowNotFound) => None,
+                    Err(e) => return Err(e),
+                },
+                None => None,
+            }
+        }
+    };
+
+    cache_host(host, account_id);
+    Ok(account_id)
+}
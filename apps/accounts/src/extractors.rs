@@ -1,8 +1,7 @@
-use addr::parse_domain_name;
 use aide::OperationIo;
 use axum::{
     extract::{FromRef, FromRequestParts, Host},
-    http::request::Parts,
+    http::{header, request::Parts},
 };
 
 use axum_auth::AuthBearer;
@@ -12,17 +11,21 @@ use shared::AppError;
 use sqlx::PgPool;
 
 use crate::{
+    api_keys::{get_active_api_key, ApiKey, ApiKeyScope, RequiredScope},
     authentication::{get_claims_from_bearer_token, ConsubClaims},
     users::get_user_by_id,
     Account, User,
 };
 use anyhow::anyhow;
+use std::{marker::PhantomData, ops::Deref};
 use uuid::Uuid;
 
-use crate::accounts::{get_account_by_subdomain, get_account_by_x_api_key};
+use crate::accounts::get_account_by_id;
+use crate::custom_domains::get_account_id_by_host;
 
+/// The raw value of the X-Api-Key header.
 #[derive(Debug, Clone)]
-pub struct APIKey(pub Uuid);
+pub struct APIKey(pub String);
 
 #[derive(Debug, Serialize, OperationIo)]
 pub struct AccountID(pub Uuid);
@@ -41,23 +44,16 @@ where
         // Tries to fetch by X-API-KEY header.
         if let Ok(APIKey(x_api_key)) = APIKey::from_request_parts(parts, state).await {
             let pool = PgPool::from_ref(state);
-            if let Ok(account) = get_account_by_x_api_key(&pool, x_api_key).await {
-                return Ok(AccountID(account.id));
+            if let Ok(api_key) = get_active_api_key(&pool, &x_api_key).await {
+                return Ok(AccountID(api_key.account_id));
             }
         }
 
+        // Tries to fetch by the verified custom domain, then the subdomain.
         let Host(hostname) = Host::from_request_parts(parts, state).await?;
-
-        let domain = parse_domain_name(&hostname)
-            .map_err(|_| AppError::BadRequest("Invalid domain name".into()))?;
-        let prefix = match domain.prefix() {
-            Some(prefix) => prefix,
-            None => return Err(AppError::BadRequest("Invalid domain name".into())),
-        };
-        // Tries to fetch using the subdomain.
         let pool = PgPool::from_ref(state);
-        if let Ok(account) = get_account_by_subdomain(&pool, prefix.to_string()).await {
-            return Ok(AccountID(account.id));
+        if let Some(account_id) = get_account_id_by_host(&pool, &hostname).await? {
+            return Ok(AccountID(account_id));
         }
 
         Err(AppError::BadRequest("AccountID is not valid".into()))
@@ -72,10 +68,29 @@ where
 {
     type Rejection = AppError;
 
+    /// The user of the access token, or the user an api key with the admin scope acts as
+    /// when there's no access token, for automations.
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        let claims = ConsubClaims::from_request_parts(parts, state).await?;
         let pool = PgPool::from_ref(state);
-        let user = get_user_by_id(&pool, claims.user_id).await?;
+        let user_id = match APIKey::from_request_parts(parts, state).await {
+            Ok(APIKey(x_api_key)) if !parts.headers.contains_key(header::AUTHORIZATION) => {
+                let api_key = scoped_api_key(&pool, &x_api_key, ApiKeyScope::Admin).await?;
+                api_key.user_id.ok_or_else(|| {
+                    AppError::Forbidden("The API Key doesn't act as a user".into())
+                })?
+            }
+            _ => {
+                ConsubClaims::from_request_parts(parts, state)
+                    .await?
+                    .user_id
+            }
+        };
+        let user = get_user_by_id(&pool, user_id).await.map_err(|e| match e {
+            sqlx::Error::RowNotFound => {
+                AppError::Unauthorized("The user was removed from the account".into())
+            }
+            e => e.into(),
+        })?;
         Ok(user)
     }
 }
@@ -93,12 +108,12 @@ where
             .headers
             .get(X_API_KEY_HEADER_KEY)
             .and_then(|api_key| api_key.to_str().ok())
-            .and_then(|api_key| Uuid::parse_str(api_key).ok())
+            .filter(|api_key| !api_key.is_empty())
         {
-            return Ok(APIKey(api_key));
+            return Ok(APIKey(api_key.to_string()));
         }
 
-        Err(anyhow!("API Key is not valid").into())
+        Err(AppError::Unauthorized("API Key is missing".into()))
     }
 }
 
@@ -111,15 +126,52 @@ where
     type Rejection = AppError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // Kept in the extensions, so the other extractors of the request don't validate it again.
+        if let Some(claims) = parts.extensions.get::<ConsubClaims>() {
+            return Ok(claims.clone());
+        }
+
         let AuthBearer(token) = AuthBearer::from_request_parts(parts, state)
             .await
             .map_err(|_| anyhow!("Unable to get authorization bearer token"))?;
         let pool = PgPool::from_ref(state);
         let claims = get_claims_from_bearer_token(&pool, token).await?;
+        parts.extensions.insert(claims.clone());
         Ok(claims)
     }
 }
 
+/// Looks up an api key, then checks it has the scope.
+async fn scoped_api_key(pool: &PgPool, key: &str, scope: ApiKeyScope) -> Result<ApiKey, AppError> {
+    let api_key = get_active_api_key(pool, key).await.map_err(|e| match e {
+        sqlx::Error::RowNotFound => AppError::Unauthorized("API Key is not valid".into()),
+        e => e.into(),
+    })?;
+
+    if !api_key.has_scope(scope) {
+        return Err(AppError::Forbidden(format!(
+            "The API Key doesn't have the {} scope",
+            scope.as_str()
+        )));
+    }
+    Ok(api_key)
+}
+
+/// Looks up the api key of the request, then checks it has the scope.
+async fn account_with_scope<S>(
+    parts: &mut Parts, state: &S, scope: ApiKeyScope,
+) -> Result<(Account, ApiKey), AppError>
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+{
+    let APIKey(x_api_key) = APIKey::from_request_parts(parts, state).await?;
+    let pool = PgPool::from_ref(state);
+    let api_key = scoped_api_key(&pool, &x_api_key, scope).await?;
+    let account = get_account_by_id(&pool, api_key.account_id).await?;
+    Ok((account, api_key))
+}
+
 #[axum::async_trait]
 impl<S> FromRequestParts<S> for Account
 where
@@ -128,16 +180,46 @@ where
 {
     type Rejection = AppError;
 
-    /// Extracts the account from the x-api-key.
-    /// The x-api-key takes precedence to fetch the account ID.    
+    /// Extracts the account from the x-api-key, which must have the public:read scope.
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
-        // Tries to fetch by X-API-KEY header.
-        if let Ok(APIKey(x_api_key)) = APIKey::from_request_parts(parts, state).await {
-            let pool = PgPool::from_ref(state);
-            if let Ok(account) = get_account_by_x_api_key(&pool, x_api_key).await {
-                return Ok(account);
-            }
-        }
-        Err(anyhow!("Account is not valid").into())
+        let (account, _) = account_with_scope(parts, state, ApiKeyScope::PublicRead).await?;
+        Ok(account)
+    }
+}
+
+/// The account of an api key which has the scope `S`, e.g. `ScopedAccount<LeadsWrite>`.
+#[derive(Debug)]
+pub struct ScopedAccount<S: RequiredScope> {
+    pub account: Account,
+    pub api_key: ApiKey,
+    scope: PhantomData<fn() -> S>,
+}
+
+impl<S: RequiredScope> Deref for ScopedAccount<S> {
+    type Target = Account;
+
+    fn deref(&self) -> &Self::Target {
+        &self.account
+    }
+}
+
+impl<S: RequiredScope> aide::OperationInput for ScopedAccount<S> {}
+
+#[axum::async_trait]
+impl<S, R> FromRequestParts<S> for ScopedAccount<R>
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+    R: RequiredScope,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let (account, api_key) = account_with_scope(parts, state, R::SCOPE).await?;
+        Ok(ScopedAccount {
+            account,
+            api_key,
+            scope: PhantomData,
+        })
     }
 }
@@ -0,0 +1,95 @@
+use std::{collections::BTreeMap, marker::PhantomData, sync::RwLock};
+
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::request::Parts,
+};
+use axum_auth::AuthBearer;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use shared::AppError;
+use sqlx::PgPool;
+
+use crate::{accounts::get_account_by_id, authentication::ConsubClaims, extractors::AccountID};
+
+/// A feature accounts turn on in their settings. Each app declares its own flags and gates
+/// its routes with [`Enabled`], the api registers them at startup with [`register_feature_flag`].
+pub trait FeatureFlag: Send + Sync + 'static {
+    /// The key in `accounts.feature_flags`.
+    const KEY: &'static str;
+    const DESCRIPTION: &'static str;
+}
+
+/// The flags registered by the apps, only these can be changed in the account settings.
+static FEATURE_FLAGS: Lazy<RwLock<BTreeMap<&'static str, &'static str>>> =
+    Lazy::new(Default::default);
+
+pub fn register_feature_flag<F: FeatureFlag>() {
+    FEATURE_FLAGS
+        .write()
+        .unwrap()
+        .insert(F::KEY, F::DESCRIPTION);
+}
+
+/// The registered flags with their description, by key.
+pub fn registered_feature_flags() -> BTreeMap<&'static str, &'static str> {
+    FEATURE_FLAGS.read().unwrap().clone()
+}
+
+/// The flags of an account, a flag that was never set is off.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct AccountFeatureFlags(pub BTreeMap<String, bool>);
+
+impl AccountFeatureFlags {
+    pub fn is_enabled<F: FeatureFlag>(&self) -> bool {
+        self.is_key_enabled(F::KEY)
+    }
+
+    pub fn is_key_enabled(&self, key: &str) -> bool {
+        self.0.get(key).copied().unwrap_or_default()
+    }
+}
+
+/// Only extracted when the account of the request has the flag `F` on,
+/// e.g. `_feature: Enabled<Clippings>`. The account comes from the access token,
+/// or on public routes like [`AccountID`], from the api key, custom domain or subdomain.
+/// Disabled features are forbidden in the admin and don't exist on the public api.
+#[derive(Debug)]
+pub struct Enabled<F: FeatureFlag>(PhantomData<fn() -> F>);
+
+impl<F: FeatureFlag> aide::OperationInput for Enabled<F> {}
+
+#[axum::async_trait]
+impl<S, F> FromRequestParts<S> for Enabled<F>
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+    F: FeatureFlag,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let pool = PgPool::from_ref(state);
+
+        if AuthBearer::from_request_parts(parts, state).await.is_ok() {
+            let claims = ConsubClaims::from_request_parts(parts, state).await?;
+            let account = get_account_by_id(&pool, claims.account_id).await?;
+            if !account.feature_flags.is_enabled::<F>() {
+                return Err(AppError::Forbidden(format!(
+                    "The {} feature is not enabled for the account",
+                    F::KEY
+                )));
+            }
+            return Ok(Enabled(PhantomData));
+        }
+
+        let AccountID(account_id) = AccountID::from_request_parts(parts, state).await?;
+        let account = get_account_by_id(&pool, account_id).await?;
+        if !account.feature_flags.is_enabled::<F>() {
+            return Err(AppError::NotFound("Not found".into()));
+        }
+        Ok(Enabled(PhantomData))
+    }
+}
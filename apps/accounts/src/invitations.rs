@@ -0,0 +1,259 @@
+use chrono::NaiveDateTime;
+use rand::{distributions::Alphanumeric, Rng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use shared::{
+    mail::{Mail, Mailer},
+    AppError,
+};
+use sqlx::PgConnection;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::accounts::get_account_by_id;
+use crate::authorization::Role;
+use crate::passwords::update_password;
+use crate::users::{create_member, User};
+
+/// How long an invitation can be accepted.
+pub const INVITATION_DURATION_DAYS: i32 = 7;
+
+/// Invitation to join an account with a role, accepted by choosing a password.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Invitation {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub account_id: Uuid,
+    pub email: String,
+    pub role: Role,
+    pub invited_by_id: Uuid,
+    pub expires_at: NaiveDateTime,
+    pub accepted_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct CreateInvitationInput {
+    #[validate(email)]
+    pub email: String,
+    pub role: Role,
+}
+
+/// Invitation tokens are random strings sent by email, only their hash is stored.
+fn generate_invitation_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .collect()
+}
+
+fn hash_invitation_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// Creates an invitation and returns it with its token,
+/// the pending invitations for the same email are revoked.
+pub async fn create_invitation(
+    conn: &mut PgConnection, account_id: Uuid, invited_by_id: Uuid, input: CreateInvitationInput,
+) -> Result<(Invitation, String), sqlx::Error> {
+    let email = input.email.to_lowercase();
+    sqlx::query!(
+        r#"
+        UPDATE accounts.invitations
+        SET revoked_at = NOW()
+        WHERE account_id = $1
+        AND email = $2
+        AND accepted_at IS NULL
+        AND revoked_at IS NULL
+        "#,
+        account_id,
+        email,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let token = generate_invitation_token();
+    let invitation = sqlx::query_as!(
+        Invitation,
+        r#"
+        INSERT INTO accounts.invitations (account_id, email, role, token_hash, invited_by_id, expires_at)
+        VALUES ($1, $2, $3, $4, $5, NOW() + make_interval(days => $6))
+        RETURNING id, account_id, email, role as "role: Role", invited_by_id,
+            expires_at, accepted_at, revoked_at, created_at
+        "#,
+        account_id,
+        email,
+        input.role as Role,
+        hash_invitation_token(&token),
+        invited_by_id,
+        INVITATION_DURATION_DAYS,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok((invitation, token))
+}
+
+/// Lists the invitations which can still be accepted.
+pub async fn list_pending_invitations(
+    conn: &mut PgConnection, account_id: Uuid,
+) -> Result<Vec<Invitation>, sqlx::Error> {
+    sqlx::query_as!(
+        Invitation,
+        r#"
+        SELECT id, account_id, email, role as "role: Role", invited_by_id,
+            expires_at, accepted_at, revoked_at, created_at
+        FROM accounts.invitations
+        WHERE account_id = $1
+        AND accepted_at IS NULL
+        AND revoked_at IS NULL
+        AND expires_at > NOW()
+        ORDER BY created_at DESC
+        "#,
+        account_id,
+    )
+    .fetch_all(conn)
+    .await
+}
+
+pub async fn revoke_invitation(
+    conn: &mut PgConnection, account_id: Uuid, invitation_id: Uuid,
+) -> Result<Invitation, sqlx::Error> {
+    sqlx::query_as!(
+        Invitation,
+        r#"
+        UPDATE accounts.invitations
+        SET revoked_at = NOW()
+        WHERE id = $2
+        AND account_id = $1
+        AND accepted_at IS NULL
+        AND revoked_at IS NULL
+        RETURNING id, account_id, email, role as "role: Role", invited_by_id,
+            expires_at, accepted_at, revoked_at, created_at
+        "#,
+        account_id,
+        invitation_id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Marks an invitation as accepted.
+/// Returns None when the token is unknown, expired, revoked or was already used.
+pub async fn accept_invitation(
+    conn: &mut PgConnection, token: &str,
+) -> Result<Option<Invitation>, sqlx::Error> {
+    sqlx::query_as!(
+        Invitation,
+        r#"
+        UPDATE accounts.invitations
+        SET accepted_at = NOW()
+        WHERE token_hash = $1
+        AND accepted_at IS NULL
+        AND revoked_at IS NULL
+        AND expires_at > NOW()
+        RETURNING id, account_id, email, role as "role: Role", invited_by_id,
+            expires_at, accepted_at, revoked_at, created_at
+        "#,
+        hash_invitation_token(token),
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+pub async fn is_member(
+    conn: &mut PgConnection, account_id: Uuid, email: &str,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM accounts.users
+            WHERE account_id = $1
+            AND email = $2
+            AND removed_at IS NULL
+        ) as "exists!"
+        "#,
+        account_id,
+        email.to_lowercase(),
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Invites someone to the account of the inviter, within the transaction of the caller.
+/// Only owners can invite other owners. Returns the token, emailed with [`send_invitation`]
+/// once the invitation is committed.
+pub async fn invite_user(
+    conn: &mut PgConnection, inviter: &User, input: CreateInvitationInput,
+) -> Result<(Invitation, String), AppError> {
+    if input.role == Role::Owner && inviter.role != Role::Owner {
+        return Err(AppError::Forbidden(
+            "Only owners can invite other owners".into(),
+        ));
+    }
+
+    if is_member(&mut *conn, inviter.account_id, &input.email).await? {
+        return Err(AppError::BadRequest(
+            "The email already belongs to a user of the account".into(),
+        ));
+    }
+    Ok(create_invitation(conn, inviter.account_id, inviter.id, input).await?)
+}
+
+/// Emails the token of an invitation to the invitee. When it fails, the invitation
+/// can be revoked and sent again.
+pub async fn send_invitation(
+    pool: &sqlx::PgPool, mailer: &Mailer, inviter: &User, invitation: &Invitation, token: &str,
+) -> Result<(), AppError> {
+    let account = get_account_by_id(pool, inviter.account_id).await?;
+
+    let instructions = match account.origin {
+        Some(origin) => format!(
+            "Follow this link to join: {}/accept-invitation?token={token}",
+            origin.trim_end_matches('/')
+        ),
+        None => format!("Use this token to join: {token}"),
+    };
+
+    mailer
+        .send(Mail {
+            to: invitation.email.clone(),
+            subject: format!("You've been invited to {}", account.name),
+            body: format!(
+                "{} invited you to {} on Consub.\n\n{instructions}\n\n\
+                The invitation expires in {INVITATION_DURATION_DAYS} days.",
+                inviter.email, account.name
+            ),
+        })
+        .await?;
+    Ok(())
+}
+
+/// Accepts an invitation, the invitee becomes a user of the account with the given password.
+pub async fn accept_invitation_with_password(
+    pool: &sqlx::PgPool, token: String, password: String,
+) -> Result<User, AppError> {
+    let mut tx = pool.begin().await?;
+    let invitation = accept_invitation(&mut tx, &token)
+        .await?
+        .ok_or(AppError::BadRequest(
+            "The invitation is not valid or has expired".into(),
+        ))?;
+
+    let user = create_member(
+        &mut tx,
+        invitation.account_id,
+        &invitation.email,
+        invitation.role,
+    )
+    .await?
+    .ok_or(AppError::BadRequest(
+        "The email already belongs to a user of the account".into(),
+    ))?;
+
+    update_password(&mut tx, user.id, password).await?;
+    tx.commit().await?;
+    Ok(user)
+}
@@ -1,15 +1,34 @@
 mod accounts;
+mod api_keys;
 mod authentication;
+mod authorization;
+mod cors;
+mod custom_domains;
 mod extractors;
+mod feature_flags;
+mod invitations;
+mod login_attempts;
+mod oidc;
 mod passwords;
+mod profiles;
+mod rate_limits;
 mod router;
+mod sessions;
+mod settings;
+mod two_factor;
 mod users;
 
 pub use crate::accounts::Account;
+pub use crate::api_keys::{scopes, ApiKey, ApiKeyScope, RequiredScope};
+pub use crate::cors::public_cors;
+pub use crate::feature_flags::{register_feature_flag, AccountFeatureFlags, Enabled, FeatureFlag};
+pub use crate::passwords::configure_argon2;
+pub use crate::profiles::{list_public_authors, PublicAuthor, SocialLink};
+pub use crate::rate_limits::{rate_limit, RateLimitGroup};
 pub use crate::users::User;
+pub use authorization::{permissions, Authorized, Permission, RequiredPermission, Role};
 pub use router::routes;
 
-pub use extractors::{APIKey, AccountID};
+pub use extractors::{APIKey, AccountID, ScopedAccount};
 
 pub use authentication::authenticate_user_with_password;
-pub use authentication::authorization_layer;
@@ -0,0 +1,241 @@
+use chrono::{Duration, NaiveDateTime};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use shared::{client::ClientInfo, AppError};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+/// Failures older than this are forgotten.
+const FAILURE_WINDOW_MINUTES: i32 = 60;
+
+/// How many failures are allowed before attempts are delayed and then locked.
+struct Backoff {
+    free_failures: i64,
+    lockout_failures: i64,
+}
+
+/// Each email gets a few tries, the delay then doubles from 30 seconds until it's locked.
+const EMAIL_BACKOFF: Backoff = Backoff {
+    free_failures: 5,
+    lockout_failures: 10,
+};
+
+/// An ip address may be shared by many people (offices, NATs), so it gets more tries.
+const IP_BACKOFF: Backoff = Backoff {
+    free_failures: 50,
+    lockout_failures: 100,
+};
+
+const BACKOFF_BASE_SECONDS: i64 = 30;
+const LOCKOUT_MINUTES: i64 = 15;
+
+impl Backoff {
+    /// How long to wait after the last failure before trying again.
+    fn delay(&self, failures: i64) -> Option<Duration> {
+        if failures < self.free_failures {
+            None
+        } else if failures >= self.lockout_failures {
+            Some(Duration::minutes(LOCKOUT_MINUTES))
+        } else {
+            let exponent = (failures - self.free_failures) as u32;
+            Some(Duration::seconds(
+                BACKOFF_BASE_SECONDS * 2_i64.pow(exponent),
+            ))
+        }
+    }
+
+    /// How long until the next attempt is allowed, None when it's allowed already.
+    fn retry_after(&self, failures: &RecentFailures) -> Option<Duration> {
+        let delay = self.delay(failures.failures)?;
+        let elapsed = Duration::milliseconds((failures.since_last_failure? * 1000.0) as i64);
+        (delay > elapsed).then_some(delay - elapsed)
+    }
+}
+
+/// A password login attempt, failed ones are listed to the account owners.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LoginAttempt {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub account_id: Uuid,
+    pub email: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub succeeded: bool,
+    pub created_at: NaiveDateTime,
+}
+
+struct RecentFailures {
+    failures: i64,
+    /// Seconds since the last failure by the clock of the database, which wrote it.
+    since_last_failure: Option<f64>,
+}
+
+pub async fn record_login_attempt(
+    conn: &mut PgConnection, account_id: Uuid, email: &str, client: &ClientInfo, succeeded: bool,
+) -> Result<Uuid, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO accounts.login_attempts (account_id, email, ip_address, user_agent, succeeded)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id
+        "#,
+        account_id,
+        email,
+        client.ip_address,
+        client.user_agent,
+        succeeded,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Forgets an attempt recorded as failed before its password was checked, once it's found right.
+pub async fn forget_login_attempt(conn: &mut PgConnection, id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM accounts.login_attempts WHERE id = $1", id)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Failures of an email since its last successful login.
+async fn recent_email_failures(
+    conn: &mut PgConnection, account_id: Uuid, email: &str,
+) -> Result<RecentFailures, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) as "failures!",
+            EXTRACT(EPOCH FROM LOCALTIMESTAMP - MAX(created_at))::float8 as since_last_failure
+        FROM accounts.login_attempts
+        WHERE account_id = $1
+        AND email = $2
+        AND NOT succeeded
+        AND created_at > NOW() - make_interval(mins => $3)
+        AND created_at > COALESCE((
+            SELECT MAX(created_at) FROM accounts.login_attempts
+            WHERE account_id = $1 AND email = $2 AND succeeded
+        ), '-infinity')
+        "#,
+        account_id,
+        email,
+        FAILURE_WINDOW_MINUTES,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(RecentFailures {
+        failures: row.failures,
+        since_last_failure: row.since_last_failure,
+    })
+}
+
+/// Failures of an ip address, whatever the account or email.
+async fn recent_ip_failures(
+    conn: &mut PgConnection, ip_address: &str,
+) -> Result<RecentFailures, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) as "failures!",
+            EXTRACT(EPOCH FROM LOCALTIMESTAMP - MAX(created_at))::float8 as since_last_failure
+        FROM accounts.login_attempts
+        WHERE ip_address = $1
+        AND NOT succeeded
+        AND created_at > NOW() - make_interval(mins => $2)
+        "#,
+        ip_address,
+        FAILURE_WINDOW_MINUTES,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(RecentFailures {
+        failures: row.failures,
+        since_last_failure: row.since_last_failure,
+    })
+}
+
+/// Takes the locks of the email and of the ip address until the transaction ends, false when
+/// another attempt holds one. Otherwise concurrent attempts would all pass [`login_retry_after`]
+/// before any failure is recorded. They aren't waited for, it would hold a connection each.
+pub async fn lock_login_attempts(
+    conn: &mut PgConnection, account_id: Uuid, email: &str, client: &ClientInfo,
+) -> Result<bool, sqlx::Error> {
+    let mut keys = vec![format!("login_attempts:{account_id}:{email}")];
+    if let Some(ip_address) = &client.ip_address {
+        keys.push(format!("login_attempts:{ip_address}"));
+    }
+
+    for key in keys {
+        let locked = sqlx::query_scalar!(
+            r#"SELECT pg_try_advisory_xact_lock(hashtextextended($1, 0)) as "locked!""#,
+            key,
+        )
+        .fetch_one(&mut *conn)
+        .await?;
+        if !locked {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// How long until attempts for the email or the ip address can be made again,
+/// None when they're not being throttled.
+pub async fn login_retry_after(
+    conn: &mut PgConnection, account_id: Uuid, email: &str, client: &ClientInfo,
+) -> Result<Option<Duration>, sqlx::Error> {
+    let email_retry_after =
+        EMAIL_BACKOFF.retry_after(&recent_email_failures(conn, account_id, email).await?);
+
+    let ip_retry_after = match &client.ip_address {
+        Some(ip_address) => IP_BACKOFF.retry_after(&recent_ip_failures(conn, ip_address).await?),
+        None => None,
+    };
+
+    Ok(email_retry_after.max(ip_retry_after))
+}
+
+/// Takes the locks of the attempt, then refuses it while the email or the ip address are
+/// throttled. The locks are kept until the transaction ends, record the failure within it.
+pub async fn throttle_login_attempt(
+    conn: &mut PgConnection, account_id: Uuid, email: &str, client: &ClientInfo,
+) -> Result<(), AppError> {
+    let retry_after = if lock_login_attempts(conn, account_id, email, client).await? {
+        login_retry_after(conn, account_id, email, client).await?
+    } else {
+        // Another attempt of the email or the ip address is being checked.
+        Some(Duration::zero())
+    };
+
+    match retry_after {
+        Some(retry_after) => {
+            let retry_after_seconds = retry_after.num_seconds() as u64 + 1;
+            Err(AppError::TooManyRequests {
+                message: format!("Too many attempts, try again in {retry_after_seconds} seconds"),
+                retry_after_seconds,
+                rate_limit: None,
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+/// Lists the latest failed attempts of the account.
+pub async fn list_failed_login_attempts(
+    conn: &mut PgConnection, account_id: Uuid,
+) -> Result<Vec<LoginAttempt>, sqlx::Error> {
+    sqlx::query_as!(
+        LoginAttempt,
+        r#"
+        SELECT id, account_id, email, ip_address, user_agent, succeeded, created_at
+        FROM accounts.login_attempts
+        WHERE account_id = $1
+        AND NOT succeeded
+        ORDER BY created_at DESC
+        LIMIT 100
+        "#,
+        account_id,
+    )
+    .fetch_all(conn)
+    .await
+}
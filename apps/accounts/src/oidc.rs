@@ -0,0 +1,513 @@
+use chrono::NaiveDateTime;
+use data_encoding::BASE64URL_NOPAD;
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use once_cell::sync::Lazy;
+use rand::{distributions::Alphanumeric, Rng};
+use regex::Regex;
+use reqwest::Url;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use shared::{client::ClientInfo, AppError};
+use sqlx::PgConnection;
+use std::time::Duration;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::authentication::{start_login, PasswordLogin};
+use crate::users::{get_user_by_email, get_user_by_id};
+
+/// How long the user has to come back from the provider.
+const OIDC_STATE_DURATION_MINUTES: i32 = 10;
+
+/// How long a request to a provider may take, a login waits for it.
+const OIDC_REQUEST_TIMEOUT_SECONDS: u64 = 10;
+
+static SLUG_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[0-9a-z\-]+$").unwrap());
+
+/// Shared by the requests to the providers, so their connections are reused.
+static HTTP_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(OIDC_REQUEST_TIMEOUT_SECONDS))
+        .build()
+        .expect("Failed to build the OpenID Connect http client")
+});
+
+/// An OpenID Connect provider configured for an account.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct OidcProvider {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub account_id: Uuid,
+    pub name: String,
+    pub slug: String,
+    pub issuer: String,
+    pub client_id: String,
+    #[serde(skip)]
+    pub client_secret: Option<String>,
+    /// Where the provider sends the user back, the page calling the callback endpoint.
+    pub redirect_uri: String,
+    pub scopes: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct CreateOidcProviderInput {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[validate(length(min = 1, max = 100), regex = "SLUG_REGEX")]
+    pub slug: String,
+    #[validate(url)]
+    pub issuer: String,
+    #[validate(length(min = 1))]
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    #[validate(url)]
+    pub redirect_uri: String,
+    pub scopes: Option<String>,
+}
+
+/// The part of the provider metadata (`/.well-known/openid-configuration`) we use.
+#[derive(Debug, Deserialize)]
+pub struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    /// Where the keys signing the id tokens are published.
+    jwks_uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct OidcAuthorization {
+    /// Where to send the user to sign in with the provider.
+    pub authorization_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// The claims of a verified id token, the issuer, audience and expiration are checked with the signature.
+#[derive(Debug, Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+    email: Option<String>,
+    #[serde(default)]
+    email_verified: bool,
+}
+
+fn random_string(len: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .collect()
+}
+
+fn hash_state(state: &str) -> String {
+    format!("{:x}", Sha256::digest(state.as_bytes()))
+}
+
+/// The S256 PKCE challenge of a code verifier.
+fn code_challenge(code_verifier: &str) -> String {
+    BASE64URL_NOPAD.encode(&Sha256::digest(code_verifier.as_bytes()))
+}
+
+fn encode_query_value(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+/// OpenID Connect requires TLS, plain http is only accepted for a provider on the same machine.
+fn is_secure_url(url: &str) -> bool {
+    match Url::parse(url) {
+        Ok(url) if url.scheme() == "https" => true,
+        Ok(url) if url.scheme() == "http" => {
+            matches!(url.host_str(), Some("localhost" | "127.0.0.1" | "[::1]"))
+        }
+        _ => false,
+    }
+}
+
+async fn discover_provider(issuer: &str) -> Result<ProviderMetadata, anyhow::Error> {
+    if !is_secure_url(issuer) {
+        anyhow::bail!("The issuer must be an https url");
+    }
+    let url = format!(
+        "{}/.well-known/openid-configuration",
+        issuer.trim_end_matches('/')
+    );
+    let metadata: ProviderMetadata = HTTP_CLIENT
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if metadata.issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+        anyhow::bail!("The provider metadata is for another issuer");
+    }
+    let endpoints = [
+        &metadata.authorization_endpoint,
+        &metadata.token_endpoint,
+        &metadata.jwks_uri,
+    ];
+    if !endpoints
+        .into_iter()
+        .all(|endpoint| is_secure_url(endpoint))
+    {
+        anyhow::bail!("The provider endpoints must be https urls");
+    }
+    Ok(metadata)
+}
+
+/// Checks the signature of an id token with the keys the provider publishes, then its issuer,
+/// audience and expiration. The keys are fetched on each login, providers rotate them.
+async fn verify_id_token(
+    provider: &OidcProvider, id_token: &str,
+) -> Result<IdTokenClaims, anyhow::Error> {
+    let header = decode_header(id_token)?;
+    // The keys are public, a token signed with a shared secret could be signed by anyone.
+    if matches!(
+        header.alg,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        anyhow::bail!("The id token isn't signed with a key of the provider");
+    }
+
+    let metadata = discover_provider(&provider.issuer).await?;
+    let keys: JwkSet = HTTP_CLIENT
+        .get(&metadata.jwks_uri)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let key = match &header.kid {
+        Some(kid) => keys.find(kid),
+        None if keys.keys.len() == 1 => keys.keys.first(),
+        None => None,
+    }
+    .filter(|key| key.common.algorithm.map_or(true, |alg| alg == header.alg))
+    .ok_or_else(|| anyhow::anyhow!("The key of the id token isn't published by the provider"))?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+    validation.set_issuer(&[&provider.issuer]);
+    validation.set_audience(&[&provider.client_id]);
+
+    let token = decode::<IdTokenClaims>(id_token, &DecodingKey::from_jwk(key)?, &validation)?;
+    Ok(token.claims)
+}
+
+/// Discovers the endpoints of a provider before it's created, without holding a connection.
+pub async fn discover_oidc_provider(issuer: &str) -> Result<ProviderMetadata, AppError> {
+    discover_provider(issuer).await.map_err(|e| {
+        AppError::BadRequest(format!(
+            "Unable to discover the provider configuration: {e}"
+        ))
+    })
+}
+
+pub async fn create_oidc_provider(
+    conn: &mut PgConnection, account_id: Uuid, input: CreateOidcProviderInput,
+    metadata: ProviderMetadata,
+) -> Result<OidcProvider, sqlx::Error> {
+    sqlx::query_as!(
+        OidcProvider,
+        r#"
+        INSERT INTO accounts.oidc_providers (
+            account_id, name, slug, issuer, client_id, client_secret, redirect_uri, scopes,
+            authorization_endpoint, token_endpoint
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, COALESCE($8, 'openid email profile'), $9, $10)
+        RETURNING id, account_id, name, slug, issuer, client_id, client_secret, redirect_uri,
+            scopes, authorization_endpoint, token_endpoint, created_at
+        "#,
+        account_id,
+        input.name,
+        input.slug,
+        metadata.issuer,
+        input.client_id,
+        input.client_secret,
+        input.redirect_uri,
+        input.scopes,
+        metadata.authorization_endpoint,
+        metadata.token_endpoint,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn list_oidc_providers(
+    conn: &mut PgConnection, account_id: Uuid,
+) -> Result<Vec<OidcProvider>, sqlx::Error> {
+    sqlx::query_as!(
+        OidcProvider,
+        r#"
+        SELECT id, account_id, name, slug, issuer, client_id, client_secret, redirect_uri,
+            scopes, authorization_endpoint, token_endpoint, created_at
+        FROM accounts.oidc_providers
+        WHERE account_id = $1
+        ORDER BY name
+        "#,
+        account_id,
+    )
+    .fetch_all(conn)
+    .await
+}
+
+pub async fn get_oidc_provider_by_slug(
+    conn: &mut PgConnection, account_id: Uuid, slug: &str,
+) -> Result<OidcProvider, sqlx::Error> {
+    sqlx::query_as!(
+        OidcProvider,
+        r#"
+        SELECT id, account_id, name, slug, issuer, client_id, client_secret, redirect_uri,
+            scopes, authorization_endpoint, token_endpoint, created_at
+        FROM accounts.oidc_providers
+        WHERE account_id = $1
+        AND slug = $2
+        "#,
+        account_id,
+        slug,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Deletes a provider along with the identities linked to it.
+pub async fn delete_oidc_provider(
+    conn: &mut PgConnection, account_id: Uuid, provider_id: Uuid,
+) -> Result<OidcProvider, sqlx::Error> {
+    sqlx::query_as!(
+        OidcProvider,
+        r#"
+        DELETE FROM accounts.oidc_providers
+        WHERE id = $2
+        AND account_id = $1
+        RETURNING id, account_id, name, slug, issuer, client_id, client_secret, redirect_uri,
+            scopes, authorization_endpoint, token_endpoint, created_at
+        "#,
+        account_id,
+        provider_id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Starts a login with the provider: the state, PKCE verifier and nonce are kept
+/// until the user comes back with a code.
+pub async fn start_oidc_login(
+    conn: &mut PgConnection, provider: &OidcProvider,
+) -> Result<OidcAuthorization, sqlx::Error> {
+    let state = random_string(43);
+    let code_verifier = random_string(64);
+    let nonce = random_string(32);
+
+    sqlx::query!(
+        r#"
+        INSERT INTO accounts.oidc_states (provider_id, state_hash, code_verifier, nonce, expires_at)
+        VALUES ($1, $2, $3, $4, NOW() + make_interval(mins => $5))
+        "#,
+        provider.id,
+        hash_state(&state),
+        code_verifier,
+        nonce,
+        OIDC_STATE_DURATION_MINUTES,
+    )
+    .execute(conn)
+    .await?;
+
+    let separator = if provider.authorization_endpoint.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+    let authorization_url = format!(
+        "{}{separator}response_type=code&client_id={}&redirect_uri={}&scope={}&state={state}&nonce={nonce}&code_challenge={}&code_challenge_method=S256",
+        provider.authorization_endpoint,
+        encode_query_value(&provider.client_id),
+        encode_query_value(&provider.redirect_uri),
+        encode_query_value(&provider.scopes),
+        code_challenge(&code_verifier),
+    );
+
+    Ok(OidcAuthorization { authorization_url })
+}
+
+struct OidcState {
+    provider_id: Uuid,
+    code_verifier: String,
+    nonce: String,
+}
+
+/// Marks a state as used, returning it.
+/// Returns None when the state is unknown, expired or was already used.
+async fn use_oidc_state(
+    conn: &mut PgConnection, state: &str,
+) -> Result<Option<OidcState>, sqlx::Error> {
+    sqlx::query_as!(
+        OidcState,
+        r#"
+        UPDATE accounts.oidc_states
+        SET used_at = NOW()
+        WHERE state_hash = $1
+        AND used_at IS NULL
+        AND expires_at > NOW()
+        RETURNING provider_id, code_verifier, nonce
+        "#,
+        hash_state(state),
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+async fn get_oidc_provider(
+    conn: &mut PgConnection, provider_id: Uuid,
+) -> Result<OidcProvider, sqlx::Error> {
+    sqlx::query_as!(
+        OidcProvider,
+        r#"
+        SELECT id, account_id, name, slug, issuer, client_id, client_secret, redirect_uri,
+            scopes, authorization_endpoint, token_endpoint, created_at
+        FROM accounts.oidc_providers
+        WHERE id = $1
+        "#,
+        provider_id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Finds the user of an identity and records the login.
+async fn touch_identity(
+    conn: &mut PgConnection, provider_id: Uuid, subject: &str, email: Option<&str>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        UPDATE accounts.identities
+        SET last_login_at = NOW(), email = COALESCE($3, email)
+        WHERE provider_id = $1
+        AND subject = $2
+        RETURNING user_id
+        "#,
+        provider_id,
+        subject,
+        email,
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+async fn link_identity(
+    conn: &mut PgConnection, user_id: Uuid, provider_id: Uuid, subject: &str, email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO accounts.identities (user_id, provider_id, subject, email)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        user_id,
+        provider_id,
+        subject,
+        email,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+fn invalid_login(message: &str) -> AppError {
+    AppError::Unauthorized(format!("Unable to sign in with the provider: {message}"))
+}
+
+/// Completes a login with the code the provider sent back. The identity is linked to the
+/// user of the account with the same email the first time, when the provider verified it.
+/// Users aren't created here, they join an account through invitations.
+pub async fn complete_oidc_login(
+    pool: &sqlx::PgPool, state: String, code: String, client: ClientInfo,
+) -> Result<PasswordLogin, AppError> {
+    let mut conn = pool.acquire().await?;
+    let oidc_state = use_oidc_state(&mut conn, &state)
+        .await?
+        .ok_or_else(|| invalid_login("the state is not valid or has expired"))?;
+    let provider = get_oidc_provider(&mut conn, oidc_state.provider_id).await?;
+    // Not held while the provider is called.
+    drop(conn);
+
+    let mut form = vec![
+        ("grant_type", "authorization_code".to_string()),
+        ("code", code),
+        ("redirect_uri", provider.redirect_uri.clone()),
+        ("client_id", provider.client_id.clone()),
+        ("code_verifier", oidc_state.code_verifier),
+    ];
+    if let Some(client_secret) = &provider.client_secret {
+        form.push(("client_secret", client_secret.clone()));
+    }
+
+    let response = HTTP_CLIENT
+        .post(&provider.token_endpoint)
+        .form(&form)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(invalid_login("the code was refused"));
+    }
+    let tokens: TokenResponse = response.json().await?;
+
+    let claims = verify_id_token(&provider, &tokens.id_token)
+        .await
+        .map_err(|_| invalid_login("invalid id token"))?;
+    if claims.nonce.as_deref() != Some(oidc_state.nonce.as_str()) {
+        return Err(invalid_login("invalid id token"));
+    }
+
+    let email = claims.email.map(|email| email.to_lowercase());
+    let mut conn = pool.acquire().await?;
+    let user_id =
+        match touch_identity(&mut conn, provider.id, &claims.sub, email.as_deref()).await? {
+            Some(user_id) => user_id,
+            None => {
+                let email = match email {
+                    Some(email) if claims.email_verified => email,
+                    _ => return Err(invalid_login("the provider didn't verify the email")),
+                };
+                let user = get_user_by_email(pool, provider.account_id, &email)
+                    .await
+                    .map_err(|e| match e {
+                        sqlx::Error::RowNotFound => invalid_login("no user has this email"),
+                        e => e.into(),
+                    })?;
+                link_identity(&mut conn, user.id, provider.id, &claims.sub, &email).await?;
+                user.id
+            }
+        };
+    drop(conn);
+
+    let user = get_user_by_id(pool, user_id).await.map_err(|e| match e {
+        sqlx::Error::RowNotFound => invalid_login("the user was removed from the account"),
+        e => e.into(),
+    })?;
+
+    start_login(
+        pool,
+        user.account_id,
+        user.id,
+        &user.email,
+        user.role,
+        client,
+    )
+    .await
+}
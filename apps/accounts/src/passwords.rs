@@ -1,35 +1,228 @@
 use anyhow::anyhow;
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
-    Argon2, PasswordHash, PasswordVerifier,
+    Algorithm, Argon2, Params, PasswordHash, PasswordVerifier, Version,
 };
+use once_cell::sync::{Lazy, OnceCell};
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+use sqlx::PgConnection;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
 
-/// TODO: Make this run in a tokio thread/async
-pub fn hash_password(raw: String) -> Result<String, anyhow::Error> {
-    let salt = SaltString::generate(&mut OsRng);
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
 
-    // TODO: Return here to choose parameters
-    let argon2 = Argon2::default();
+fn env_parse<T: std::str::FromStr>(name: &str, default: T) -> Result<T, anyhow::Error> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| anyhow!("Invalid {name}: {value}")),
+        Err(_) => Ok(default),
+    }
+}
 
-    let password_hash = argon2
-        .hash_password(raw.as_bytes(), &salt)
-        .map_err(|_| anyhow!("Unable to hash password"))?
-        .to_string();
+/// Argon2id parameters, set per deployment with `ARGON2_MEMORY_KIB`, `ARGON2_ITERATIONS`
+/// and `ARGON2_PARALLELISM` through [`configure_argon2`]. Defaults to the argon2 crate defaults.
+static ARGON2_PARAMS: OnceCell<Params> = OnceCell::new();
 
-    Ok(password_hash)
+/// Verified when the email is unknown, hashed with the configured parameters so it takes
+/// as long as a wrong password.
+static DUMMY_PASSWORD_HASH: tokio::sync::OnceCell<String> = tokio::sync::OnceCell::const_new();
+
+/// Reads the argon2 parameters, call it at startup so invalid ones stop the server
+/// instead of failing the first login.
+pub async fn configure_argon2() -> Result<(), anyhow::Error> {
+    let params = Params::new(
+        env_parse("ARGON2_MEMORY_KIB", Params::DEFAULT_M_COST)?,
+        env_parse("ARGON2_ITERATIONS", Params::DEFAULT_T_COST)?,
+        env_parse("ARGON2_PARALLELISM", Params::DEFAULT_P_COST)?,
+        None,
+    )
+    .map_err(|e| anyhow!("Invalid argon2 parameters: {e}"))?;
+    ARGON2_PARAMS
+        .set(params)
+        .map_err(|_| anyhow!("Argon2 parameters are already set"))?;
+    dummy_password_hash().await?;
+    Ok(())
 }
 
-/// TODO: Make this run in a tokio thread/async
-pub fn verify_password(raw: String, hash: String) -> Result<(), anyhow::Error> {
-    let argon2 = Argon2::default();
+fn argon2_params() -> &'static Params {
+    ARGON2_PARAMS.get_or_init(Params::default)
+}
 
-    let password_hash = PasswordHash::new(&hash).map_err(|_| anyhow!("Unable to hash password"))?;
+async fn dummy_password_hash() -> Result<&'static String, anyhow::Error> {
+    DUMMY_PASSWORD_HASH
+        .get_or_try_init(|| hash_password(generate_reset_token()))
+        .await
+}
 
-    match argon2
-        .verify_password(raw.as_bytes(), &password_hash)
-        .map_err(|_| anyhow!("Verify password failed"))
-    {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e),
-    }
+/// Bounds how many hashes run at once (`ARGON2_MAX_CONCURRENCY`), each one holds
+/// a blocking thread and `ARGON2_MEMORY_KIB` of memory.
+static HASHING_PERMITS: Lazy<Semaphore> =
+    Lazy::new(|| Semaphore::new(env_or("ARGON2_MAX_CONCURRENCY", 4)));
+
+fn argon2() -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params().clone())
+}
+
+/// Runs the hashing work on the blocking pool, waiting for a permit first.
+async fn run_hashing<T, F>(f: F) -> Result<T, anyhow::Error>
+where
+    F: FnOnce() -> Result<T, anyhow::Error> + Send + 'static,
+    T: Send + 'static,
+{
+    let _permit = HASHING_PERMITS.acquire().await?;
+    tokio::task::spawn_blocking(f).await?
+}
+
+pub async fn hash_password(raw: String) -> Result<String, anyhow::Error> {
+    run_hashing(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        let password_hash = argon2()
+            .hash_password(raw.as_bytes(), &salt)
+            .map_err(|_| anyhow!("Unable to hash password"))?
+            .to_string();
+        Ok(password_hash)
+    })
+    .await
+}
+
+/// Verifies with the parameters stored in the hash, so older hashes keep working.
+pub async fn verify_password(raw: String, hash: String) -> Result<(), anyhow::Error> {
+    run_hashing(move || {
+        let password_hash =
+            PasswordHash::new(&hash).map_err(|_| anyhow!("Unable to hash password"))?;
+        argon2()
+            .verify_password(raw.as_bytes(), &password_hash)
+            .map_err(|_| anyhow!("Verify password failed"))
+    })
+    .await
+}
+
+/// Verifies against a hash of a random password, for unknown users to take as long as
+/// the known ones.
+pub async fn verify_dummy_password(raw: String) -> Result<(), anyhow::Error> {
+    let hash = dummy_password_hash().await?.clone();
+    verify_password(raw, hash).await
+}
+
+/// Whether a hash was made with another algorithm or parameters than the configured ones.
+pub fn needs_rehash(hash: &str) -> bool {
+    let password_hash = match PasswordHash::new(hash) {
+        Ok(password_hash) => password_hash,
+        Err(_) => return true,
+    };
+    let configured = argon2_params();
+    let same_params = Params::try_from(&password_hash).map_or(false, |params| {
+        params.m_cost() == configured.m_cost()
+            && params.t_cost() == configured.t_cost()
+            && params.p_cost() == configured.p_cost()
+    });
+    password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+        || !same_params
+}
+
+/// How long a password reset token can be used.
+pub const PASSWORD_RESET_DURATION_MINUTES: i32 = 60;
+
+/// Reset tokens are random strings sent by email, only their hash is stored.
+fn generate_reset_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .collect()
+}
+
+fn hash_reset_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+pub async fn get_password_hash(
+    conn: &mut PgConnection, user_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT hash_password FROM accounts.passwords
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn update_password(
+    conn: &mut PgConnection, user_id: Uuid, raw: String,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO accounts.passwords (user_id, hash_password)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE SET hash_password = EXCLUDED.hash_password
+        "#,
+        user_id,
+        hash_password(raw).await?,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Issues a reset token for the user, the tokens issued before stop working.
+pub async fn create_password_reset(
+    conn: &mut PgConnection, user_id: Uuid,
+) -> Result<String, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE accounts.password_resets
+        SET expires_at = NOW()
+        WHERE user_id = $1
+        AND used_at IS NULL
+        AND expires_at > NOW()
+        "#,
+        user_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let token = generate_reset_token();
+    sqlx::query!(
+        r#"
+        INSERT INTO accounts.password_resets (user_id, token_hash, expires_at)
+        VALUES ($1, $2, NOW() + make_interval(mins => $3))
+        "#,
+        user_id,
+        hash_reset_token(&token),
+        PASSWORD_RESET_DURATION_MINUTES,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(token)
+}
+
+/// Marks a reset token as used, returning its user.
+/// Returns None when the token is unknown, expired or was already used.
+pub async fn use_password_reset(
+    conn: &mut PgConnection, token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        UPDATE accounts.password_resets
+        SET used_at = NOW()
+        WHERE token_hash = $1
+        AND used_at IS NULL
+        AND expires_at > NOW()
+        RETURNING user_id
+        "#,
+        hash_reset_token(token),
+    )
+    .fetch_optional(conn)
+    .await
 }
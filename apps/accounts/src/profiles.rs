@@ -0,0 +1,162 @@
+use chrono::NaiveDateTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use shared::AppError;
+use sqlx::{types::Json, PgConnection};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::authorization::Role;
+use crate::users::User;
+
+/// A link shown on a profile, e.g. a website or a social network.
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, JsonSchema)]
+pub struct SocialLink {
+    #[validate(length(min = 1, max = 50))]
+    pub name: String,
+    #[validate(url)]
+    pub url: String,
+}
+
+/// The profile of a user, as the user sees it.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Profile {
+    pub id: Uuid,
+    pub email: String,
+    pub role: Role,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub avatar_media_id: Option<Uuid>,
+    #[schemars(with = "Vec<SocialLink>")]
+    pub social_links: Json<Vec<SocialLink>>,
+    pub updated_at: NaiveDateTime,
+}
+
+/// The public part of a profile, embedded in the content the user authored.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PublicAuthor {
+    pub id: Uuid,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub avatar_media_id: Option<Uuid>,
+    #[schemars(with = "Vec<SocialLink>")]
+    pub social_links: Json<Vec<SocialLink>>,
+}
+
+/// Fields left out are kept, `null` clears the optional ones.
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct ChangeProfileInput {
+    #[serde(
+        default,
+        deserialize_with = "::serde_with::rust::double_option::deserialize"
+    )]
+    #[validate(length(min = 1, max = 100))]
+    pub display_name: Option<Option<String>>,
+    #[serde(
+        default,
+        deserialize_with = "::serde_with::rust::double_option::deserialize"
+    )]
+    #[validate(length(max = 2000))]
+    pub bio: Option<Option<String>>,
+    #[serde(
+        default,
+        deserialize_with = "::serde_with::rust::double_option::deserialize"
+    )]
+    pub avatar_media_id: Option<Option<Uuid>>,
+    #[validate]
+    #[validate(length(max = 10))]
+    pub social_links: Option<Vec<SocialLink>>,
+}
+
+pub async fn get_profile(conn: &mut PgConnection, user_id: Uuid) -> Result<Profile, sqlx::Error> {
+    sqlx::query_as!(
+        Profile,
+        r#"
+        SELECT id, email, role as "role: Role", display_name, bio, avatar_media_id,
+            social_links as "social_links: Json<Vec<SocialLink>>", updated_at
+        FROM accounts.users
+        WHERE id = $1
+        "#,
+        user_id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+async fn is_account_image(
+    conn: &mut PgConnection, account_id: Uuid, media_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM media.images
+            WHERE id = $2
+            AND account_id = $1
+        ) as "exists!"
+        "#,
+        account_id,
+        media_id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Changes the profile of the user, read by the caller with [`get_profile`].
+/// The avatar must be an image of the account.
+pub async fn change_profile(
+    conn: &mut PgConnection, user: &User, profile: &Profile, input: ChangeProfileInput,
+) -> Result<Profile, AppError> {
+    if let Some(Some(avatar_media_id)) = input.avatar_media_id {
+        if !is_account_image(conn, user.account_id, avatar_media_id).await? {
+            return Err(AppError::BadRequest(
+                "The avatar must be an image of the account".into(),
+            ));
+        }
+    }
+
+    let profile = sqlx::query_as!(
+        Profile,
+        r#"
+        UPDATE accounts.users
+        SET display_name = $2,
+            bio = $3,
+            avatar_media_id = $4,
+            social_links = COALESCE($5, social_links)
+        WHERE id = $1
+        RETURNING id, email, role as "role: Role", display_name, bio, avatar_media_id,
+            social_links as "social_links: Json<Vec<SocialLink>>", updated_at
+        "#,
+        user.id,
+        input
+            .display_name
+            .unwrap_or_else(|| profile.display_name.clone()),
+        input.bio.unwrap_or_else(|| profile.bio.clone()),
+        input.avatar_media_id.unwrap_or(profile.avatar_media_id),
+        input.social_links.map(Json) as Option<Json<Vec<SocialLink>>>,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(profile)
+}
+
+/// Lists the public profiles of the given users of the account,
+/// removed users are included since what they authored is kept.
+pub async fn list_public_authors(
+    conn: &mut PgConnection, account_id: Uuid, user_ids: Vec<Uuid>,
+) -> Result<Vec<PublicAuthor>, sqlx::Error> {
+    sqlx::query_as!(
+        PublicAuthor,
+        r#"
+        SELECT id, display_name, bio, avatar_media_id,
+            social_links as "social_links: Json<Vec<SocialLink>>"
+        FROM accounts.users
+        WHERE account_id = $1
+        AND id IN (SELECT UNNEST($2::uuid[]))
+        "#,
+        account_id,
+        &user_ids,
+    )
+    .fetch_all(conn)
+    .await
+}
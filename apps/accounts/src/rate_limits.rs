@@ -0,0 +1,221 @@
+use std::{
+    collections::HashMap,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{FromRequestParts, State},
+    http::{request::Parts, Request},
+    middleware::Next,
+    response::Response,
+};
+use once_cell::sync::Lazy;
+use shared::{
+    client::ClientInfo,
+    rate_limit::{Quota, RateLimiter},
+    AppError,
+};
+use sqlx::PgPool;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{api_keys::hash_api_key, authentication::ConsubClaims, extractors::APIKey};
+
+/// The plan of clients without an account, e.g. someone logging in.
+const ANONYMOUS_PLAN: &str = "anonymous";
+
+/// How long the limits of the plans are kept in memory.
+const PLAN_RATE_LIMITS_CACHE_DURATION: Duration = Duration::from_secs(60);
+
+/// Routes sharing a rate limit, their limits per plan are in `accounts.plan_rate_limits`.
+/// Layered with [`rate_limit`], e.g.
+/// `middleware::from_fn_with_state(RateLimitGroup::new("analytics", pool, limiter), rate_limit)`.
+#[derive(Clone)]
+pub struct RateLimitGroup {
+    name: &'static str,
+    pool: PgPool,
+    limiter: RateLimiter,
+}
+
+impl RateLimitGroup {
+    pub fn new(name: &'static str, pool: PgPool, limiter: RateLimiter) -> Self {
+        RateLimitGroup {
+            name,
+            pool,
+            limiter,
+        }
+    }
+}
+
+struct PlanRateLimits {
+    quotas: HashMap<(String, String), Quota>,
+    loaded_at: Instant,
+}
+
+static PLAN_RATE_LIMITS: Lazy<RwLock<Option<PlanRateLimits>>> = Lazy::new(Default::default);
+
+async fn load_plan_rate_limits(
+    pool: &PgPool,
+) -> Result<HashMap<(String, String), Quota>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT plan, route_group, capacity, refill_per_minute
+        FROM accounts.plan_rate_limits
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let quota = Quota {
+                capacity: row.capacity as u32,
+                refill_per_minute: row.refill_per_minute as u32,
+            };
+            ((row.plan, row.route_group), quota)
+        })
+        .collect())
+}
+
+fn cached_quota(key: &(String, String)) -> Option<Option<Quota>> {
+    let limits = PLAN_RATE_LIMITS.read().unwrap();
+    let limits = limits.as_ref()?;
+    (limits.loaded_at.elapsed() < PLAN_RATE_LIMITS_CACHE_DURATION)
+        .then(|| limits.quotas.get(key).copied())
+}
+
+/// The limit of the plan on the group of routes, `None` when it isn't limited.
+async fn get_quota(pool: &PgPool, plan: &str, group: &str) -> Result<Option<Quota>, sqlx::Error> {
+    let key = (plan.to_string(), group.to_string());
+    if let Some(quota) = cached_quota(&key) {
+        return Ok(quota);
+    }
+
+    let quotas = load_plan_rate_limits(pool).await?;
+    let quota = quotas.get(&key).copied();
+    *PLAN_RATE_LIMITS.write().unwrap() = Some(PlanRateLimits {
+        quotas,
+        loaded_at: Instant::now(),
+    });
+    Ok(quota)
+}
+
+/// Who the request is limited as: the api key, the user's account or no one.
+struct RateLimitClient {
+    account_id: Option<Uuid>,
+    api_key_id: Option<Uuid>,
+    plan: String,
+}
+
+impl RateLimitClient {
+    fn anonymous() -> Self {
+        RateLimitClient {
+            account_id: None,
+            api_key_id: None,
+            plan: ANONYMOUS_PLAN.to_string(),
+        }
+    }
+}
+
+async fn get_api_key_client(
+    pool: &PgPool, key: &str,
+) -> Result<Option<RateLimitClient>, sqlx::Error> {
+    let client = sqlx::query!(
+        r#"
+        SELECT k.id, k.account_id, a.plan
+        FROM accounts.api_keys k
+        INNER JOIN accounts.accounts a ON (a.id = k.account_id)
+        WHERE k.key_hash = $1
+        AND k.revoked_at IS NULL
+        "#,
+        hash_api_key(key),
+    )
+    .fetch_optional(pool)
+    .await?
+    .map(|row| RateLimitClient {
+        account_id: Some(row.account_id),
+        api_key_id: Some(row.id),
+        plan: row.plan,
+    });
+
+    Ok(client)
+}
+
+async fn get_account_plan(pool: &PgPool, account_id: Uuid) -> Result<String, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT plan
+        FROM accounts.accounts
+        WHERE id = $1
+        "#,
+        account_id,
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Unknown api keys and invalid tokens are anonymous, so they can't get a limit of their own.
+async fn request_client(parts: &mut Parts, pool: &PgPool) -> Result<RateLimitClient, AppError> {
+    if let Ok(APIKey(key)) = APIKey::from_request_parts(parts, pool).await {
+        let client = get_api_key_client(pool, &key).await?;
+        return Ok(client.unwrap_or_else(RateLimitClient::anonymous));
+    }
+
+    if let Ok(claims) = ConsubClaims::from_request_parts(parts, pool).await {
+        return Ok(RateLimitClient {
+            account_id: Some(claims.account_id),
+            api_key_id: None,
+            plan: get_account_plan(pool, claims.account_id).await?,
+        });
+    }
+
+    Ok(RateLimitClient::anonymous())
+}
+
+/// The bucket of the client in the group: its api key, its account, or for anonymous clients
+/// the address the request came from. `None` when an anonymous client has no address,
+/// rather than sharing one bucket with every other such client.
+fn bucket_key(group: &str, rate_limited: &RateLimitClient, client: &ClientInfo) -> Option<String> {
+    match (rate_limited.api_key_id, rate_limited.account_id) {
+        (Some(api_key_id), _) => Some(format!("{group}:api_key:{api_key_id}")),
+        (None, Some(account_id)) => Some(format!("{group}:account:{account_id}")),
+        (None, None) => client
+            .ip_address
+            .as_ref()
+            .map(|ip_address| format!("{group}:ip:{ip_address}")),
+    }
+}
+
+/// Limits the requests of each client (api key, account or ip address) to the group
+/// of routes with a token bucket. Refused requests answer 429 with `Retry-After`,
+/// every limited response has the `RateLimit-*` headers.
+pub async fn rate_limit<B: Send>(
+    State(group): State<RateLimitGroup>, client: ClientInfo, request: Request<B>, next: Next<B>,
+) -> Result<Response, AppError> {
+    let (mut parts, body) = request.into_parts();
+    let rate_limited = request_client(&mut parts, &group.pool).await?;
+    let request = Request::from_parts(parts, body);
+
+    let quota = match get_quota(&group.pool, &rate_limited.plan, group.name).await? {
+        Some(quota) => quota,
+        None => return Ok(next.run(request).await),
+    };
+
+    let key = match bucket_key(group.name, &rate_limited, &client) {
+        Some(key) => key,
+        None => {
+            warn!("Request without a client address, it isn't rate limited");
+            return Ok(next.run(request).await);
+        }
+    };
+    let rate_limit = group.limiter.take(&key, quota).await?;
+    if !rate_limit.is_allowed() {
+        return Err(AppError::rate_limited(rate_limit));
+    }
+
+    let mut response = next.run(request).await;
+    rate_limit.set_headers(response.headers_mut());
+    Ok(response)
+}
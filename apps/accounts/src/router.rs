@@ -1,18 +1,35 @@
-use crate::accounts::CreatePublicAccountInput;
-use crate::authentication::AccessToken;
+use crate::accounts::{AccountKey, CreatePublicAccountInput, ExpireAccountKeyInput};
+use crate::api_keys::{ApiKey, CreateApiKeyInput, CreatedApiKey};
+use crate::authentication::{AccessToken, ConsubClaims, PasswordLogin};
+use crate::custom_domains::{CreateCustomDomainInput, CustomDomain};
+use crate::invitations::{CreateInvitationInput, Invitation};
+use crate::login_attempts::LoginAttempt;
+use crate::oidc::{CreateOidcProviderInput, OidcAuthorization, OidcProvider};
+use crate::profiles::{ChangeProfileInput, Profile};
+use crate::sessions::Session;
+use crate::settings::{AccountSettings, ChangeAccountSettingsInput};
+use crate::two_factor::{LoginChallenge, RecoveryCodes, TotpEnrollment};
 
+use crate::authorization::permissions::{
+    ManageAccount, ManageOidcProviders, ManageUsers, ReadAuditLog, ReadContent,
+};
 use crate::extractors::AccountID;
-use crate::User;
-use aide::axum::routing::{get_with, post_with};
-use aide::axum::{IntoApiResponse, ApiRouter};
+use crate::{Authorized, User};
+use aide::axum::routing::{delete_with, get_with, patch_with, post_with};
+use aide::axum::{ApiRouter, IntoApiResponse};
 use aide::transform::TransformOperation;
 use axum::http::StatusCode;
-use axum::routing::{get, post};
+use axum::routing::post;
 use axum::{debug_handler, Json};
-use axum::{extract::State, response::IntoResponse};
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+};
 use schemars::JsonSchema;
-use shared::{AppError, AppState};
+use shared::audit::{self, Action};
+use shared::{client::ClientInfo, dns::DnsResolver, mail::Mailer, AppError, AppState};
 use sqlx::PgPool;
+use uuid::Uuid;
 use validator::Validate;
 
 #[debug_handler]
@@ -32,54 +49,1241 @@ pub struct CreateUserAccessTokenWithPassword {
 }
 
 pub async fn create_user_access_token_with_password(
-    State(pool): State<PgPool>, AccountID(account_id): AccountID,
+    State(pool): State<PgPool>, AccountID(account_id): AccountID, client: ClientInfo,
     Json(body): Json<CreateUserAccessTokenWithPassword>,
 ) -> Result<impl IntoApiResponse, AppError> {
     body.validate()?;
-    let access_token = crate::authentication::authenticate_user_with_password(
+    let login = crate::authentication::create_session_with_password(
         &pool,
         account_id,
         body.email,
         body.password,
+        client,
     )
     .await?;
 
-    Ok((StatusCode::CREATED, Json(access_token)))
+    // Users with two-factor get a challenge to complete at /users/access-tokens/two-factor.
+    let status = match login {
+        PasswordLogin::AccessToken(_) => StatusCode::CREATED,
+        PasswordLogin::Challenge(_) => StatusCode::ACCEPTED,
+    };
+    Ok((status, Json(login)))
 }
 
 pub fn create_user_access_token_with_password_docs(op: TransformOperation) -> TransformOperation {
     op.id("get_access_token_with_password")
-        .description("Get an access token for a user with a password.")
-        .response::<200, Json<AccessToken>>()
+        .description(
+            "Get an access token for a user with a password, or a challenge when the user has two-factor.",
+        )
+        .response::<201, Json<AccessToken>>()
+        .response::<202, Json<LoginChallenge>>()
         .security_requirement("ApiKey")
         .tag("accounts")
 }
 
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct CompleteLoginChallengeInput {
+    pub challenge_token: String,
+    /// A code of the authenticator app, or a recovery code.
+    pub code: String,
+}
+
+pub async fn complete_login_challenge(
+    State(pool): State<PgPool>, client: ClientInfo, Json(body): Json<CompleteLoginChallengeInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let access_token = crate::authentication::complete_login_challenge_with_code(
+        &pool,
+        body.challenge_token,
+        body.code,
+        client,
+    )
+    .await?;
+    Ok((StatusCode::CREATED, Json(access_token)))
+}
+
+pub fn complete_login_challenge_docs(op: TransformOperation) -> TransformOperation {
+    op.id("complete_login_challenge")
+        .description("Get an access token with a two-factor code, after the password login.")
+        .response::<201, Json<AccessToken>>()
+        .tag("accounts")
+}
+
+pub async fn enroll_totp(
+    State(pool): State<PgPool>, user: User, client: ClientInfo,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    let enrollment = crate::two_factor::enroll_totp(&pool, &mut tx, &user).await?;
+    // The secret is left out of the audit log.
+    audit::record(
+        &mut tx,
+        &user.actor(client),
+        Action::Created,
+        "accounts.user_totp",
+        user.id,
+        None::<&()>,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((StatusCode::CREATED, Json(enrollment)))
+}
+
+pub fn enroll_totp_docs(op: TransformOperation) -> TransformOperation {
+    op.id("enroll_totp")
+        .description("Generate a TOTP secret for the current user, to be confirmed with a code.")
+        .response::<201, Json<TotpEnrollment>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct TwoFactorCodeInput {
+    pub code: String,
+}
+
+pub async fn confirm_totp(
+    State(pool): State<PgPool>, user: User, client: ClientInfo,
+    Json(body): Json<TwoFactorCodeInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    let recovery_codes =
+        crate::two_factor::confirm_totp_enrollment(&mut tx, &user, &body.code).await?;
+    audit::record(
+        &mut tx,
+        &user.actor(client),
+        Action::Verified,
+        "accounts.user_totp",
+        user.id,
+        None::<&()>,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((StatusCode::OK, Json(recovery_codes)))
+}
+
+pub fn confirm_totp_docs(op: TransformOperation) -> TransformOperation {
+    op.id("confirm_totp")
+        .description("Enable two-factor with a first code, the recovery codes are only shown here.")
+        .response::<200, Json<RecoveryCodes>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn disable_totp(
+    State(pool): State<PgPool>, user: User, client: ClientInfo,
+    Json(body): Json<TwoFactorCodeInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    crate::two_factor::turn_off_totp(&pool, &mut tx, &user, &body.code, &client).await?;
+    audit::record(
+        &mut tx,
+        &user.actor(client),
+        Action::Deleted,
+        "accounts.user_totp",
+        user.id,
+        None::<&()>,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn disable_totp_docs(op: TransformOperation) -> TransformOperation {
+    op.id("disable_totp")
+        .description("Disable two-factor with a current code, the recovery codes are deleted.")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn regenerate_recovery_codes(
+    State(pool): State<PgPool>, user: User, client: ClientInfo,
+    Json(body): Json<TwoFactorCodeInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    let recovery_codes =
+        crate::two_factor::regenerate_recovery_codes(&pool, &mut tx, &user, &body.code, &client)
+            .await?;
+    // The codes are left out of the audit log.
+    audit::record(
+        &mut tx,
+        &user.actor(client),
+        Action::Changed,
+        "accounts.recovery_codes",
+        user.id,
+        None::<&()>,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((StatusCode::OK, Json(recovery_codes)))
+}
+
+pub fn regenerate_recovery_codes_docs(op: TransformOperation) -> TransformOperation {
+    op.id("regenerate_recovery_codes")
+        .description("Replace the recovery codes, the previous ones stop working.")
+        .response::<200, Json<RecoveryCodes>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct RefreshAccessTokenInput {
+    pub refresh_token: String,
+}
+
+pub async fn refresh_access_token(
+    State(pool): State<PgPool>, client: ClientInfo, Json(body): Json<RefreshAccessTokenInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let access_token =
+        crate::authentication::refresh_access_token(&pool, body.refresh_token, client).await?;
+    Ok((StatusCode::CREATED, Json(access_token)))
+}
+
+pub fn refresh_access_token_docs(op: TransformOperation) -> TransformOperation {
+    op.id("refresh_access_token")
+        .description("Exchange a refresh token for a new access token and refresh token.")
+        .response::<201, Json<AccessToken>>()
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Serialize, JsonSchema)]
+pub struct CurrentSession {
+    #[serde(flatten)]
+    pub session: Session,
+    /// Whether this is the session of the access token used for the request.
+    pub current: bool,
+}
+
+pub async fn list_sessions(
+    State(pool): State<PgPool>, claims: ConsubClaims,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let sessions = crate::sessions::list_active_sessions(&mut conn, claims.user_id).await?;
+    let sessions: Vec<CurrentSession> = sessions
+        .into_iter()
+        .map(|session| CurrentSession {
+            current: Some(session.id) == claims.session_id,
+            session,
+        })
+        .collect();
+    Ok((StatusCode::OK, Json(sessions)))
+}
+
+pub fn list_sessions_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_sessions")
+        .description("List the active sessions of the current user.")
+        .response::<200, Json<Vec<CurrentSession>>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PathSession {
+    pub session_id: Uuid,
+}
+
+pub async fn revoke_session(
+    State(pool): State<PgPool>, claims: ConsubClaims, Path(path): Path<PathSession>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    crate::sessions::revoke_session(&mut conn, claims.user_id, path.session_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("Session not found".into()),
+            e => e.into(),
+        })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn revoke_session_docs(op: TransformOperation) -> TransformOperation {
+    op.id("revoke_session")
+        .description("Revoke a session of the current user, its tokens stop working right away.")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn logout(
+    State(pool): State<PgPool>, claims: ConsubClaims,
+) -> Result<impl IntoApiResponse, AppError> {
+    if let Some(session_id) = claims.session_id {
+        let mut conn = pool.acquire().await?;
+        crate::sessions::revoke_session(&mut conn, claims.user_id, session_id).await?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn logout_docs(op: TransformOperation) -> TransformOperation {
+    op.id("logout")
+        .description("Revoke the session of the access token used for the request.")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, Validate, JsonSchema)]
+pub struct ChangePasswordInput {
+    pub current_password: String,
+    #[validate(length(min = 6))]
+    pub new_password: String,
+}
+
+pub async fn change_password(
+    State(pool): State<PgPool>, claims: ConsubClaims, client: ClientInfo,
+    Json(body): Json<ChangePasswordInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    let mut tx = pool.begin().await?;
+    crate::authentication::change_password(
+        &mut tx,
+        &claims,
+        body.current_password,
+        body.new_password,
+    )
+    .await?;
+    // The hash is left out of the audit log.
+    audit::record(
+        &mut tx,
+        &claims.actor(client),
+        Action::Changed,
+        "accounts.password",
+        claims.user_id,
+        None::<&()>,
+        None,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn change_password_docs(op: TransformOperation) -> TransformOperation {
+    op.id("change_password")
+        .description("Change the password of the current user, the other sessions are revoked.")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, Validate, JsonSchema)]
+pub struct ForgotPasswordInput {
+    #[validate(email)]
+    pub email: String,
+}
+
+pub async fn forgot_password(
+    State(pool): State<PgPool>, State(mailer): State<Mailer>, AccountID(account_id): AccountID,
+    Json(body): Json<ForgotPasswordInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    // Sent after the response, its time and errors would tell which emails have a user.
+    tokio::spawn(async move {
+        let email = body.email;
+        if let Err(e) =
+            crate::authentication::request_password_reset(&pool, &mailer, account_id, email).await
+        {
+            tracing::error!(error = %e, %account_id, "failed to send the password reset");
+        }
+    });
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub fn forgot_password_docs(op: TransformOperation) -> TransformOperation {
+    op.id("forgot_password")
+        .description("Email a password reset token, if the email belongs to a user.")
+        .response::<202, ()>()
+        .security_requirement("ApiKey")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, Validate, JsonSchema)]
+pub struct ResetPasswordInput {
+    pub token: String,
+    #[validate(length(min = 6))]
+    pub new_password: String,
+}
+
+pub async fn reset_password(
+    State(pool): State<PgPool>, Json(body): Json<ResetPasswordInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    crate::authentication::reset_password(&pool, body.token, body.new_password).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn reset_password_docs(op: TransformOperation) -> TransformOperation {
+    op.id("reset_password")
+        .description(
+            "Choose a new password with a reset token, every session of the user is revoked.",
+        )
+        .response::<204, ()>()
+        .tag("accounts")
+}
+
 pub async fn user_profile(
-    State(_pool): State<PgPool>, user: User,
+    State(pool): State<PgPool>, user: User,
 ) -> Result<impl IntoApiResponse, AppError> {
-    Ok((StatusCode::OK, Json(user)))
+    let mut conn = pool.acquire().await?;
+    let profile = crate::profiles::get_profile(&mut conn, user.id).await?;
+    Ok((StatusCode::OK, Json(profile)))
 }
 
 fn user_profile_docs(op: TransformOperation) -> TransformOperation {
     op.id("get_user_profile")
         .description("Get the profile of the current user.")
-        .response::<200, Json<User>>()
+        .response::<200, Json<Profile>>()
         .security_requirement("ApiKey")
         .tag("accounts")
 }
 
+pub async fn change_user_profile(
+    State(pool): State<PgPool>, user: User, client: ClientInfo,
+    Json(body): Json<ChangeProfileInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    let mut tx = pool.begin().await?;
+    let before = crate::profiles::get_profile(&mut tx, user.id).await?;
+    let profile = crate::profiles::change_profile(&mut tx, &user, &before, body).await?;
+    audit::changed(
+        &mut tx,
+        &user.actor(client),
+        "accounts.user",
+        user.id,
+        &before,
+        &profile,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((StatusCode::OK, Json(profile)))
+}
+
+fn change_user_profile_docs(op: TransformOperation) -> TransformOperation {
+    op.id("change_user_profile")
+        .description("Change the profile of the current user, shown on the content they author.")
+        .response::<200, Json<Profile>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
 pub async fn list_account_keys(
-    State(pool): State<PgPool>, user: User,
-) -> Result<impl IntoResponse, AppError> {
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>,
+) -> Result<impl IntoApiResponse, AppError> {
     let keys = crate::accounts::get_account_keys(&pool, user.account_id).await?;
     Ok((StatusCode::OK, Json(keys)))
 }
 
+fn list_account_keys_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_account_keys")
+        .description("List the keys of the account, newest first.")
+        .response::<200, Json<Vec<AccountKey>>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn create_account_key(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>, client: ClientInfo,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    let key = crate::accounts::create_account_key(&mut tx, user.account_id).await?;
+    audit::created(
+        &mut tx,
+        &user.actor(client),
+        "accounts.account_key",
+        key.id,
+        &key,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((StatusCode::CREATED, Json(key)))
+}
+
+fn create_account_key_docs(op: TransformOperation) -> TransformOperation {
+    op.id("create_account_key")
+        .description("Mint a new key, it signs the access tokens from now on.")
+        .response::<201, Json<AccountKey>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PathAccountKey {
+    pub account_key_id: Uuid,
+}
+
+fn account_key_not_found(e: sqlx::Error) -> AppError {
+    match e {
+        sqlx::Error::RowNotFound => AppError::NotFound("Account key not found".into()),
+        e => e.into(),
+    }
+}
+
+pub async fn expire_account_key(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>, client: ClientInfo,
+    Path(path): Path<PathAccountKey>, Json(body): Json<ExpireAccountKeyInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    let mut tx = pool.begin().await?;
+    crate::accounts::lock_account_keys(&mut tx, user.account_id).await?;
+    if !crate::accounts::has_other_valid_account_key(
+        &mut tx,
+        user.account_id,
+        path.account_key_id,
+        body.overlap_minutes,
+    )
+    .await?
+    {
+        return Err(AppError::BadRequest(
+            "Create a new account key before expiring this one".into(),
+        ));
+    }
+    let key = crate::accounts::expire_account_key(
+        &mut tx,
+        user.account_id,
+        path.account_key_id,
+        body.overlap_minutes,
+    )
+    .await
+    .map_err(account_key_not_found)?;
+    let actor = user.actor(client);
+    audit::record(
+        &mut tx,
+        &actor,
+        Action::Expired,
+        "accounts.account_key",
+        key.id,
+        None,
+        Some(&key),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((StatusCode::OK, Json(key)))
+}
+
+fn expire_account_key_docs(op: TransformOperation) -> TransformOperation {
+    op.id("expire_account_key")
+        .description("Schedule the expiry of a key, it keeps verifying tokens during the overlap.")
+        .response::<200, Json<AccountKey>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn revoke_account_key(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>, client: ClientInfo,
+    Path(path): Path<PathAccountKey>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    crate::accounts::lock_account_keys(&mut tx, user.account_id).await?;
+    if !crate::accounts::has_other_valid_account_key(
+        &mut tx,
+        user.account_id,
+        path.account_key_id,
+        0,
+    )
+    .await?
+    {
+        return Err(AppError::BadRequest(
+            "Create a new account key before revoking this one".into(),
+        ));
+    }
+    let key = crate::accounts::revoke_account_key(&mut tx, user.account_id, path.account_key_id)
+        .await
+        .map_err(account_key_not_found)?;
+    let actor = user.actor(client);
+    audit::record(
+        &mut tx,
+        &actor,
+        Action::Revoked,
+        "accounts.account_key",
+        key.id,
+        None,
+        Some(&key),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn revoke_account_key_docs(op: TransformOperation) -> TransformOperation {
+    op.id("revoke_account_key")
+        .description("Revoke a key right away, tokens signed by it stop working.")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn list_failed_login_attempts(
+    State(pool): State<PgPool>, user: Authorized<ReadAuditLog>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let attempts =
+        crate::login_attempts::list_failed_login_attempts(&mut conn, user.account_id).await?;
+    Ok((StatusCode::OK, Json(attempts)))
+}
+
+fn list_failed_login_attempts_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_failed_login_attempts")
+        .description("List the latest failed login attempts of the account.")
+        .response::<200, Json<Vec<LoginAttempt>>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn get_account_settings(
+    State(pool): State<PgPool>, user: Authorized<ReadContent>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let account = crate::accounts::get_account_by_id(&pool, user.account_id).await?;
+    Ok((StatusCode::OK, Json(AccountSettings::from(account))))
+}
+
+fn get_account_settings_docs(op: TransformOperation) -> TransformOperation {
+    op.id("get_account_settings")
+        .description("Get the settings of the account, with every feature flag.")
+        .response::<200, Json<AccountSettings>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn change_account_settings(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>, client: ClientInfo,
+    Json(body): Json<ChangeAccountSettingsInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    let mut tx = pool.begin().await?;
+    let before = crate::accounts::lock_account(&mut tx, user.account_id).await?;
+    let account = crate::settings::change_account_settings(&mut tx, &before, body).await?;
+    let before = AccountSettings::from(before);
+    let settings = AccountSettings::from(account);
+    let actor = user.actor(client);
+    audit::changed(
+        &mut tx,
+        &actor,
+        "accounts.account",
+        user.account_id,
+        &before,
+        &settings,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((StatusCode::OK, Json(settings)))
+}
+
+fn change_account_settings_docs(op: TransformOperation) -> TransformOperation {
+    op.id("change_account_settings")
+        .description("Change the name, origins or feature flags of the account.")
+        .response::<200, Json<AccountSettings>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn list_custom_domains(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let domains = crate::custom_domains::list_custom_domains(&mut conn, user.account_id).await?;
+    Ok((StatusCode::OK, Json(domains)))
+}
+
+fn list_custom_domains_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_custom_domains")
+        .description("List the custom domains of the account.")
+        .response::<200, Json<Vec<CustomDomain>>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn create_custom_domain(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>, client: ClientInfo,
+    Json(body): Json<CreateCustomDomainInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    let mut tx = pool.begin().await?;
+    let domain =
+        crate::custom_domains::create_custom_domain(&mut tx, user.account_id, body).await?;
+    audit::created(
+        &mut tx,
+        &user.actor(client),
+        "accounts.custom_domain",
+        domain.id,
+        &domain,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((StatusCode::CREATED, Json(domain)))
+}
+
+fn create_custom_domain_docs(op: TransformOperation) -> TransformOperation {
+    op.id("create_custom_domain")
+        .description(
+            "Add a custom domain, it's used once its verification token is published in a TXT record.",
+        )
+        .response::<201, Json<CustomDomain>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PathCustomDomain {
+    pub custom_domain_id: Uuid,
+}
+
+fn custom_domain_not_found(e: sqlx::Error) -> AppError {
+    match e {
+        sqlx::Error::RowNotFound => AppError::NotFound("Custom domain not found".into()),
+        e => e.into(),
+    }
+}
+
+pub async fn verify_custom_domain(
+    State(pool): State<PgPool>, State(dns): State<DnsResolver>, user: Authorized<ManageAccount>,
+    client: ClientInfo, Path(path): Path<PathCustomDomain>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    let domain = crate::custom_domains::verify_custom_domain(
+        &mut tx,
+        &dns,
+        user.account_id,
+        path.custom_domain_id,
+    )
+    .await?;
+    let actor = user.actor(client);
+    audit::record(
+        &mut tx,
+        &actor,
+        Action::Verified,
+        "accounts.custom_domain",
+        domain.id,
+        None,
+        Some(&domain),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((StatusCode::OK, Json(domain)))
+}
+
+fn verify_custom_domain_docs(op: TransformOperation) -> TransformOperation {
+    op.id("verify_custom_domain")
+        .description("Check the TXT record of a custom domain holds its verification token.")
+        .response::<200, Json<CustomDomain>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn delete_custom_domain(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>, client: ClientInfo,
+    Path(path): Path<PathCustomDomain>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    let domain =
+        crate::custom_domains::get_custom_domain(&mut tx, user.account_id, path.custom_domain_id)
+            .await
+            .map_err(custom_domain_not_found)?;
+    crate::custom_domains::delete_custom_domain(&mut tx, user.account_id, domain.id)
+        .await
+        .map_err(custom_domain_not_found)?;
+    audit::deleted(
+        &mut tx,
+        &user.actor(client),
+        "accounts.custom_domain",
+        domain.id,
+        &domain,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn delete_custom_domain_docs(op: TransformOperation) -> TransformOperation {
+    op.id("delete_custom_domain")
+        .description("Delete a custom domain, the account stops being served on it.")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn list_api_keys(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let keys = crate::api_keys::list_api_keys(&mut conn, user.account_id).await?;
+    Ok((StatusCode::OK, Json(keys)))
+}
+
+fn list_api_keys_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_api_keys")
+        .description("List the api keys of the account, newest first.")
+        .response::<200, Json<Vec<ApiKey>>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn create_api_key(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>, client: ClientInfo,
+    Json(body): Json<CreateApiKeyInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    let mut tx = pool.begin().await?;
+    let key = crate::api_keys::create_api_key(&mut tx, user.account_id, user.id, body).await?;
+    // The key itself is left out, only its prefix is kept.
+    let api_key = &key.api_key;
+    audit::created(
+        &mut tx,
+        &user.actor(client),
+        "accounts.api_key",
+        api_key.id,
+        api_key,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((StatusCode::CREATED, Json(key)))
+}
+
+fn create_api_key_docs(op: TransformOperation) -> TransformOperation {
+    op.id("create_api_key")
+        .description(
+            "Create an api key, only returned in this response. Admin keys act as the user creating them.",
+        )
+        .response::<201, Json<CreatedApiKey>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PathApiKey {
+    pub api_key_id: Uuid,
+}
+
+pub async fn revoke_api_key(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>, client: ClientInfo,
+    Path(path): Path<PathApiKey>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    let key = crate::api_keys::revoke_api_key(&mut tx, user.account_id, path.api_key_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("API key not found".into()),
+            e => e.into(),
+        })?;
+    let actor = user.actor(client);
+    audit::record(
+        &mut tx,
+        &actor,
+        Action::Revoked,
+        "accounts.api_key",
+        key.id,
+        None,
+        Some(&key),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn revoke_api_key_docs(op: TransformOperation) -> TransformOperation {
+    op.id("revoke_api_key")
+        .description("Revoke an api key right away.")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn list_users(
+    State(pool): State<PgPool>, user: Authorized<ManageUsers>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let users = crate::users::list_users(&mut conn, user.account_id).await?;
+    Ok((StatusCode::OK, Json(users)))
+}
+
+fn list_users_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_users")
+        .description("List the users of the account.")
+        .response::<200, Json<Vec<User>>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PathUser {
+    pub user_id: Uuid,
+}
+
+pub async fn remove_user(
+    State(pool): State<PgPool>, user: Authorized<ManageUsers>, client: ClientInfo,
+    Path(path): Path<PathUser>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    let removed = crate::users::remove_user_from_account(&mut tx, &user, path.user_id).await?;
+    audit::deleted(
+        &mut tx,
+        &user.actor(client),
+        "accounts.user",
+        removed.id,
+        &removed,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn remove_user_docs(op: TransformOperation) -> TransformOperation {
+    op.id("remove_user")
+        .description("Remove a user from the account, the user can't sign in anymore.")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn list_invitations(
+    State(pool): State<PgPool>, user: Authorized<ManageUsers>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let invitations =
+        crate::invitations::list_pending_invitations(&mut conn, user.account_id).await?;
+    Ok((StatusCode::OK, Json(invitations)))
+}
+
+fn list_invitations_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_invitations")
+        .description("List the invitations which weren't accepted, revoked nor expired.")
+        .response::<200, Json<Vec<Invitation>>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn create_invitation(
+    State(pool): State<PgPool>, State(mailer): State<Mailer>, user: Authorized<ManageUsers>,
+    client: ClientInfo, Json(body): Json<CreateInvitationInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    let mut tx = pool.begin().await?;
+    let (invitation, token) = crate::invitations::invite_user(&mut tx, &user, body).await?;
+    let actor = user.actor(client);
+    audit::created(
+        &mut tx,
+        &actor,
+        "accounts.invitation",
+        invitation.id,
+        &invitation,
+    )
+    .await?;
+    tx.commit().await?;
+    crate::invitations::send_invitation(&pool, &mailer, &user, &invitation, &token).await?;
+    Ok((StatusCode::CREATED, Json(invitation)))
+}
+
+fn create_invitation_docs(op: TransformOperation) -> TransformOperation {
+    op.id("create_invitation")
+        .description("Invite someone to the account with a role, the invitation is emailed.")
+        .response::<201, Json<Invitation>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PathInvitation {
+    pub invitation_id: Uuid,
+}
+
+pub async fn revoke_invitation(
+    State(pool): State<PgPool>, user: Authorized<ManageUsers>, client: ClientInfo,
+    Path(path): Path<PathInvitation>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    let invitation =
+        crate::invitations::revoke_invitation(&mut tx, user.account_id, path.invitation_id)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound => AppError::NotFound("Invitation not found".into()),
+                e => e.into(),
+            })?;
+    let actor = user.actor(client);
+    audit::record(
+        &mut tx,
+        &actor,
+        Action::Revoked,
+        "accounts.invitation",
+        invitation.id,
+        None,
+        Some(&invitation),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn revoke_invitation_docs(op: TransformOperation) -> TransformOperation {
+    op.id("revoke_invitation")
+        .description("Revoke a pending invitation.")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, Validate, JsonSchema)]
+pub struct AcceptInvitationInput {
+    pub token: String,
+    #[validate(length(min = 6))]
+    pub password: String,
+}
+
+pub async fn accept_invitation(
+    State(pool): State<PgPool>, Json(body): Json<AcceptInvitationInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    let user =
+        crate::invitations::accept_invitation_with_password(&pool, body.token, body.password)
+            .await?;
+    Ok((StatusCode::CREATED, Json(user)))
+}
+
+fn accept_invitation_docs(op: TransformOperation) -> TransformOperation {
+    op.id("accept_invitation")
+        .description("Accept an invitation by choosing a password, the user can then sign in.")
+        .response::<201, Json<User>>()
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PathOidcProvider {
+    pub oidc_provider_id: Uuid,
+}
+
+pub async fn list_oidc_providers(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let providers = crate::oidc::list_oidc_providers(&mut conn, user.account_id).await?;
+    Ok((StatusCode::OK, Json(providers)))
+}
+
+fn list_oidc_providers_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_oidc_providers")
+        .description("List the OpenID Connect providers users of the account can sign in with.")
+        .response::<200, Json<Vec<OidcProvider>>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn create_oidc_provider(
+    State(pool): State<PgPool>, user: Authorized<ManageOidcProviders>, client: ClientInfo,
+    Json(body): Json<CreateOidcProviderInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    let metadata = crate::oidc::discover_oidc_provider(&body.issuer).await?;
+    let mut tx = pool.begin().await?;
+    let provider =
+        crate::oidc::create_oidc_provider(&mut tx, user.account_id, body, metadata).await?;
+    audit::created(
+        &mut tx,
+        &user.actor(client),
+        "accounts.oidc_provider",
+        provider.id,
+        &provider,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((StatusCode::CREATED, Json(provider)))
+}
+
+fn create_oidc_provider_docs(op: TransformOperation) -> TransformOperation {
+    op.id("create_oidc_provider")
+        .description(
+            "Add an OpenID Connect provider, its endpoints are discovered from the issuer.",
+        )
+        .response::<201, Json<OidcProvider>>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+pub async fn delete_oidc_provider(
+    State(pool): State<PgPool>, user: Authorized<ManageOidcProviders>, client: ClientInfo,
+    Path(path): Path<PathOidcProvider>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    let provider =
+        crate::oidc::delete_oidc_provider(&mut tx, user.account_id, path.oidc_provider_id)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound => AppError::NotFound("OIDC provider not found".into()),
+                e => e.into(),
+            })?;
+    audit::deleted(
+        &mut tx,
+        &user.actor(client),
+        "accounts.oidc_provider",
+        provider.id,
+        &provider,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn delete_oidc_provider_docs(op: TransformOperation) -> TransformOperation {
+    op.id("delete_oidc_provider")
+        .description("Delete an OpenID Connect provider and the identities linked to it.")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PathOidcProviderSlug {
+    pub slug: String,
+}
+
+pub async fn authorize_with_oidc(
+    State(pool): State<PgPool>, AccountID(account_id): AccountID,
+    Path(path): Path<PathOidcProviderSlug>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let provider = crate::oidc::get_oidc_provider_by_slug(&mut conn, account_id, &path.slug)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("OIDC provider not found".into()),
+            e => e.into(),
+        })?;
+    let authorization = crate::oidc::start_oidc_login(&mut conn, &provider).await?;
+    Ok((StatusCode::CREATED, Json(authorization)))
+}
+
+fn authorize_with_oidc_docs(op: TransformOperation) -> TransformOperation {
+    op.id("authorize_with_oidc")
+        .description(
+            "Start a login with an OpenID Connect provider, the user is sent to the returned url.",
+        )
+        .response::<201, Json<OidcAuthorization>>()
+        .security_requirement("ApiKey")
+        .tag("accounts")
+}
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct OidcCallbackInput {
+    pub state: String,
+    pub code: String,
+}
+
+pub async fn create_user_access_token_with_oidc(
+    State(pool): State<PgPool>, client: ClientInfo, Json(body): Json<OidcCallbackInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let login = crate::oidc::complete_oidc_login(&pool, body.state, body.code, client).await?;
+
+    let status = match login {
+        PasswordLogin::AccessToken(_) => StatusCode::CREATED,
+        PasswordLogin::Challenge(_) => StatusCode::ACCEPTED,
+    };
+    Ok((status, Json(login)))
+}
+
+fn create_user_access_token_with_oidc_docs(op: TransformOperation) -> TransformOperation {
+    op.id("get_access_token_with_oidc")
+        .description(
+            "Get an access token with the code sent back by the provider, or a challenge when the user has two-factor.",
+        )
+        .response::<201, Json<AccessToken>>()
+        .response::<202, Json<LoginChallenge>>()
+        .tag("accounts")
+}
+
 pub fn routes() -> ApiRouter<AppState> {
     ApiRouter::new()
         .route("/", post(create_account))
-        .route("/account-keys", get(list_account_keys))
+        .api_route(
+            "/account-keys",
+            get_with(list_account_keys, list_account_keys_docs),
+        )
+        .api_route(
+            "/account-keys",
+            post_with(create_account_key, create_account_key_docs),
+        )
+        .api_route(
+            "/account-keys/:account_key_id/expire",
+            post_with(expire_account_key, expire_account_key_docs),
+        )
+        .api_route(
+            "/account-keys/:account_key_id",
+            delete_with(revoke_account_key, revoke_account_key_docs),
+        )
+        .api_route(
+            "/login-attempts/failed",
+            get_with(list_failed_login_attempts, list_failed_login_attempts_docs),
+        )
+        .api_route(
+            "/settings",
+            get_with(get_account_settings, get_account_settings_docs),
+        )
+        .api_route(
+            "/settings",
+            patch_with(change_account_settings, change_account_settings_docs),
+        )
+        .api_route(
+            "/custom-domains",
+            get_with(list_custom_domains, list_custom_domains_docs),
+        )
+        .api_route(
+            "/custom-domains",
+            post_with(create_custom_domain, create_custom_domain_docs),
+        )
+        .api_route(
+            "/custom-domains/:custom_domain_id",
+            delete_with(delete_custom_domain, delete_custom_domain_docs),
+        )
+        .api_route(
+            "/custom-domains/:custom_domain_id/verify",
+            post_with(verify_custom_domain, verify_custom_domain_docs),
+        )
+        .api_route("/api-keys", get_with(list_api_keys, list_api_keys_docs))
+        .api_route("/api-keys", post_with(create_api_key, create_api_key_docs))
+        .api_route(
+            "/api-keys/:api_key_id",
+            delete_with(revoke_api_key, revoke_api_key_docs),
+        )
+        .api_route(
+            "/invitations",
+            get_with(list_invitations, list_invitations_docs),
+        )
+        .api_route(
+            "/invitations",
+            post_with(create_invitation, create_invitation_docs),
+        )
+        .api_route(
+            "/invitations/accept",
+            post_with(accept_invitation, accept_invitation_docs),
+        )
+        .api_route(
+            "/invitations/:invitation_id",
+            delete_with(revoke_invitation, revoke_invitation_docs),
+        )
+        .api_route(
+            "/oidc-providers",
+            get_with(list_oidc_providers, list_oidc_providers_docs),
+        )
+        .api_route(
+            "/oidc-providers",
+            post_with(create_oidc_provider, create_oidc_provider_docs),
+        )
+        .api_route(
+            "/oidc-providers/:oidc_provider_id",
+            delete_with(delete_oidc_provider, delete_oidc_provider_docs),
+        )
+        .api_route(
+            "/oidc/:slug/authorize",
+            post_with(authorize_with_oidc, authorize_with_oidc_docs),
+        )
+        .api_route(
+            "/users/access-tokens/oidc",
+            post_with(
+                create_user_access_token_with_oidc,
+                create_user_access_token_with_oidc_docs,
+            ),
+        )
+        .api_route("/users", get_with(list_users, list_users_docs))
+        .api_route(
+            "/users/:user_id",
+            delete_with(remove_user, remove_user_docs),
+        )
         .api_route(
             "/users/access-tokens/passwords",
             post_with(
@@ -87,5 +1291,54 @@ pub fn routes() -> ApiRouter<AppState> {
                 create_user_access_token_with_password_docs,
             ),
         )
-        .api_route("/users/profiles", get_with(user_profile, user_profile_docs))        
+        .api_route(
+            "/users/access-tokens/two-factor",
+            post_with(complete_login_challenge, complete_login_challenge_docs),
+        )
+        .api_route(
+            "/users/access-tokens/refresh",
+            post_with(refresh_access_token, refresh_access_token_docs),
+        )
+        .api_route("/users/logout", post_with(logout, logout_docs))
+        .api_route(
+            "/users/sessions",
+            get_with(list_sessions, list_sessions_docs),
+        )
+        .api_route(
+            "/users/sessions/:session_id",
+            delete_with(revoke_session, revoke_session_docs),
+        )
+        .api_route(
+            "/users/passwords",
+            post_with(change_password, change_password_docs),
+        )
+        .api_route(
+            "/users/passwords/forgot",
+            post_with(forgot_password, forgot_password_docs),
+        )
+        .api_route(
+            "/users/passwords/reset",
+            post_with(reset_password, reset_password_docs),
+        )
+        .api_route(
+            "/users/two-factor",
+            post_with(enroll_totp, enroll_totp_docs),
+        )
+        .api_route(
+            "/users/two-factor",
+            delete_with(disable_totp, disable_totp_docs),
+        )
+        .api_route(
+            "/users/two-factor/confirm",
+            post_with(confirm_totp, confirm_totp_docs),
+        )
+        .api_route(
+            "/users/two-factor/recovery-codes",
+            post_with(regenerate_recovery_codes, regenerate_recovery_codes_docs),
+        )
+        .api_route("/users/profiles", get_with(user_profile, user_profile_docs))
+        .api_route(
+            "/users/profiles",
+            patch_with(change_user_profile, change_user_profile_docs),
+        )
 }
@@ -0,0 +1,194 @@
+use chrono::NaiveDateTime;
+use rand::{distributions::Alphanumeric, Rng};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use shared::client::ClientInfo;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+/// How long a session lasts without being refreshed.
+pub const SESSION_DURATION_DAYS: i32 = 30;
+
+/// Session is created when a user logs in, it lives as long as its refresh token is used.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Session {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub account_id: Uuid,
+    #[serde(skip)]
+    pub user_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub last_used_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+
+/// Refresh tokens are random strings, only their hash is stored.
+pub fn generate_refresh_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .collect()
+}
+
+fn hash_refresh_token(refresh_token: &str) -> String {
+    format!("{:x}", Sha256::digest(refresh_token.as_bytes()))
+}
+
+pub async fn create_session(
+    conn: &mut PgConnection, account_id: Uuid, user_id: Uuid, refresh_token: &str,
+    client: ClientInfo,
+) -> Result<Session, sqlx::Error> {
+    sqlx::query_as!(
+        Session,
+        r#"
+        INSERT INTO accounts.sessions (
+            account_id, user_id, refresh_token_hash, user_agent, ip_address, expires_at
+        )
+        VALUES ($1, $2, $3, $4, $5, NOW() + make_interval(days => $6))
+        RETURNING id, account_id, user_id, user_agent, ip_address,
+            expires_at, last_used_at, revoked_at, created_at
+        "#,
+        account_id,
+        user_id,
+        hash_refresh_token(refresh_token),
+        client.user_agent,
+        client.ip_address,
+        SESSION_DURATION_DAYS,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Swaps the refresh token of an active session for a new one and extends the session.
+/// Returns None when no active session has the given refresh token.
+pub async fn rotate_session(
+    conn: &mut PgConnection, refresh_token: &str, new_refresh_token: &str, client: ClientInfo,
+) -> Result<Option<Session>, sqlx::Error> {
+    sqlx::query_as!(
+        Session,
+        r#"
+        UPDATE accounts.sessions
+        SET previous_refresh_token_hash = refresh_token_hash,
+            refresh_token_hash = $2,
+            user_agent = COALESCE($3, user_agent),
+            ip_address = COALESCE($4, ip_address),
+            last_used_at = NOW(),
+            expires_at = NOW() + make_interval(days => $5)
+        WHERE refresh_token_hash = $1
+        AND revoked_at IS NULL
+        AND expires_at > NOW()
+        RETURNING id, account_id, user_id, user_agent, ip_address,
+            expires_at, last_used_at, revoked_at, created_at
+        "#,
+        hash_refresh_token(refresh_token),
+        hash_refresh_token(new_refresh_token),
+        client.user_agent,
+        client.ip_address,
+        SESSION_DURATION_DAYS,
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// A refresh token that was already rotated is being used again, either the client
+/// misbehaved or the token was stolen; in doubt, the whole session is revoked.
+pub async fn revoke_session_by_reused_refresh_token(
+    conn: &mut PgConnection, refresh_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE accounts.sessions
+        SET revoked_at = NOW()
+        WHERE previous_refresh_token_hash = $1
+        AND revoked_at IS NULL
+        "#,
+        hash_refresh_token(refresh_token),
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn revoke_session(
+    conn: &mut PgConnection, user_id: Uuid, session_id: Uuid,
+) -> Result<Session, sqlx::Error> {
+    sqlx::query_as!(
+        Session,
+        r#"
+        UPDATE accounts.sessions
+        SET revoked_at = NOW()
+        WHERE id = $1
+        AND user_id = $2
+        AND revoked_at IS NULL
+        RETURNING id, account_id, user_id, user_agent, ip_address,
+            expires_at, last_used_at, revoked_at, created_at
+        "#,
+        session_id,
+        user_id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Lists the sessions of a user which are neither revoked nor expired.
+pub async fn list_active_sessions(
+    conn: &mut PgConnection, user_id: Uuid,
+) -> Result<Vec<Session>, sqlx::Error> {
+    sqlx::query_as!(
+        Session,
+        r#"
+        SELECT id, account_id, user_id, user_agent, ip_address,
+            expires_at, last_used_at, revoked_at, created_at
+        FROM accounts.sessions
+        WHERE user_id = $1
+        AND revoked_at IS NULL
+        AND expires_at > NOW()
+        ORDER BY last_used_at DESC
+        "#,
+        user_id,
+    )
+    .fetch_all(conn)
+    .await
+}
+
+pub async fn is_session_active(
+    conn: &mut PgConnection, session_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM accounts.sessions
+            WHERE id = $1
+            AND revoked_at IS NULL
+            AND expires_at > NOW()
+        ) as "active!"
+        "#,
+        session_id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Revokes every active session of a user but the given one, e.g. after a password change.
+pub async fn revoke_user_sessions(
+    conn: &mut PgConnection, user_id: Uuid, except_session_id: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE accounts.sessions
+        SET revoked_at = NOW()
+        WHERE user_id = $1
+        AND revoked_at IS NULL
+        AND ($2::uuid IS NULL OR id <> $2)
+        "#,
+        user_id,
+        except_session_id,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDateTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use shared::AppError;
+use sqlx::PgConnection;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::accounts::Account;
+use crate::cors::is_valid_origin_pattern;
+use crate::feature_flags::{registered_feature_flags, AccountFeatureFlags};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FeatureFlagSetting {
+    pub key: String,
+    pub description: String,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AccountSettings {
+    pub id: Uuid,
+    pub name: String,
+    /// Set when the account is created, it can't be changed.
+    pub subdomain: String,
+    pub origin: Option<String>,
+    pub allowed_origins: Vec<String>,
+    /// Every flag registered by the apps, with its value for the account.
+    pub feature_flags: Vec<FeatureFlagSetting>,
+    pub updated_at: NaiveDateTime,
+}
+
+impl From<Account> for AccountSettings {
+    fn from(account: Account) -> Self {
+        let feature_flags = registered_feature_flags()
+            .into_iter()
+            .map(|(key, description)| FeatureFlagSetting {
+                key: key.to_string(),
+                description: description.to_string(),
+                enabled: account.feature_flags.is_key_enabled(key),
+            })
+            .collect();
+
+        AccountSettings {
+            id: account.id,
+            name: account.name,
+            subdomain: account.subdomain,
+            origin: account.origin,
+            allowed_origins: account.allowed_origins,
+            feature_flags,
+            updated_at: account.updated_at,
+        }
+    }
+}
+
+/// Fields left out are kept, `null` clears the origin.
+/// The allowed origins are replaced, only the given feature flags are changed.
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct ChangeAccountSettingsInput {
+    #[validate(length(min = 1, max = 100))]
+    pub name: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "::serde_with::rust::double_option::deserialize"
+    )]
+    #[validate(url)]
+    pub origin: Option<Option<String>>,
+    /// `scheme://host[:port]`, the host can start with `*.` to allow its subdomains.
+    /// `*` allows any origin.
+    #[validate(length(max = 20))]
+    pub allowed_origins: Option<Vec<String>>,
+    pub feature_flags: Option<BTreeMap<String, bool>>,
+}
+
+/// Changes the settings of the account, locked by the caller with [`crate::accounts::lock_account`].
+pub async fn change_account_settings(
+    conn: &mut PgConnection, account: &Account, input: ChangeAccountSettingsInput,
+) -> Result<Account, AppError> {
+    let feature_flags = input.feature_flags.unwrap_or_default();
+    let registered = registered_feature_flags();
+    if let Some(key) = feature_flags
+        .keys()
+        .find(|key| !registered.contains_key(key.as_str()))
+    {
+        return Err(AppError::BadRequest(format!(
+            "The feature flag {key} doesn't exist"
+        )));
+    }
+
+    if let Some(origin) = input
+        .allowed_origins
+        .iter()
+        .flatten()
+        .find(|origin| !is_valid_origin_pattern(origin))
+    {
+        return Err(AppError::BadRequest(format!(
+            "The allowed origin {origin} is not valid"
+        )));
+    }
+
+    let account = sqlx::query_as!(
+        Account,
+        r#"
+        UPDATE accounts.accounts
+        SET name = COALESCE($2, name),
+            origin = $3,
+            allowed_origins = COALESCE($4, allowed_origins),
+            feature_flags = feature_flags || $5
+        WHERE id = $1
+        RETURNING id, name, subdomain, origin, allowed_origins,
+        feature_flags as "feature_flags!: sqlx::types::Json<AccountFeatureFlags>",
+        updated_at
+        "#,
+        account.id,
+        input.name,
+        input.origin.unwrap_or_else(|| account.origin.clone()),
+        input.allowed_origins.as_deref(),
+        sqlx::types::Json(AccountFeatureFlags(feature_flags)) as _,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(account)
+}
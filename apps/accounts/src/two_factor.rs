@@ -0,0 +1,450 @@
+use chrono::NaiveDateTime;
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng, RngCore};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use shared::{client::ClientInfo, AppError};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::accounts::get_account_by_id;
+use crate::login_attempts::{record_login_attempt, throttle_login_attempt};
+use crate::users::User;
+
+/// TOTP codes change every 30 seconds (RFC 6238 defaults: SHA1, 6 digits).
+const TOTP_STEP_SECONDS: i64 = 30;
+
+/// How many steps before and after the current one are accepted, for clock drift.
+const TOTP_SKEW_STEPS: i64 = 1;
+
+const RECOVERY_CODES_COUNT: usize = 10;
+
+/// How long a login challenge can be completed, and how many codes can be tried.
+pub const LOGIN_CHALLENGE_DURATION_MINUTES: i32 = 5;
+const LOGIN_CHALLENGE_MAX_ATTEMPTS: i32 = 5;
+
+/// Returned by the password login of a user with two-factor, instead of an access token.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct LoginChallenge {
+    /// Sent back with a TOTP (or recovery) code to get the access token.
+    pub challenge_token: String,
+    pub expires_at: NaiveDateTime,
+}
+
+/// Shown once when enrolling, to be added to an authenticator app.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct TotpEnrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+pub struct RecoveryCodes {
+    pub recovery_codes: Vec<String>,
+}
+
+/// A challenge which can still be completed.
+pub struct ActiveLoginChallenge {
+    pub id: Uuid,
+    pub account_id: Uuid,
+    pub user_id: Uuid,
+}
+
+pub struct UserTotp {
+    pub user_id: Uuid,
+    pub secret: String,
+    pub enabled_at: Option<NaiveDateTime>,
+}
+
+fn hash_secret_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+fn generate_totp_secret() -> String {
+    let mut secret = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut secret);
+    BASE32_NOPAD.encode(&secret)
+}
+
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC takes keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 1_000_000
+}
+
+/// Checks a code against the steps around `unix_time`, returning the matching step.
+pub fn verify_totp(secret: &str, code: &str, unix_time: i64) -> Option<i64> {
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    let code: u32 = code.trim().parse().ok()?;
+    let current = unix_time / TOTP_STEP_SECONDS;
+    (current - TOTP_SKEW_STEPS..=current + TOTP_SKEW_STEPS)
+        .find(|step| *step >= 0 && hotp(&secret, *step as u64) == code)
+}
+
+/// The URI encoded in the QR code scanned by authenticator apps.
+pub fn otpauth_uri(issuer: &str, email: &str, secret: &str) -> String {
+    let encode = |value: &str| -> String {
+        value
+            .bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{b:02X}"),
+            })
+            .collect()
+    };
+    format!(
+        "otpauth://totp/{}:{}?secret={secret}&issuer={}&algorithm=SHA1&digits=6&period={TOTP_STEP_SECONDS}",
+        encode(issuer),
+        encode(email),
+        encode(issuer),
+    )
+}
+
+pub async fn get_user_totp(
+    conn: &mut PgConnection, user_id: Uuid,
+) -> Result<Option<UserTotp>, sqlx::Error> {
+    sqlx::query_as!(
+        UserTotp,
+        r#"
+        SELECT user_id, secret, enabled_at
+        FROM accounts.user_totps
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// Generates a new secret for a user without two-factor enabled,
+/// returns None when two-factor is already enabled.
+pub async fn start_totp_enrollment(
+    conn: &mut PgConnection, user_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO accounts.user_totps (user_id, secret)
+        VALUES ($1, $2)
+        ON CONFLICT (user_id) DO UPDATE
+        SET secret = EXCLUDED.secret, last_used_step = NULL
+        WHERE user_totps.enabled_at IS NULL
+        RETURNING secret
+        "#,
+        user_id,
+        generate_totp_secret(),
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+pub async fn enable_totp(conn: &mut PgConnection, user_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE accounts.user_totps
+        SET enabled_at = NOW()
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Turns two-factor off, the secret and the recovery codes are deleted.
+pub async fn disable_totp(conn: &mut PgConnection, user_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM accounts.user_totps
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM accounts.recovery_codes
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Records the step of a verified code, false if that step (or a later one) was already used.
+async fn use_totp_step(
+    conn: &mut PgConnection, user_id: Uuid, step: i64,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE accounts.user_totps
+        SET last_used_step = $2
+        WHERE user_id = $1
+        AND (last_used_step IS NULL OR last_used_step < $2)
+        "#,
+        user_id,
+        step,
+    )
+    .execute(conn)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Replaces the recovery codes of a user, the codes are only returned here.
+pub async fn replace_recovery_codes(
+    conn: &mut PgConnection, user_id: Uuid,
+) -> Result<RecoveryCodes, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM accounts.recovery_codes
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODES_COUNT)
+        .map(|_| {
+            let code: String = rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(10)
+                .collect::<String>()
+                .to_lowercase();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect();
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_secret_token(code))
+        .collect();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO accounts.recovery_codes (user_id, code_hash)
+        SELECT $1, UNNEST($2::text[])
+        "#,
+        user_id,
+        &hashes,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(RecoveryCodes { recovery_codes })
+}
+
+async fn use_recovery_code(
+    conn: &mut PgConnection, user_id: Uuid, code: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE accounts.recovery_codes
+        SET used_at = NOW()
+        WHERE user_id = $1
+        AND code_hash = $2
+        AND used_at IS NULL
+        "#,
+        user_id,
+        hash_secret_token(&code.trim().to_lowercase()),
+    )
+    .execute(conn)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+/// Checks a TOTP code of the user, a recovery code is accepted as well
+/// (and can't be used again). Returns false for users without two-factor.
+pub async fn verify_second_factor(
+    conn: &mut PgConnection, user_id: Uuid, code: &str,
+) -> Result<bool, sqlx::Error> {
+    let totp = match get_user_totp(conn, user_id).await? {
+        Some(totp) => totp,
+        None => return Ok(false),
+    };
+
+    let now = chrono::Utc::now().timestamp();
+    if let Some(step) = verify_totp(&totp.secret, code, now) {
+        return use_totp_step(conn, user_id, step).await;
+    }
+
+    if totp.enabled_at.is_some() {
+        return use_recovery_code(conn, user_id, code).await;
+    }
+
+    Ok(false)
+}
+
+pub async fn create_login_challenge(
+    conn: &mut PgConnection, account_id: Uuid, user_id: Uuid,
+) -> Result<LoginChallenge, sqlx::Error> {
+    let challenge_token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(64)
+        .collect();
+
+    let expires_at = sqlx::query_scalar!(
+        r#"
+        INSERT INTO accounts.login_challenges (account_id, user_id, token_hash, expires_at)
+        VALUES ($1, $2, $3, NOW() + make_interval(mins => $4))
+        RETURNING expires_at
+        "#,
+        account_id,
+        user_id,
+        hash_secret_token(&challenge_token),
+        LOGIN_CHALLENGE_DURATION_MINUTES,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(LoginChallenge {
+        challenge_token,
+        expires_at,
+    })
+}
+
+/// Counts an attempt at completing an active challenge, returning its user.
+/// Returns None when the challenge is unknown, expired, completed or out of attempts.
+pub async fn attempt_login_challenge(
+    conn: &mut PgConnection, challenge_token: &str,
+) -> Result<Option<ActiveLoginChallenge>, sqlx::Error> {
+    sqlx::query_as!(
+        ActiveLoginChallenge,
+        r#"
+        UPDATE accounts.login_challenges
+        SET attempts = attempts + 1
+        WHERE token_hash = $1
+        AND completed_at IS NULL
+        AND expires_at > NOW()
+        AND attempts < $2
+        RETURNING id, account_id, user_id
+        "#,
+        hash_secret_token(challenge_token),
+        LOGIN_CHALLENGE_MAX_ATTEMPTS,
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+pub async fn complete_login_challenge(
+    conn: &mut PgConnection, challenge_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE accounts.login_challenges
+        SET completed_at = NOW()
+        WHERE id = $1
+        "#,
+        challenge_id,
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+pub async fn is_totp_enabled(conn: &mut PgConnection, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    let totp = get_user_totp(conn, user_id).await?;
+    Ok(totp.map_or(false, |totp| totp.enabled_at.is_some()))
+}
+
+/// Starts (or restarts) the enrollment of the user, two-factor is only enabled
+/// once a first code is confirmed with [`confirm_totp_enrollment`].
+pub async fn enroll_totp(
+    pool: &sqlx::PgPool, conn: &mut PgConnection, user: &User,
+) -> Result<TotpEnrollment, AppError> {
+    let account = get_account_by_id(pool, user.account_id).await?;
+    let secret = start_totp_enrollment(conn, user.id)
+        .await?
+        .ok_or(AppError::BadRequest(
+            "Two-factor authentication is already enabled".into(),
+        ))?;
+
+    Ok(TotpEnrollment {
+        otpauth_uri: otpauth_uri(&format!("Consub {}", account.name), &user.email, &secret),
+        secret,
+    })
+}
+
+/// Enables two-factor with a first code from the authenticator app,
+/// returning the recovery codes.
+pub async fn confirm_totp_enrollment(
+    conn: &mut PgConnection, user: &User, code: &str,
+) -> Result<RecoveryCodes, AppError> {
+    match get_user_totp(&mut *conn, user.id).await? {
+        Some(totp) if totp.enabled_at.is_none() => {}
+        Some(_) => {
+            return Err(AppError::BadRequest(
+                "Two-factor authentication is already enabled".into(),
+            ))
+        }
+        None => {
+            return Err(AppError::BadRequest(
+                "Start the two-factor enrollment first".into(),
+            ))
+        }
+    }
+
+    if !verify_second_factor(&mut *conn, user.id, code).await? {
+        return Err(AppError::BadRequest("Invalid code".into()));
+    }
+
+    enable_totp(&mut *conn, user.id).await?;
+    let recovery_codes = replace_recovery_codes(conn, user.id).await?;
+    Ok(recovery_codes)
+}
+
+/// Checks a code of a user with two-factor enabled, before changing the two-factor settings.
+/// Wrong codes are failed login attempts of the user, so guessing them is throttled like passwords.
+async fn require_second_factor(
+    pool: &sqlx::PgPool, conn: &mut PgConnection, user: &User, code: &str, client: &ClientInfo,
+) -> Result<(), AppError> {
+    if !is_totp_enabled(conn, user.id).await? {
+        return Err(AppError::BadRequest(
+            "Two-factor authentication is not enabled".into(),
+        ));
+    }
+
+    let email = user.email.to_lowercase();
+    throttle_login_attempt(conn, user.account_id, &email, client).await?;
+    if !verify_second_factor(conn, user.id, code).await? {
+        // Recorded apart from the transaction of the change, which is rolled back.
+        let mut db = pool.acquire().await?;
+        record_login_attempt(&mut db, user.account_id, &email, client, false).await?;
+        return Err(AppError::BadRequest("Invalid code".into()));
+    }
+    Ok(())
+}
+
+/// Disables two-factor after checking a code, within the transaction of the caller.
+pub async fn turn_off_totp(
+    pool: &sqlx::PgPool, conn: &mut PgConnection, user: &User, code: &str, client: &ClientInfo,
+) -> Result<(), AppError> {
+    require_second_factor(pool, conn, user, code, client).await?;
+    disable_totp(conn, user.id).await?;
+    Ok(())
+}
+
+/// Replaces the recovery codes after checking a code, within the transaction of the caller.
+pub async fn regenerate_recovery_codes(
+    pool: &sqlx::PgPool, conn: &mut PgConnection, user: &User, code: &str, client: &ClientInfo,
+) -> Result<RecoveryCodes, AppError> {
+    require_second_factor(pool, conn, user, code, client).await?;
+    let recovery_codes = replace_recovery_codes(conn, user.id).await?;
+    Ok(recovery_codes)
+}
@@ -5,6 +5,9 @@ use serde::{Deserialize, Serialize};
 use sqlx::PgConnection;
 use uuid::Uuid;
 
+use shared::{audit::Actor, client::ClientInfo, AppError};
+
+use crate::authorization::Role;
 use crate::passwords::hash_password;
 
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, OperationIo)]
@@ -13,13 +16,26 @@ pub struct User {
     #[serde(skip)]
     pub account_id: Uuid,
     pub email: String,
+    pub role: Role,
     pub updated_at: NaiveDateTime,
 }
 
+impl User {
+    /// The user as the author of the changes recorded in the audit log.
+    pub fn actor(&self, client: ClientInfo) -> Actor {
+        Actor {
+            account_id: self.account_id,
+            user_id: Some(self.id),
+            client,
+        }
+    }
+}
+
 pub struct CreateUserWithPasswordInput {
     pub account_id: Uuid,
     pub email: String,
     pub password: String,
+    pub role: Role,
 }
 
 pub async fn create_user_with_password(
@@ -28,12 +44,13 @@ pub async fn create_user_with_password(
     let user = sqlx::query_as!(
         User,
         r#"
-        INSERT INTO accounts.users (account_id, email)
-        VALUES ($1, $2)
-        RETURNING id, account_id, email, updated_at
+        INSERT INTO accounts.users (account_id, email, role)
+        VALUES ($1, $2, $3)
+        RETURNING id, account_id, email, role as "role: Role", updated_at
         "#,
         input.account_id,
         input.email.to_lowercase(),
+        input.role as Role,
     )
     .fetch_one(&mut *conn)
     .await?;
@@ -44,7 +61,7 @@ pub async fn create_user_with_password(
         VALUES ($1, $2)
         "#,
         user.id,
-        hash_password(input.password)?,
+        hash_password(input.password).await?,
     )
     .execute(&mut *conn)
     .await?;
@@ -56,12 +73,178 @@ pub async fn get_user_by_id(conn: &sqlx::PgPool, id: Uuid) -> Result<User, sqlx:
     sqlx::query_as!(
         User,
         r#"
-        SELECT id, account_id, email, updated_at
+        SELECT id, account_id, email, role as "role: Role", updated_at
         FROM accounts.users
         WHERE id = $1
+        AND removed_at IS NULL
         "#,
         id,
     )
     .fetch_one(conn)
     .await
 }
+
+pub async fn get_user_by_email(
+    conn: &sqlx::PgPool, account_id: Uuid, email: &str,
+) -> Result<User, sqlx::Error> {
+    sqlx::query_as!(
+        User,
+        r#"
+        SELECT id, account_id, email, role as "role: Role", updated_at
+        FROM accounts.users
+        WHERE account_id = $1 AND email = $2
+        AND removed_at IS NULL
+        "#,
+        account_id,
+        email.to_lowercase(),
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Lists the users of the account, removed users are left out.
+pub async fn list_users(
+    conn: &mut PgConnection, account_id: Uuid,
+) -> Result<Vec<User>, sqlx::Error> {
+    sqlx::query_as!(
+        User,
+        r#"
+        SELECT id, account_id, email, role as "role: Role", updated_at
+        FROM accounts.users
+        WHERE account_id = $1
+        AND removed_at IS NULL
+        ORDER BY email
+        "#,
+        account_id,
+    )
+    .fetch_all(conn)
+    .await
+}
+
+/// Adds a user to the account, a removed user with the same email is brought back.
+/// Returns None when the email already belongs to a user of the account.
+pub async fn create_member(
+    conn: &mut PgConnection, account_id: Uuid, email: &str, role: Role,
+) -> Result<Option<User>, sqlx::Error> {
+    sqlx::query_as!(
+        User,
+        r#"
+        INSERT INTO accounts.users (account_id, email, role)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (account_id, email) DO UPDATE
+        SET role = EXCLUDED.role, removed_at = NULL
+        WHERE users.removed_at IS NOT NULL
+        RETURNING id, account_id, email, role as "role: Role", updated_at
+        "#,
+        account_id,
+        email.to_lowercase(),
+        role as Role,
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// Locks the owners of the account until the transaction ends, returning their ids, so
+/// concurrent removals check for another owner one after the other.
+pub async fn lock_owners(
+    conn: &mut PgConnection, account_id: Uuid,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT id FROM accounts.users
+        WHERE account_id = $1
+        AND role = 'owner'
+        AND removed_at IS NULL
+        ORDER BY id
+        FOR UPDATE
+        "#,
+        account_id,
+    )
+    .fetch_all(conn)
+    .await
+}
+
+/// Removes a user from the account: the user can't sign in anymore but what
+/// they authored is kept. The password, reset tokens and sessions go away.
+pub async fn remove_user(
+    conn: &mut PgConnection, account_id: Uuid, user_id: Uuid,
+) -> Result<User, sqlx::Error> {
+    let user = sqlx::query_as!(
+        User,
+        r#"
+        UPDATE accounts.users
+        SET removed_at = NOW()
+        WHERE id = $2
+        AND account_id = $1
+        AND removed_at IS NULL
+        RETURNING id, account_id, email, role as "role: Role", updated_at
+        "#,
+        account_id,
+        user_id,
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM accounts.passwords
+        WHERE user_id = $1
+        "#,
+        user.id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE accounts.password_resets
+        SET expires_at = NOW()
+        WHERE user_id = $1
+        AND used_at IS NULL
+        AND expires_at > NOW()
+        "#,
+        user.id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    crate::sessions::revoke_user_sessions(&mut *conn, user.id, None).await?;
+
+    Ok(user)
+}
+
+/// Checks who can remove whom before removing a user from the account,
+/// within the transaction of the caller.
+pub async fn remove_user_from_account(
+    conn: &mut PgConnection, actor: &User, user_id: Uuid,
+) -> Result<User, AppError> {
+    if actor.id == user_id {
+        return Err(AppError::BadRequest(
+            "Users can't remove themselves from the account".into(),
+        ));
+    }
+
+    let owners = lock_owners(&mut *conn, actor.account_id).await?;
+    let user = remove_user(&mut *conn, actor.account_id, user_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("User not found".into()),
+            e => e.into(),
+        })?;
+
+    if user.role == Role::Owner {
+        // Read from the locked rows, the actor may have been removed meanwhile.
+        if !owners.contains(&actor.id) {
+            return Err(AppError::Forbidden(
+                "Only owners can remove another owner".into(),
+            ));
+        }
+        if owners.iter().all(|owner_id| *owner_id == user.id) {
+            return Err(AppError::BadRequest(
+                "The account must keep at least one owner".into(),
+            ));
+        }
+    }
+
+    Ok(user)
+}
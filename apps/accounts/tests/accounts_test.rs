@@ -1,5 +1,8 @@
 use accounts::routes;
-use shared::testing::test_app;
+use chrono::{DateTime, Duration, Utc};
+use jwt_compact::{alg::Ed25519, prelude::*, Algorithm};
+use shared::rate_limit::{MemoryBackend, RateLimiter};
+use shared::testing::{test_app, test_app_with_dns, test_app_with_mailbox};
 
 #[sqlx::test(migrations = "../../migrations")]
 async fn test_create_a_valid_account(pool: sqlx::PgPool) {
@@ -20,6 +23,21 @@ async fn test_create_a_valid_account(pool: sqlx::PgPool) {
         .unwrap();
 
     assert_eq!(response.status(), 201);
+    let account = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(account["subdomain"], "fancy");
+
+    // The account comes with the api key of its frontend.
+    let response = client
+        .post(format!("{address}/users/access-tokens/passwords"))
+        .header("X-API-KEY", account["api_key"]["key"].as_str().unwrap())
+        .json(&serde_json::json!({
+            "email": "fancy@website.com",
+            "password": "consub123",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
 }
 
 #[sqlx::test(migrations = "../../migrations", fixtures("account"))]
@@ -102,3 +120,1686 @@ async fn test_login_with_wrong_password(pool: sqlx::PgPool) {
 
     assert_eq!(response.status(), 401);
 }
+
+const ACCOUNT_KEY_ID: uuid::Uuid = uuid::uuid!("e6af50e6-0ef3-4908-80c0-a83622d96d03");
+
+/// Signs the fixture user claims with the fixture account key,
+/// so the time claims can be set to anything.
+async fn sign_token(
+    pool: &sqlx::PgPool, not_before: DateTime<Utc>, expiration: Option<DateTime<Utc>>,
+) -> String {
+    let keypair: Vec<u8> =
+        sqlx::query_scalar("SELECT keypair FROM accounts.account_keys WHERE id = $1")
+            .bind(ACCOUNT_KEY_ID)
+            .fetch_one(pool)
+            .await
+            .unwrap();
+    let key = <Ed25519 as Algorithm>::SigningKey::from_slice(&keypair).unwrap();
+
+    let mut claims = Claims::new(serde_json::json!({
+        "user_id": "ad38ffbe-dabe-43a1-b63a-4028e23090eb",
+        "account_id": "263b6188-aac6-45e9-9a2c-4728fdfd7ea1",
+        "role": "owner",
+    }))
+    .set_not_before(not_before);
+    claims.expiration = expiration;
+
+    let header = Header::default().with_key_id(ACCOUNT_KEY_ID.to_string());
+    Ed25519::with_specific_name()
+        .token(header, &claims, &key)
+        .unwrap()
+}
+
+async fn profile_status(address: &str, token: &str) -> u16 {
+    reqwest::Client::new()
+        .get(format!("{address}/users/profiles"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_valid_token_is_accepted(pool: sqlx::PgPool) {
+    let token = sign_token(&pool, Utc::now(), Some(Utc::now() + Duration::hours(1))).await;
+
+    let address = test_app(pool, routes()).await;
+
+    assert_eq!(profile_status(&address, &token).await, 200);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_expired_token_is_rejected(pool: sqlx::PgPool) {
+    let token = sign_token(
+        &pool,
+        Utc::now() - Duration::hours(2),
+        Some(Utc::now() - Duration::hours(1)),
+    )
+    .await;
+
+    let address = test_app(pool, routes()).await;
+
+    assert_eq!(profile_status(&address, &token).await, 401);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_token_without_expiration_is_rejected(pool: sqlx::PgPool) {
+    let token = sign_token(&pool, Utc::now(), None).await;
+
+    let address = test_app(pool, routes()).await;
+
+    assert_eq!(profile_status(&address, &token).await, 401);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_immature_token_is_rejected(pool: sqlx::PgPool) {
+    let token = sign_token(
+        &pool,
+        Utc::now() + Duration::hours(1),
+        Some(Utc::now() + Duration::hours(2)),
+    )
+    .await;
+
+    let address = test_app(pool, routes()).await;
+
+    assert_eq!(profile_status(&address, &token).await, 401);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_token_of_expired_account_key_is_rejected(pool: sqlx::PgPool) {
+    let token = sign_token(&pool, Utc::now(), Some(Utc::now() + Duration::hours(1))).await;
+
+    sqlx::query(
+        "UPDATE accounts.account_keys SET expires_at = NOW() - INTERVAL '1 day' WHERE id = $1",
+    )
+    .bind(ACCOUNT_KEY_ID)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let address = test_app(pool, routes()).await;
+
+    assert_eq!(profile_status(&address, &token).await, 401);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_token_of_revoked_account_key_is_rejected(pool: sqlx::PgPool) {
+    let token = sign_token(&pool, Utc::now(), Some(Utc::now() + Duration::hours(1))).await;
+
+    sqlx::query("UPDATE accounts.account_keys SET revoked_at = NOW() WHERE id = $1")
+        .bind(ACCOUNT_KEY_ID)
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let address = test_app(pool, routes()).await;
+
+    assert_eq!(profile_status(&address, &token).await, 401);
+}
+
+async fn login(address: &str) -> serde_json::Value {
+    reqwest::Client::new()
+        .post(format!("{address}/users/access-tokens/passwords"))
+        .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+        .json(&serde_json::json!({
+            "email": "thiagovarela@consub.io",
+            "password": "123456",
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn refresh(address: &str, refresh_token: &serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{address}/users/access-tokens/refresh"))
+        .json(&serde_json::json!({ "refresh_token": refresh_token }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_refresh_token_is_rotated(pool: sqlx::PgPool) {
+    let address = test_app(pool, routes()).await;
+
+    let access_token = login(&address).await;
+
+    let response = refresh(&address, &access_token["refresh_token"]).await;
+    assert_eq!(response.status(), 201);
+
+    let refreshed = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(refreshed["session_id"], access_token["session_id"]);
+    assert_ne!(refreshed["refresh_token"], access_token["refresh_token"]);
+
+    // Reusing a rotated refresh token revokes the whole session.
+    let response = refresh(&address, &access_token["refresh_token"]).await;
+    assert_eq!(response.status(), 401);
+
+    let response = refresh(&address, &refreshed["refresh_token"]).await;
+    assert_eq!(response.status(), 401);
+
+    let token = refreshed["token"].as_str().unwrap();
+    assert_eq!(profile_status(&address, token).await, 401);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_logout_revokes_the_session(pool: sqlx::PgPool) {
+    let address = test_app(pool, routes()).await;
+
+    let access_token = login(&address).await;
+    let token = access_token["token"].as_str().unwrap();
+
+    assert_eq!(profile_status(&address, token).await, 200);
+
+    let response = reqwest::Client::new()
+        .post(format!("{address}/users/logout"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    assert_eq!(profile_status(&address, token).await, 401);
+    let response = refresh(&address, &access_token["refresh_token"]).await;
+    assert_eq!(response.status(), 401);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_list_and_revoke_sessions(pool: sqlx::PgPool) {
+    let address = test_app(pool, routes()).await;
+
+    let laptop = login(&address).await;
+    let phone = login(&address).await;
+    let token = laptop["token"].as_str().unwrap();
+
+    let client = reqwest::Client::new();
+
+    let sessions = client
+        .get(format!("{address}/users/sessions"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+
+    assert_eq!(sessions.len(), 2);
+    let current: Vec<_> = sessions.iter().filter(|s| s["current"] == true).collect();
+    assert_eq!(current.len(), 1);
+    assert_eq!(current[0]["id"], laptop["session_id"]);
+
+    let response = client
+        .delete(format!(
+            "{address}/users/sessions/{}",
+            phone["session_id"].as_str().unwrap()
+        ))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    let phone_token = phone["token"].as_str().unwrap();
+    assert_eq!(profile_status(&address, phone_token).await, 401);
+    assert_eq!(profile_status(&address, token).await, 200);
+}
+
+fn token_key_id(token: &str) -> String {
+    UntrustedToken::new(token)
+        .unwrap()
+        .header()
+        .key_id
+        .clone()
+        .unwrap()
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_rotate_account_keys(pool: sqlx::PgPool) {
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let old_token = login(&address).await["token"].as_str().unwrap().to_string();
+    assert_eq!(token_key_id(&old_token), ACCOUNT_KEY_ID.to_string());
+
+    // The only key of the account can't be revoked.
+    let response = client
+        .delete(format!("{address}/account-keys/{ACCOUNT_KEY_ID}"))
+        .header("Authorization", format!("Bearer {old_token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let response = client
+        .post(format!("{address}/account-keys"))
+        .header("Authorization", format!("Bearer {old_token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let new_key = response.json::<serde_json::Value>().await.unwrap();
+
+    // The newest key signs the new tokens, the old one still verifies.
+    let new_token = login(&address).await["token"].as_str().unwrap().to_string();
+    assert_eq!(token_key_id(&new_token), new_key["id"].as_str().unwrap());
+
+    let response = client
+        .post(format!("{address}/account-keys/{ACCOUNT_KEY_ID}/expire"))
+        .header("Authorization", format!("Bearer {new_token}"))
+        .json(&serde_json::json!({ "overlap_minutes": 60 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let expiring_key = response.json::<serde_json::Value>().await.unwrap();
+    assert!(expiring_key["expires_at"].is_string());
+
+    assert_eq!(profile_status(&address, &old_token).await, 200);
+
+    let response = client
+        .delete(format!("{address}/account-keys/{ACCOUNT_KEY_ID}"))
+        .header("Authorization", format!("Bearer {new_token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    assert_eq!(profile_status(&address, &old_token).await, 401);
+    assert_eq!(profile_status(&address, &new_token).await, 200);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_concurrent_revocations_keep_a_valid_account_key(pool: sqlx::PgPool) {
+    let address = test_app(pool.clone(), routes()).await;
+
+    let client = reqwest::Client::new();
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+
+    let response = client
+        .post(format!("{address}/account-keys"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let new_key = response.json::<serde_json::Value>().await.unwrap();
+
+    let revocations = [
+        ACCOUNT_KEY_ID.to_string(),
+        new_key["id"].as_str().unwrap().to_string(),
+    ]
+    .into_iter()
+    .map(|account_key_id| {
+        let client = client.clone();
+        let address = address.clone();
+        let token = token.clone();
+        tokio::spawn(async move {
+            client
+                .delete(format!("{address}/account-keys/{account_key_id}"))
+                .header("Authorization", format!("Bearer {token}"))
+                .send()
+                .await
+                .unwrap()
+                .status()
+                .as_u16()
+        })
+    })
+    .collect::<Vec<_>>();
+    let mut statuses = Vec::new();
+    for revocation in revocations {
+        statuses.push(revocation.await.unwrap());
+    }
+    statuses.sort();
+    assert_eq!(statuses, vec![204, 400]);
+
+    let valid: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM accounts.account_keys WHERE revoked_at IS NULL")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(valid, 1);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_create_use_and_revoke_api_keys(pool: sqlx::PgPool) {
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+
+    let response = client
+        .post(format!("{address}/api-keys"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "name": "Website", "scopes": ["public:read"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let api_key = response.json::<serde_json::Value>().await.unwrap();
+    let key = api_key["key"].as_str().unwrap().to_string();
+    assert!(key.starts_with(api_key["prefix"].as_str().unwrap()));
+    assert!(api_key["last_used_at"].is_null());
+
+    let login_with_key = |key: String| {
+        client
+            .post(format!("{address}/users/access-tokens/passwords"))
+            .header("X-API-KEY", key)
+            .json(&serde_json::json!({
+                "email": "thiagovarela@consub.io",
+                "password": "123456",
+            }))
+            .send()
+    };
+    assert_eq!(login_with_key(key.clone()).await.unwrap().status(), 201);
+
+    let response = client
+        .get(format!("{address}/api-keys"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    let keys = response.json::<Vec<serde_json::Value>>().await.unwrap();
+    let listed = keys.iter().find(|k| k["id"] == api_key["id"]).unwrap();
+    assert!(listed["key"].is_null());
+    assert!(listed["last_used_at"].is_string());
+
+    let response = client
+        .delete(format!(
+            "{address}/api-keys/{}",
+            api_key["id"].as_str().unwrap()
+        ))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    // Without a valid key the account can't be found from the test server's host.
+    assert_eq!(login_with_key(key.clone()).await.unwrap().status(), 400);
+
+    // Only keys with the admin scope call the admin api, as the user who created them.
+    let list_with_key = |key: String| {
+        client
+            .get(format!("{address}/api-keys"))
+            .header("X-API-KEY", key)
+            .send()
+    };
+    assert_eq!(list_with_key(key).await.unwrap().status(), 401);
+
+    let response = client
+        .post(format!("{address}/api-keys"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "name": "Website", "scopes": ["public:read"] }))
+        .send()
+        .await
+        .unwrap();
+    let public_key = response.json::<serde_json::Value>().await.unwrap()["key"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(list_with_key(public_key).await.unwrap().status(), 403);
+
+    let response = client
+        .post(format!("{address}/api-keys"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "name": "Deploys", "scopes": ["admin"] }))
+        .send()
+        .await
+        .unwrap();
+    let admin_key = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(admin_key["user_id"], "ad38ffbe-dabe-43a1-b63a-4028e23090eb");
+    let admin_key = admin_key["key"].as_str().unwrap().to_string();
+    assert_eq!(list_with_key(admin_key).await.unwrap().status(), 200);
+}
+
+async fn login_with_password(address: &str, password: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{address}/users/access-tokens/passwords"))
+        .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+        .json(&serde_json::json!({
+            "email": "thiagovarela@consub.io",
+            "password": password,
+        }))
+        .send()
+        .await
+        .unwrap()
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_change_password(pool: sqlx::PgPool) {
+    let address = test_app(pool.clone(), routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let other_session = login(&address).await["token"].as_str().unwrap().to_string();
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+
+    let response = client
+        .post(format!("{address}/users/passwords"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({
+            "current_password": "wrong-password",
+            "new_password": "consub-new-password",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let response = client
+        .post(format!("{address}/users/passwords"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({
+            "current_password": "123456",
+            "new_password": "consub-new-password",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    // The session doing the change is kept, the others are revoked.
+    assert_eq!(profile_status(&address, &token).await, 200);
+    assert_eq!(profile_status(&address, &other_session).await, 401);
+
+    let response = login_with_password(&address, "consub-new-password").await;
+    assert_eq!(response.status(), 201);
+
+    // The attempt with the wrong password was rolled back, the hash is left out.
+    let events: Vec<(String, Option<serde_json::Value>)> = sqlx::query_as(
+        "SELECT action, after FROM audit.events WHERE entity_type = 'accounts.password'",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(events, vec![("changed".to_string(), None)]);
+}
+
+async fn wait_for_mails(mailbox: &shared::mail::MemoryTransport) -> Vec<shared::mail::Mail> {
+    for _ in 0..50 {
+        let mails = mailbox.sent();
+        if !mails.is_empty() {
+            return mails;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    mailbox.sent()
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_reset_forgotten_password(pool: sqlx::PgPool) {
+    let (address, mailbox) = test_app_with_mailbox(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+
+    let forgot = |email: &'static str| {
+        client
+            .post(format!("{address}/users/passwords/forgot"))
+            .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+            .json(&serde_json::json!({ "email": email }))
+            .send()
+    };
+
+    // Unknown emails get the same response, without an email being sent.
+    assert_eq!(forgot("nobody@consub.io").await.unwrap().status(), 202);
+
+    assert_eq!(
+        forgot("thiagovarela@consub.io").await.unwrap().status(),
+        202
+    );
+    // The email is sent after the response.
+    let mails = wait_for_mails(&mailbox).await;
+    assert_eq!(mails.len(), 1);
+    assert_eq!(mails[0].to, "thiagovarela@consub.io");
+    let reset_token = mails[0]
+        .body
+        .split_whitespace()
+        .find(|word| word.len() == 64)
+        .unwrap()
+        .to_string();
+
+    let reset = |reset_token: String| {
+        client
+            .post(format!("{address}/users/passwords/reset"))
+            .json(&serde_json::json!({
+                "token": reset_token,
+                "new_password": "consub-new-password",
+            }))
+            .send()
+    };
+    assert_eq!(reset(reset_token.clone()).await.unwrap().status(), 204);
+
+    // Tokens are single use and the sessions of the user are revoked.
+    assert_eq!(reset(reset_token).await.unwrap().status(), 400);
+    assert_eq!(profile_status(&address, &token).await, 401);
+
+    let response = login_with_password(&address, "consub-new-password").await;
+    assert_eq!(response.status(), 201);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_invite_accept_and_remove_a_user(pool: sqlx::PgPool) {
+    let (address, mailbox) = test_app_with_mailbox(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+
+    let invite = |email: &'static str| {
+        client
+            .post(format!("{address}/invitations"))
+            .header("Authorization", format!("Bearer {token}"))
+            .json(&serde_json::json!({ "email": email, "role": "editor" }))
+            .send()
+    };
+
+    // Users of the account can't be invited again.
+    assert_eq!(
+        invite("thiagovarela@consub.io").await.unwrap().status(),
+        400
+    );
+
+    let response = invite("editor@consub.io").await.unwrap();
+    assert_eq!(response.status(), 201);
+    let invitation = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(invitation["role"], "editor");
+
+    let response = client
+        .get(format!("{address}/invitations"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    let invitations = response.json::<Vec<serde_json::Value>>().await.unwrap();
+    assert_eq!(invitations.len(), 1);
+
+    let mails = mailbox.sent();
+    assert_eq!(mails.len(), 1);
+    assert_eq!(mails[0].to, "editor@consub.io");
+    let invitation_token = mails[0]
+        .body
+        .split_whitespace()
+        .find(|word| word.len() == 64)
+        .unwrap()
+        .to_string();
+
+    let accept = |invitation_token: String| {
+        client
+            .post(format!("{address}/invitations/accept"))
+            .json(&serde_json::json!({
+                "token": invitation_token,
+                "password": "editor-password",
+            }))
+            .send()
+    };
+    let response = accept(invitation_token.clone()).await.unwrap();
+    assert_eq!(response.status(), 201);
+    let editor = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(editor["role"], "editor");
+    assert_eq!(accept(invitation_token).await.unwrap().status(), 400);
+
+    let response = client
+        .post(format!("{address}/users/access-tokens/passwords"))
+        .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+        .json(&serde_json::json!({
+            "email": "editor@consub.io",
+            "password": "editor-password",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let editor_token = response.json::<serde_json::Value>().await.unwrap()["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(profile_status(&address, &editor_token).await, 200);
+
+    let remove = |user_id: String| {
+        client
+            .delete(format!("{address}/users/{user_id}"))
+            .header("Authorization", format!("Bearer {token}"))
+            .send()
+    };
+    let owner_id = "ad38ffbe-dabe-43a1-b63a-4028e23090eb".to_string();
+    assert_eq!(remove(owner_id).await.unwrap().status(), 400);
+
+    let editor_id = editor["id"].as_str().unwrap().to_string();
+    assert_eq!(remove(editor_id).await.unwrap().status(), 204);
+    assert_eq!(profile_status(&address, &editor_token).await, 401);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_owners_removing_each_other_keep_an_owner(pool: sqlx::PgPool) {
+    let owner_id = "ad38ffbe-dabe-43a1-b63a-4028e23090eb";
+    let other_owner_id: String = sqlx::query_scalar(
+        "INSERT INTO accounts.users (account_id, email, role) VALUES ('263b6188-aac6-45e9-9a2c-4728fdfd7ea1', 'owner@consub.io', 'owner') RETURNING id::text",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "INSERT INTO accounts.passwords (user_id, hash_password) SELECT $1::uuid, hash_password FROM accounts.passwords WHERE user_id = $2::uuid",
+    )
+    .bind(&other_owner_id)
+    .bind(owner_id)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let address = test_app(pool.clone(), routes()).await;
+
+    let client = reqwest::Client::new();
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+    let response = client
+        .post(format!("{address}/users/access-tokens/passwords"))
+        .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+        .json(&serde_json::json!({
+            "email": "owner@consub.io",
+            "password": "123456",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let other_token = response.json::<serde_json::Value>().await.unwrap()["token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let removals = [(token, other_owner_id), (other_token, owner_id.to_string())]
+        .into_iter()
+        .map(|(token, user_id)| {
+            let client = client.clone();
+            let address = address.clone();
+            tokio::spawn(async move {
+                client
+                    .delete(format!("{address}/users/{user_id}"))
+                    .header("Authorization", format!("Bearer {token}"))
+                    .send()
+                    .await
+                    .unwrap()
+                    .status()
+                    .as_u16()
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut statuses = Vec::new();
+    for removal in removals {
+        statuses.push(removal.await.unwrap());
+    }
+    // The second removal is refused, or its owner was removed before it was authorized.
+    assert_eq!(statuses.iter().filter(|status| **status == 204).count(), 1);
+
+    let owners: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM accounts.users WHERE role = 'owner' AND removed_at IS NULL",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(owners, 1);
+}
+
+#[sqlx::test(
+    migrations = "../../migrations",
+    fixtures("account", "outdated_password")
+)]
+async fn test_outdated_password_hash_is_rehashed_on_login(pool: sqlx::PgPool) {
+    let address = test_app(pool.clone(), routes()).await;
+
+    let response = login_with_password(&address, "123456").await;
+    assert_eq!(response.status(), 201);
+
+    let hash: String = sqlx::query_scalar("SELECT hash_password FROM accounts.passwords")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert!(hash.starts_with("$argon2id$v=19$m=4096,t=3,p=1$"));
+
+    let response = login_with_password(&address, "123456").await;
+    assert_eq!(response.status(), 201);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_unknown_email_and_wrong_password_look_the_same(pool: sqlx::PgPool) {
+    let address = test_app(pool, routes()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{address}/users/access-tokens/passwords"))
+        .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+        .json(&serde_json::json!({
+            "email": "nobody@consub.io",
+            "password": "123456",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    let unknown_email = response.json::<serde_json::Value>().await.unwrap();
+
+    let response = login_with_password(&address, "wrong-password").await;
+    assert_eq!(response.status(), 401);
+    let wrong_password = response.json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(unknown_email, wrong_password);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_repeated_failed_logins_are_throttled(pool: sqlx::PgPool) {
+    let address = test_app(pool, routes()).await;
+
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+
+    for _ in 0..5 {
+        let response = login_with_password(&address, "wrong-password").await;
+        assert_eq!(response.status(), 401);
+    }
+
+    // Even the right password has to wait for the backoff.
+    let response = login_with_password(&address, "123456").await;
+    assert_eq!(response.status(), 429);
+    let retry_after: i64 = response.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 31);
+
+    let response = reqwest::Client::new()
+        .get(format!("{address}/login-attempts/failed"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let attempts = response.json::<Vec<serde_json::Value>>().await.unwrap();
+    assert_eq!(attempts.len(), 5);
+    assert_eq!(attempts[0]["email"], "thiagovarela@consub.io");
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_concurrent_failed_logins_are_throttled(pool: sqlx::PgPool) {
+    let address = test_app(pool.clone(), routes()).await;
+
+    let attempts = (0..20)
+        .map(|_| {
+            let address = address.clone();
+            tokio::spawn(async move { login_with_password(&address, "wrong-password").await })
+        })
+        .collect::<Vec<_>>();
+    let mut statuses = Vec::new();
+    for attempt in attempts {
+        statuses.push(attempt.await.unwrap().status().as_u16());
+    }
+
+    // Only the attempts checked one after the other can fail, the others are told to retry.
+    let failed = statuses.iter().filter(|status| **status == 401).count();
+    assert!(failed >= 1 && failed <= 5);
+    assert_eq!(
+        statuses.iter().filter(|status| **status == 429).count(),
+        20 - failed
+    );
+
+    let recorded: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM accounts.login_attempts WHERE NOT succeeded")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(recorded, failed as i64);
+}
+
+/// The TOTP code of the step `offset` steps away from now.
+fn totp_code(secret: &str, offset: i64) -> String {
+    use hmac::{Hmac, Mac};
+
+    let secret = data_encoding::BASE32_NOPAD
+        .decode(secret.as_bytes())
+        .unwrap();
+    let step = (Utc::now().timestamp() / 30 + offset) as u64;
+    let mut mac = Hmac::<sha1::Sha1>::new_from_slice(&secret).unwrap();
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let i = (hash[19] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[i] & 0x7f, hash[i + 1], hash[i + 2], hash[i + 3]]);
+    format!("{:06}", binary % 1_000_000)
+}
+
+async fn login_challenge(address: &str) -> serde_json::Value {
+    let response = login_with_password(address, "123456").await;
+    assert_eq!(response.status(), 202);
+    response.json::<serde_json::Value>().await.unwrap()["challenge_token"].clone()
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_two_factor_login(pool: sqlx::PgPool) {
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+
+    let response = client
+        .post(format!("{address}/users/two-factor"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let enrollment = response.json::<serde_json::Value>().await.unwrap();
+    let secret = enrollment["secret"].as_str().unwrap().to_string();
+    assert!(enrollment["otpauth_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/"));
+
+    let response = client
+        .post(format!("{address}/users/two-factor/confirm"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "code": totp_code(&secret, 0) }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let recovery_codes = response.json::<serde_json::Value>().await.unwrap()["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .clone();
+    assert_eq!(recovery_codes.len(), 10);
+
+    let complete = |challenge_token: serde_json::Value, code: serde_json::Value| {
+        client
+            .post(format!("{address}/users/access-tokens/two-factor"))
+            .json(&serde_json::json!({ "challenge_token": challenge_token, "code": code }))
+            .send()
+    };
+
+    let challenge_token = login_challenge(&address).await;
+    let response = complete(challenge_token.clone(), "not-a-code".into())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+    let code: serde_json::Value = totp_code(&secret, 1).into();
+    let response = complete(challenge_token.clone(), code.clone())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    assert!(response.json::<serde_json::Value>().await.unwrap()["token"].is_string());
+
+    // Neither the challenge nor the code can be used twice.
+    let response = complete(challenge_token, code.clone()).await.unwrap();
+    assert_eq!(response.status(), 401);
+    let response = complete(login_challenge(&address).await, code)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+
+    let response = complete(login_challenge(&address).await, recovery_codes[0].clone())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let response = complete(login_challenge(&address).await, recovery_codes[0].clone())
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+
+    let response = client
+        .delete(format!("{address}/users/two-factor"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "code": recovery_codes[1] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    let response = login_with_password(&address, "123456").await;
+    assert_eq!(response.status(), 201);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_guessing_two_factor_codes_is_throttled(pool: sqlx::PgPool) {
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+
+    let response = client
+        .post(format!("{address}/users/two-factor"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    let enrollment = response.json::<serde_json::Value>().await.unwrap();
+    let secret = enrollment["secret"].as_str().unwrap().to_string();
+
+    let response = client
+        .post(format!("{address}/users/two-factor/confirm"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "code": totp_code(&secret, 0) }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let regenerate = |code: String| {
+        client
+            .post(format!("{address}/users/two-factor/recovery-codes"))
+            .header("Authorization", format!("Bearer {token}"))
+            .json(&serde_json::json!({ "code": code }))
+            .send()
+    };
+
+    for _ in 0..5 {
+        let response = regenerate("000000".into()).await.unwrap();
+        assert_eq!(response.status(), 400);
+    }
+
+    // The wrong codes count as failed logins, even a right code has to wait.
+    let response = regenerate(totp_code(&secret, 1)).await.unwrap();
+    assert_eq!(response.status(), 429);
+    assert!(response.headers().contains_key("retry-after"));
+
+    let response = client
+        .delete(format!("{address}/users/two-factor"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "code": totp_code(&secret, 1) }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 429);
+
+    let response = login_with_password(&address, "123456").await;
+    assert_eq!(response.status(), 429);
+}
+
+/// The seed of the key the mock provider signs its id tokens with.
+const MOCK_PROVIDER_SEED: [u8; 32] = [7; 32];
+
+type MockLogin = (String, String, serde_json::Value, [u8; 32]);
+
+/// A local OpenID Connect provider, the code it accepts and the claims of the id token
+/// it returns are set by the test.
+#[derive(Clone, Default)]
+struct MockProvider {
+    issuer: String,
+    login: std::sync::Arc<std::sync::Mutex<Option<MockLogin>>>,
+}
+
+impl MockProvider {
+    fn expect_login(&self, code: &str, code_challenge: &str, claims: serde_json::Value) {
+        *self.login.lock().unwrap() = Some((
+            code.into(),
+            code_challenge.into(),
+            claims,
+            MOCK_PROVIDER_SEED,
+        ));
+    }
+
+    /// The id token is signed with a key the provider doesn't publish.
+    fn expect_forged_login(&self, code: &str, code_challenge: &str, claims: serde_json::Value) {
+        *self.login.lock().unwrap() = Some((code.into(), code_challenge.into(), claims, [9; 32]));
+    }
+}
+
+async fn mock_provider_metadata(
+    axum::extract::State(provider): axum::extract::State<MockProvider>,
+) -> axum::Json<serde_json::Value> {
+    axum::Json(serde_json::json!({
+        "issuer": provider.issuer,
+        "authorization_endpoint": format!("{}/authorize", provider.issuer),
+        "token_endpoint": format!("{}/token", provider.issuer),
+        "jwks_uri": format!("{}/jwks", provider.issuer),
+    }))
+}
+
+async fn mock_provider_keys() -> axum::Json<serde_json::Value> {
+    let keypair =
+        ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new(MOCK_PROVIDER_SEED));
+    axum::Json(serde_json::json!({
+        "keys": [{
+            "kty": "OKP",
+            "crv": "Ed25519",
+            "use": "sig",
+            "alg": "EdDSA",
+            "kid": "mock",
+            "x": data_encoding::BASE64URL_NOPAD.encode(keypair.pk.as_ref()),
+        }]
+    }))
+}
+
+async fn mock_provider_token(
+    axum::extract::State(provider): axum::extract::State<MockProvider>,
+    axum::Form(form): axum::Form<std::collections::HashMap<String, String>>,
+) -> Result<axum::Json<serde_json::Value>, axum::http::StatusCode> {
+    use sha2::{Digest, Sha256};
+
+    let (code, code_challenge, claims, seed) = provider
+        .login
+        .lock()
+        .unwrap()
+        .take()
+        .ok_or(axum::http::StatusCode::BAD_REQUEST)?;
+    let verifier = form.get("code_verifier").cloned().unwrap_or_default();
+    let challenge = data_encoding::BASE64URL_NOPAD.encode(&Sha256::digest(verifier.as_bytes()));
+    if form.get("code") != Some(&code)
+        || challenge != code_challenge
+        || form.get("client_secret").map(String::as_str) != Some("shh")
+    {
+        return Err(axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    let keypair = ed25519_compact::KeyPair::from_seed(ed25519_compact::Seed::new(seed));
+    let id_token = Ed25519
+        .token(
+            Header::default().with_key_id("mock"),
+            &Claims::new(claims),
+            &keypair.sk,
+        )
+        .unwrap();
+    Ok(axum::Json(serde_json::json!({
+        "access_token": "provider-access-token",
+        "token_type": "Bearer",
+        "id_token": id_token,
+    })))
+}
+
+fn start_mock_provider() -> MockProvider {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let provider = MockProvider {
+        issuer: format!("http://127.0.0.1:{port}"),
+        ..Default::default()
+    };
+
+    let router = axum::Router::new()
+        .route(
+            "/.well-known/openid-configuration",
+            axum::routing::get(mock_provider_metadata),
+        )
+        .route("/token", axum::routing::post(mock_provider_token))
+        .route("/jwks", axum::routing::get(mock_provider_keys))
+        .with_state(provider.clone());
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(router.into_make_service())
+            .await
+            .unwrap()
+    });
+
+    provider
+}
+
+/// Starts a login with the provider, returns the state, nonce and PKCE challenge
+/// the user would carry to the provider.
+async fn authorize_with_oidc(address: &str) -> (String, String, String) {
+    let response = reqwest::Client::new()
+        .post(format!("{address}/oidc/example/authorize"))
+        .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let url = response.json::<serde_json::Value>().await.unwrap()["authorization_url"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    let param = |name: &str| {
+        url.split_once('?')
+            .unwrap()
+            .1
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(&format!("{name}=")))
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(param("code_challenge_method"), "S256");
+    assert_eq!(param("client_id"), "consub");
+    (param("state"), param("nonce"), param("code_challenge"))
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_login_with_an_oidc_provider(pool: sqlx::PgPool) {
+    let address = test_app(pool.clone(), routes()).await;
+    let provider = start_mock_provider();
+
+    let client = reqwest::Client::new();
+
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+
+    // Providers are only reached over TLS.
+    let response = client
+        .post(format!("{address}/oidc-providers"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({
+            "name": "Example",
+            "slug": "example",
+            "issuer": "http://example.com",
+            "client_id": "consub",
+            "redirect_uri": "https://consub.io/oidc/callback",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let response = client
+        .post(format!("{address}/oidc-providers"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({
+            "name": "Example",
+            "slug": "example",
+            "issuer": provider.issuer,
+            "client_id": "consub",
+            "client_secret": "shh",
+            "redirect_uri": "https://consub.io/oidc/callback",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let created = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(
+        created["token_endpoint"],
+        format!("{}/token", provider.issuer)
+    );
+    assert!(created.get("client_secret").is_none());
+
+    let claims = |nonce: &str, subject: &str, email_verified: bool| {
+        serde_json::json!({
+            "iss": provider.issuer,
+            "sub": subject,
+            "aud": "consub",
+            "exp": Utc::now().timestamp() + 300,
+            "nonce": nonce,
+            "email": "thiagovarela@consub.io",
+            "email_verified": email_verified,
+        })
+    };
+    let callback = |state: String, code: &str| {
+        client
+            .post(format!("{address}/users/access-tokens/oidc"))
+            .json(&serde_json::json!({ "state": state, "code": code }))
+            .send()
+    };
+
+    // The email isn't verified by the provider, so the identity can't be linked.
+    let (state, nonce, code_challenge) = authorize_with_oidc(&address).await;
+    provider.expect_login(
+        "code-1",
+        &code_challenge,
+        claims(&nonce, "subject-1", false),
+    );
+    let response = callback(state, "code-1").await.unwrap();
+    assert_eq!(response.status(), 401);
+
+    let (state, nonce, code_challenge) = authorize_with_oidc(&address).await;
+    provider.expect_login("code-2", &code_challenge, claims(&nonce, "subject-1", true));
+    let response = callback(state.clone(), "code-2").await.unwrap();
+    assert_eq!(response.status(), 201);
+    let access_token = response.json::<serde_json::Value>().await.unwrap();
+    let response = client
+        .get(format!("{address}/users/profiles"))
+        .header(
+            "Authorization",
+            format!("Bearer {}", access_token["token"].as_str().unwrap()),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.json::<serde_json::Value>().await.unwrap()["email"],
+        "thiagovarela@consub.io"
+    );
+
+    // The state can only be used once.
+    provider.expect_login("code-2", &code_challenge, claims(&nonce, "subject-1", true));
+    let response = callback(state, "code-2").await.unwrap();
+    assert_eq!(response.status(), 401);
+
+    // Once linked, the identity is found by its subject.
+    let (state, nonce, code_challenge) = authorize_with_oidc(&address).await;
+    provider.expect_login(
+        "code-3",
+        &code_challenge,
+        claims(&nonce, "subject-1", false),
+    );
+    let response = callback(state, "code-3").await.unwrap();
+    assert_eq!(response.status(), 201);
+
+    // An id token not signed by the provider is refused.
+    let (state, nonce, code_challenge) = authorize_with_oidc(&address).await;
+    provider.expect_forged_login("code-4", &code_challenge, claims(&nonce, "subject-1", true));
+    let response = callback(state, "code-4").await.unwrap();
+    assert_eq!(response.status(), 401);
+
+    // An id token issued for another login is refused.
+    let (state, _, code_challenge) = authorize_with_oidc(&address).await;
+    provider.expect_login(
+        "code-5",
+        &code_challenge,
+        claims("another-nonce", "subject-1", true),
+    );
+    let response = callback(state, "code-5").await.unwrap();
+    assert_eq!(response.status(), 401);
+
+    // Only owners manage the providers, a provider can sign in as any user.
+    sqlx::query("UPDATE accounts.users SET role = 'admin'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let response = client
+        .delete(format!(
+            "{address}/oidc-providers/{}",
+            created["id"].as_str().unwrap()
+        ))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "avatar"))]
+async fn test_change_user_profile(pool: sqlx::PgPool) {
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+
+    let change_profile = |body: serde_json::Value| {
+        client
+            .patch(format!("{address}/users/profiles"))
+            .header("Authorization", format!("Bearer {token}"))
+            .json(&body)
+            .send()
+    };
+
+    let response = change_profile(serde_json::json!({
+        "display_name": "Thiago Varela",
+        "bio": "Writes about Rust.",
+        "avatar_media_id": "0c6a3b52-5d1f-4a51-8a0e-7c2f1b9e4d21",
+        "social_links": [{ "name": "GitHub", "url": "https://github.com/thiagovarela" }],
+    }))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 200);
+
+    // Fields left out are kept, null clears them.
+    let response = change_profile(serde_json::json!({ "bio": null }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .get(format!("{address}/users/profiles"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let profile = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(profile["display_name"], "Thiago Varela");
+    assert!(profile["bio"].is_null());
+    assert_eq!(
+        profile["avatar_media_id"],
+        "0c6a3b52-5d1f-4a51-8a0e-7c2f1b9e4d21"
+    );
+    assert_eq!(
+        profile["social_links"][0]["url"],
+        "https://github.com/thiagovarela"
+    );
+
+    let response = change_profile(serde_json::json!({
+        "avatar_media_id": "a4f0e1d2-1111-4c3b-9d8e-2f6a7b8c9d0e",
+    }))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let response = change_profile(serde_json::json!({
+        "social_links": [{ "name": "GitHub", "url": "not a url" }],
+    }))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 422);
+}
+
+struct Newsletter;
+
+impl accounts::FeatureFlag for Newsletter {
+    const KEY: &'static str = "newsletter_enabled";
+    const DESCRIPTION: &'static str = "Newsletters";
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_change_account_settings(pool: sqlx::PgPool) {
+    accounts::register_feature_flag::<Newsletter>();
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+
+    let response = client
+        .patch(format!("{address}/settings"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({
+            "name": "Consub Blog",
+            "origin": "https://consub.io",
+            "feature_flags": { "newsletter_enabled": true },
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .get(format!("{address}/settings"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let settings = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(settings["name"], "Consub Blog");
+    assert_eq!(settings["origin"], "https://consub.io");
+    assert_eq!(settings["subdomain"], "consub");
+    let newsletter = settings["feature_flags"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|flag| flag["key"] == "newsletter_enabled")
+        .unwrap();
+    assert_eq!(newsletter["enabled"], true);
+
+    let response = client
+        .patch(format!("{address}/settings"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "feature_flags": { "unknown_enabled": true } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_gate_public_routes_of_subdomains_by_feature_flags(pool: sqlx::PgPool) {
+    async fn newsletter(
+        accounts::AccountID(account_id): accounts::AccountID,
+        _feature: accounts::Enabled<Newsletter>,
+    ) -> String {
+        account_id.to_string()
+    }
+    let public_routes =
+        aide::axum::ApiRouter::new().route("/newsletter", axum::routing::get(newsletter));
+    let address = test_app(pool.clone(), public_routes).await;
+
+    let client = reqwest::Client::new();
+    let get_newsletter = || {
+        client
+            .get(format!("{address}/newsletter"))
+            .header("Host", "consub.consub.io")
+            .send()
+    };
+
+    let response = get_newsletter().await.unwrap();
+    assert_eq!(response.status(), 404);
+
+    sqlx::query(
+        "UPDATE accounts.accounts SET feature_flags = feature_flags || '{\"newsletter_enabled\": true}'",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let response = get_newsletter().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "263b6188-aac6-45e9-9a2c-4728fdfd7ea1"
+    );
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_serve_an_account_on_a_verified_custom_domain(pool: sqlx::PgPool) {
+    let (address, dns) = test_app_with_dns(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+
+    let response = client
+        .post(format!("{address}/custom-domains"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "domain": "Blog.Customer.com" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let custom_domain = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(custom_domain["domain"], "blog.customer.com");
+    assert_eq!(
+        custom_domain["verification_record"],
+        "_consub-verification.blog.customer.com"
+    );
+    let custom_domain_id = custom_domain["id"].as_str().unwrap();
+
+    let login_on_custom_domain = || {
+        client
+            .post(format!("{address}/users/access-tokens/passwords"))
+            .header("Host", "blog.customer.com")
+            .json(&serde_json::json!({
+                "email": "thiagovarela@consub.io",
+                "password": "123456"
+            }))
+            .send()
+    };
+
+    // Not used before it's verified.
+    let response = login_on_custom_domain().await.unwrap();
+    assert_eq!(response.status(), 400);
+
+    let response = client
+        .post(format!(
+            "{address}/custom-domains/{custom_domain_id}/verify"
+        ))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    dns.set_txt_records(
+        "_consub-verification.blog.customer.com",
+        vec![custom_domain["verification_token"]
+            .as_str()
+            .unwrap()
+            .to_string()],
+    );
+
+    let response = client
+        .post(format!(
+            "{address}/custom-domains/{custom_domain_id}/verify"
+        ))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let custom_domain = response.json::<serde_json::Value>().await.unwrap();
+    assert!(custom_domain["verified_at"].is_string());
+
+    let response = login_on_custom_domain().await.unwrap();
+    assert_eq!(response.status(), 201);
+
+    let response = client
+        .delete(format!("{address}/custom-domains/{custom_domain_id}"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    let response = login_on_custom_domain().await.unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_public_cors_allows_the_account_origins(pool: sqlx::PgPool) {
+    let routes = routes().layer(axum::middleware::from_fn_with_state(
+        pool.clone(),
+        accounts::public_cors,
+    ));
+    let address = test_app(pool, routes).await;
+
+    let client = reqwest::Client::new();
+
+    let token = login(&address).await["token"].as_str().unwrap().to_string();
+
+    let response = client
+        .patch(format!("{address}/settings"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({
+            "origin": "https://consub.io/blog",
+            "allowed_origins": ["https://*.consub.dev", "http://localhost:3000"],
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .patch(format!("{address}/settings"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "allowed_origins": ["https://consub.dev/blog"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let preflight = |origin: &'static str| {
+        client
+            .request(
+                reqwest::Method::OPTIONS,
+                format!("{address}/users/access-tokens/passwords"),
+            )
+            .header("Host", "consub.consub.io")
+            .header("Origin", origin)
+            .header("Access-Control-Request-Method", "POST")
+            .header("Access-Control-Request-Headers", "content-type, x-api-key")
+            .send()
+    };
+
+    let response = preflight("https://www.consub.dev").await.unwrap();
+    assert_eq!(response.status(), 204);
+    assert_eq!(
+        response.headers()["access-control-allow-origin"],
+        "https://www.consub.dev"
+    );
+    assert!(response.headers()["access-control-allow-headers"]
+        .to_str()
+        .unwrap()
+        .contains("x-api-key"));
+
+    let response = preflight("https://consub.dev").await.unwrap();
+    assert_eq!(response.status(), 403);
+
+    let login_from = |origin: &'static str| {
+        client
+            .post(format!("{address}/users/access-tokens/passwords"))
+            .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+            .header("Origin", origin)
+            .json(&serde_json::json!({
+                "email": "thiagovarela@consub.io",
+                "password": "123456"
+            }))
+            .send()
+    };
+
+    let response = login_from("https://consub.io").await.unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(
+        response.headers()["access-control-allow-origin"],
+        "https://consub.io"
+    );
+
+    let response = login_from("http://localhost:3000").await.unwrap();
+    assert_eq!(response.status(), 201);
+
+    let response = login_from("https://evil.com").await.unwrap();
+    assert_eq!(response.status(), 403);
+    assert!(response
+        .headers()
+        .get("access-control-allow-origin")
+        .is_none());
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_rate_limit_the_requests_of_a_client(pool: sqlx::PgPool) {
+    // The address of the client is the one the proxy adds to `X-Forwarded-For`.
+    std::env::set_var("CLIENT_IP_HEADER", "X-Forwarded-For");
+    sqlx::query(
+        "UPDATE accounts.plan_rate_limits SET capacity = 2, refill_per_minute = 1
+        WHERE route_group = 'admin'",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let limiter = RateLimiter(std::sync::Arc::new(MemoryBackend::default()));
+    let routes = routes().layer(axum::middleware::from_fn_with_state(
+        accounts::RateLimitGroup::new("admin", pool.clone(), limiter),
+        accounts::rate_limit,
+    ));
+    let address = test_app(pool, routes).await;
+
+    let client = reqwest::Client::new();
+
+    let login_from = |ip: &'static str| {
+        client
+            .post(format!("{address}/users/access-tokens/passwords"))
+            .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+            .header("X-Forwarded-For", ip)
+            .json(&serde_json::json!({
+                "email": "thiagovarela@consub.io",
+                "password": "123456"
+            }))
+            .send()
+    };
+
+    let response = login_from("10.0.0.1").await.unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.headers()["ratelimit-limit"], "2");
+    assert_eq!(response.headers()["ratelimit-remaining"], "1");
+
+    let response = login_from("10.0.0.1").await.unwrap();
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.headers()["ratelimit-remaining"], "0");
+
+    let response = login_from("10.0.0.1").await.unwrap();
+    assert_eq!(response.status(), 429);
+    let retry_after: u64 = response.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+
+    // Each client address has its own limit.
+    let response = login_from("10.0.0.2").await.unwrap();
+    assert_eq!(response.status(), 201);
+}
@@ -4,8 +4,12 @@ INSERT INTO accounts.accounts (id,name,subdomain) VALUES
 INSERT INTO accounts.account_keys (id,account_id,keypair,expires_at) VALUES
 	 ('e6af50e6-0ef3-4908-80c0-a83622d96d03','263b6188-aac6-45e9-9a2c-4728fdfd7ea1',decode('D3BC1571AE014104D3D758003F1957F364FFC4A476B454B38C365C1717E9B7975AACA9114E01FA97C32A7721F763969CDE613141D27C2047BB80005978FD1658','hex'),NULL);
 
-INSERT INTO accounts.users (id,account_id,email) VALUES
-	 ('ad38ffbe-dabe-43a1-b63a-4028e23090eb','263b6188-aac6-45e9-9a2c-4728fdfd7ea1', 'thiagovarela@consub.io');
+-- api key: e6af50e6-0ef3-4908-80c0-a83622d96d03
+INSERT INTO accounts.api_keys (id,account_id,name,prefix,key_hash,scopes) VALUES
+	 ('0186d5c8-1b2a-4c3d-8e4f-5a6b7c8d9e0f','263b6188-aac6-45e9-9a2c-4728fdfd7ea1','Default key','e6af50e6','ec11979e4498ed3d43346cd74192bbd0217ff51459a30b3df6b06c24bbd07cdf','{public:read,analytics:write,leads:write}');
+
+INSERT INTO accounts.users (id,account_id,email,role) VALUES
+	 ('ad38ffbe-dabe-43a1-b63a-4028e23090eb','263b6188-aac6-45e9-9a2c-4728fdfd7ea1', 'thiagovarela@consub.io', 'owner');
 
 -- password: 123456
 INSERT INTO accounts.passwords (user_id,hash_password) VALUES
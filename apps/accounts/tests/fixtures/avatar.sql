@@ -0,0 +1,2 @@
+INSERT INTO media.images (id,account_id,alt) VALUES
+	 ('0c6a3b52-5d1f-4a51-8a0e-7c2f1b9e4d21','263b6188-aac6-45e9-9a2c-4728fdfd7ea1','Avatar');
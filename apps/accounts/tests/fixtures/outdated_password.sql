@@ -0,0 +1,4 @@
+-- password: 123456, hashed with m=8192,t=2 instead of the default parameters
+UPDATE accounts.passwords
+SET hash_password = '$argon2id$v=19$m=8192,t=2,p=1$xOEf1wOygtTe4OAHI8T3VA$mMAuFlIhi38682Jl2qwZ1sB+S36zAETaWVtm58Vm8LI'
+WHERE user_id = 'ad38ffbe-dabe-43a1-b63a-4028e23090eb';
@@ -1,4 +1,4 @@
-use accounts::Account;
+use accounts::{scopes::AnalyticsWrite, ScopedAccount};
 use aide::{
     axum::{routing::post_with, IntoApiResponse, ApiRouter},
     transform::TransformOperation,
@@ -11,7 +11,8 @@ use sqlx::PgPool;
 use crate::page_views::PageViewInput;
 
 pub async fn create_page_view(
-    State(pool): State<PgPool>, account: Account, Json(input): Json<PageViewInput>,
+    State(pool): State<PgPool>, account: ScopedAccount<AnalyticsWrite>,
+    Json(input): Json<PageViewInput>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     crate::page_views::create_page_view(&mut conn, account.id, input).await?;
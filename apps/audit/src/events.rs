@@ -0,0 +1,80 @@
+use chrono::NaiveDateTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use shared::pagination::CursorPagination;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+/// A change made in the account, recorded by the admin routes with `shared::audit`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub account_id: Uuid,
+    pub actor_id: Option<Uuid>,
+    /// The email of the actor, unless the user was removed since.
+    pub actor_email: Option<String>,
+    /// The app and the kind of entity, e.g. `blogs.post`.
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    /// `created`, `changed`, `deleted`, `revoked`, `expired` or `verified`.
+    pub action: String,
+    /// The fields that changed, or the whole entity when it was deleted.
+    pub before: Option<serde_json::Value>,
+    /// The fields that changed, or the whole entity when it was created.
+    pub after: Option<serde_json::Value>,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AuditEventQuery {
+    pub entity_type: Option<String>,
+    pub entity_id: Option<Uuid>,
+    pub actor_id: Option<Uuid>,
+    pub action: Option<String>,
+    /// Only the events from this date on.
+    pub since: Option<NaiveDateTime>,
+    /// Only the events before this date.
+    pub until: Option<NaiveDateTime>,
+
+    /// Events are listed from the newest, `after` is the id of the last event of the previous page.
+    #[serde(default, flatten)]
+    pub pagination: CursorPagination,
+}
+
+pub async fn list_events(
+    conn: &mut PgConnection, account_id: Uuid, query: AuditEventQuery,
+) -> Result<Vec<AuditEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT e.id, e.account_id, e.actor_id, u.email as "actor_email?", e.entity_type,
+            e.entity_id, e.action, e.before, e.after, e.ip_address, e.user_agent, e.created_at
+        FROM audit.events e
+        LEFT JOIN accounts.users u ON (u.id = e.actor_id)
+        WHERE e.account_id = $1
+        AND ($2::text IS NULL OR e.entity_type = $2)
+        AND ($3::uuid IS NULL OR e.entity_id = $3)
+        AND ($4::uuid IS NULL OR e.actor_id = $4)
+        AND ($5::text IS NULL OR e.action = $5)
+        AND ($6::timestamp IS NULL OR e.created_at >= $6)
+        AND ($7::timestamp IS NULL OR e.created_at < $7)
+        AND ($8::text IS NULL OR e.id < $8::uuid)
+        ORDER BY e.id DESC
+        LIMIT $9
+        "#,
+        account_id,
+        query.entity_type,
+        query.entity_id,
+        query.actor_id,
+        query.action,
+        query.since,
+        query.until,
+        query.pagination.after,
+        query.pagination.take,
+    )
+    .fetch_all(conn)
+    .await
+}
@@ -0,0 +1,4 @@
+mod events;
+mod router;
+
+pub use router::routes;
@@ -0,0 +1,33 @@
+use accounts::{permissions::ReadAuditLog, Authorized};
+use aide::axum::routing::get_with;
+use aide::axum::{ApiRouter, IntoApiResponse};
+use aide::transform::TransformOperation;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::{debug_handler, Json};
+use shared::{AppError, AppState};
+use sqlx::PgPool;
+
+use crate::events::{AuditEvent, AuditEventQuery};
+
+#[debug_handler]
+pub async fn list_events(
+    State(pool): State<PgPool>, user: Authorized<ReadAuditLog>,
+    Query(query): Query<AuditEventQuery>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let events = crate::events::list_events(&mut conn, user.account_id, query).await?;
+    Ok((StatusCode::OK, Json(events)))
+}
+
+pub fn list_events_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_audit_events")
+        .description("List the changes made in the account, newest first. Only for owners.")
+        .response::<200, Json<Vec<AuditEvent>>>()
+        .security_requirement("Bearer")
+        .tag("audit")
+}
+
+pub fn routes() -> ApiRouter<AppState> {
+    ApiRouter::new().api_route("/", get_with(list_events, list_events_docs))
+}
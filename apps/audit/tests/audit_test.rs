@@ -0,0 +1,93 @@
+use accounts::authenticate_user_with_password;
+use aide::axum::ApiRouter;
+use shared::testing::test_app;
+
+async fn test_token(pool: &sqlx::PgPool) -> String {
+    let access_token = authenticate_user_with_password(
+        pool,
+        uuid::uuid!("263b6188-aac6-45e9-9a2c-4728fdfd7ea1"),
+        "thiagovarela@consub.io".into(),
+        "123456".into(),
+    )
+    .await
+    .unwrap();
+    access_token.token
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_list_the_changes_made_in_the_account(pool: sqlx::PgPool) {
+    std::env::set_var("CLIENT_IP_HEADER", "X-Forwarded-For");
+    let token = test_token(&pool).await;
+
+    let routes = ApiRouter::new()
+        .nest("/blogs", blogs::routes())
+        .nest("/audit", audit::routes());
+    let address = test_app(pool, routes).await;
+
+    let client = reqwest::Client::new();
+
+    let category = client
+        .post(format!("{address}/blogs/categories"))
+        .header("Authorization", format!("Bearer {token}"))
+        .header("User-Agent", "consub-tests")
+        // Only the last address, added by the proxy, is trusted.
+        .header("X-Forwarded-For", "198.51.100.1, 203.0.113.7")
+        .json(&serde_json::json!({ "name": "News", "locale": "en-US" }))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    let category_id = category["id"].as_str().unwrap();
+
+    let response = client
+        .patch(format!("{address}/blogs/categories/{category_id}"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "name": "Breaking news" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .get(format!(
+            "{address}/audit?entity_type=blogs.category&entity_id={category_id}"
+        ))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let events = response.json::<serde_json::Value>().await.unwrap();
+    let events = events.as_array().unwrap();
+    assert_eq!(events.len(), 2);
+
+    // Newest first, a change only keeps the fields that changed.
+    assert_eq!(events[0]["action"], "changed");
+    assert_eq!(events[0]["actor_email"], "thiagovarela@consub.io");
+    assert_eq!(events[0]["before"], serde_json::json!({ "name": "News" }));
+    assert_eq!(
+        events[0]["after"],
+        serde_json::json!({ "name": "Breaking news" })
+    );
+
+    assert_eq!(events[1]["action"], "created");
+    assert_eq!(events[1]["before"], serde_json::Value::Null);
+    assert_eq!(events[1]["after"]["slug"], "news");
+    assert_eq!(events[1]["ip_address"], "203.0.113.7");
+    assert_eq!(events[1]["user_agent"], "consub-tests");
+
+    let response = client
+        .get(format!(
+            "{address}/audit?action=changed&after={}",
+            events[0]["id"].as_str().unwrap()
+        ))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    let older = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(older.as_array().unwrap().len(), 0);
+}
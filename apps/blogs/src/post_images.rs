@@ -51,7 +51,7 @@ pub async fn change_post_image(
         UPDATE blogs.post_images
         SET image_type = COALESCE($3, image_type)
         FROM blogs.posts 
-        WHERE blogs.post_images.post_id = $1
+        WHERE blogs.post_images.id = $1
         AND blogs.posts.id = blogs.post_images.post_id
         AND blogs.posts.account_id = $2
         RETURNING blogs.post_images.*
@@ -64,6 +64,24 @@ pub async fn change_post_image(
     .await
 }
 
+pub async fn get_post_image(
+    conn: &mut PgConnection, post_image_id: Uuid, account_id: Uuid,
+) -> Result<PostImage, sqlx::Error> {
+    sqlx::query_as!(
+        PostImage,
+        r#"
+        SELECT pi.* FROM blogs.post_images pi
+        INNER JOIN blogs.posts p ON p.id = pi.post_id
+        WHERE pi.id = $1
+        AND p.account_id = $2
+        "#,
+        post_image_id,
+        account_id
+    )
+    .fetch_one(conn)
+    .await
+}
+
 pub async fn delete_post_image(
     conn: &mut PgConnection, post_image_id: Uuid, account_id: Uuid,
 ) -> Result<PostImage, sqlx::Error> {
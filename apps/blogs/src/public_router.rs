@@ -1,4 +1,4 @@
-use accounts::Account;
+use accounts::{Account, PublicAuthor};
 use aide::{
     axum::{routing::get_with, IntoApiResponse, ApiRouter},
     transform::TransformOperation,
@@ -24,6 +24,7 @@ pub struct PublicPost {
     pub post: Post,
     pub images: Vec<PostImage>,
     pub category: Option<Category>,
+    pub author: Option<PublicAuthor>,
 }
 
 #[debug_handler]
@@ -55,6 +56,16 @@ pub async fn list_posts(
         .await?
     };
 
+    let authors = {
+        let mut conn = pool.acquire().await?;
+        accounts::list_public_authors(
+            &mut conn,
+            account.id,
+            posts.iter().map(|p| p.author_id).collect(),
+        )
+        .await?
+    };
+
     let posts: Vec<PublicPost> = posts
         .into_iter()
         .map(|post| {
@@ -63,6 +74,10 @@ pub async fn list_posts(
                 .filter(|image| image.post_id == post.id)
                 .cloned()
                 .collect();
+            let author = authors
+                .iter()
+                .find(|author| author.id == post.author_id)
+                .cloned();
             if let Some(category_id) = post.category_id {
                 let category = post_categories
                     .iter()
@@ -72,12 +87,14 @@ pub async fn list_posts(
                     post,
                     images,
                     category,
+                    author,
                 }
             } else {
                 PublicPost {
                     post,
                     images,
                     category: None,
+                    author,
                 }
             }
         })
@@ -1,6 +1,9 @@
-use accounts::User;
+use accounts::{
+    permissions::{DeleteContent, ReadContent, WriteContent},
+    Authorized,
+};
 use aide::axum::routing::{delete_with, get_with, patch_with, post_with};
-use aide::axum::{IntoApiResponse, ApiRouter};
+use aide::axum::{ApiRouter, IntoApiResponse};
 use aide::transform::TransformOperation;
 use axum::extract::State;
 use axum::extract::{Path, Query};
@@ -9,7 +12,7 @@ use axum::http::StatusCode;
 use axum::{debug_handler, Json};
 use axum_extra::extract::Query as ExtraQuery;
 use schemars::JsonSchema;
-use shared::{AppError, AppState};
+use shared::{audit, client::ClientInfo, AppError, AppState};
 use sqlx::PgPool;
 use uuid::Uuid;
 use validator::Validate;
@@ -27,11 +30,14 @@ pub struct PathPost {
 
 #[debug_handler]
 pub async fn create_post(
-    State(pool): State<PgPool>, user: User, Json(body): Json<CreatePostInput>,
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, client: ClientInfo,
+    Json(body): Json<CreatePostInput>,
 ) -> Result<impl IntoApiResponse, AppError> {
     body.validate()?;
-    let mut conn = pool.acquire().await?;
-    let post = crate::posts::create_post(&mut conn, user.account_id, user.id, body).await?;
+    let mut tx = pool.begin().await?;
+    let post = crate::posts::create_post(&mut tx, user.account_id, user.id, body).await?;
+    audit::created(&mut tx, &user.actor(client), "blogs.post", post.id, &post).await?;
+    tx.commit().await?;
     Ok((StatusCode::CREATED, Json(post)))
 }
 
@@ -45,7 +51,8 @@ pub fn create_post_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn list_posts(
-    State(pool): State<PgPool>, user: User, ExtraQuery(query): ExtraQuery<PostQuery>,
+    State(pool): State<PgPool>, user: Authorized<ReadContent>,
+    ExtraQuery(query): ExtraQuery<PostQuery>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     let posts = crate::posts::list_posts(&mut conn, user.account_id, query).await?;
@@ -62,7 +69,7 @@ pub fn list_posts_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn get_post(
-    State(pool): State<PgPool>, user: User, Path(path_post): Path<PathPost>,
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Path(path_post): Path<PathPost>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     let post = crate::posts::get_post(&mut conn, user.account_id, path_post.post_id).await?;
@@ -79,12 +86,22 @@ pub fn get_post_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn change_post(
-    State(pool): State<PgPool>, user: User, Path(path_post): Path<PathPost>,
-    Json(body): Json<ChangePostInput>,
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, client: ClientInfo,
+    Path(path_post): Path<PathPost>, Json(body): Json<ChangePostInput>,
 ) -> Result<impl IntoApiResponse, AppError> {
-    let mut conn = pool.acquire().await?;
-    let post =
-        crate::posts::change_post(&mut conn, user.account_id, path_post.post_id, body).await?;
+    let mut tx = pool.begin().await?;
+    let before = crate::posts::get_post(&mut tx, user.account_id, path_post.post_id).await?;
+    let post = crate::posts::change_post(&mut tx, user.account_id, path_post.post_id, body).await?;
+    audit::changed(
+        &mut tx,
+        &user.actor(client),
+        "blogs.post",
+        post.id,
+        &before,
+        &post,
+    )
+    .await?;
+    tx.commit().await?;
     Ok((StatusCode::OK, Json(post)))
 }
 
@@ -105,10 +122,14 @@ pub struct PathCategory {
 
 #[debug_handler]
 pub async fn create_category(
-    State(pool): State<PgPool>, user: User, Json(body): Json<CreateCategoryInput>,
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, client: ClientInfo,
+    Json(body): Json<CreateCategoryInput>,
 ) -> Result<impl IntoApiResponse, AppError> {
-    let mut conn = pool.acquire().await?;
-    let category = crate::categories::create_category(&mut conn, user.account_id, body).await?;
+    let mut tx = pool.begin().await?;
+    let category = crate::categories::create_category(&mut tx, user.account_id, body).await?;
+    let actor = user.actor(client);
+    audit::created(&mut tx, &actor, "blogs.category", category.id, &category).await?;
+    tx.commit().await?;
     Ok((StatusCode::CREATED, Json(category)))
 }
 
@@ -122,7 +143,7 @@ pub fn create_category_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn list_categories(
-    State(pool): State<PgPool>, user: User, Query(query): Query<CategoryQuery>,
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Query(query): Query<CategoryQuery>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     let categories = crate::categories::list_categories(&mut conn, user.account_id, query).await?;
@@ -139,7 +160,7 @@ pub fn list_categories_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn get_category(
-    State(pool): State<PgPool>, user: User, Path(path): Path<PathCategory>,
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Path(path): Path<PathCategory>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     let category =
@@ -157,13 +178,26 @@ pub fn get_category_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn change_category(
-    State(pool): State<PgPool>, user: User, Path(path): Path<PathCategory>,
-    Json(body): Json<ChangeCategoryInput>,
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, client: ClientInfo,
+    Path(path): Path<PathCategory>, Json(body): Json<ChangeCategoryInput>,
 ) -> Result<impl IntoApiResponse, AppError> {
-    let mut conn = pool.acquire().await?;
+    let mut tx = pool.begin().await?;
+    let before =
+        crate::categories::get_category_by_id(&mut tx, path.category_id, user.account_id).await?;
     let category =
-        crate::categories::change_category(&mut conn, path.category_id, user.account_id, body)
+        crate::categories::change_category(&mut tx, path.category_id, user.account_id, body)
             .await?;
+    let actor = user.actor(client);
+    audit::changed(
+        &mut tx,
+        &actor,
+        "blogs.category",
+        category.id,
+        &before,
+        &category,
+    )
+    .await?;
+    tx.commit().await?;
     Ok((StatusCode::OK, Json(category)))
 }
 
@@ -177,10 +211,21 @@ pub fn change_category_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn delete_category(
-    State(pool): State<PgPool>, user: User, Path(category_id): Path<Uuid>,
+    State(pool): State<PgPool>, user: Authorized<DeleteContent>, client: ClientInfo,
+    Path(category_id): Path<Uuid>,
 ) -> Result<impl IntoApiResponse, AppError> {
-    let mut conn = pool.acquire().await?;
-    crate::categories::delete_category(&mut conn, category_id, user.account_id).await?;
+    let mut tx = pool.begin().await?;
+    let category =
+        crate::categories::delete_category(&mut tx, category_id, user.account_id).await?;
+    audit::deleted(
+        &mut tx,
+        &user.actor(client),
+        "blogs.category",
+        category.id,
+        &category,
+    )
+    .await?;
+    tx.commit().await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -202,12 +247,21 @@ pub struct PathPostImage {
 
 #[debug_handler]
 pub async fn create_post_image(
-    State(pool): State<PgPool>, _user: User, Path(path_post): Path<PathPost>,
-    Json(body): Json<CreatePostImageInput>,
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, client: ClientInfo,
+    Path(path_post): Path<PathPost>, Json(body): Json<CreatePostImageInput>,
 ) -> Result<impl IntoApiResponse, AppError> {
-    let mut conn = pool.acquire().await?;
+    let mut tx = pool.begin().await?;
     // TODO: check if user is allowed to create post image
-    let image = crate::post_images::create_post_image(&mut conn, path_post.post_id, body).await?;
+    let image = crate::post_images::create_post_image(&mut tx, path_post.post_id, body).await?;
+    audit::created(
+        &mut tx,
+        &user.actor(client),
+        "blogs.post_image",
+        image.id,
+        &image,
+    )
+    .await?;
+    tx.commit().await?;
     Ok((StatusCode::CREATED, Json(image)))
 }
 
@@ -221,7 +275,7 @@ pub fn create_post_image_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn list_post_images(
-    State(pool): State<PgPool>, user: User, Path(path_post): Path<PathPost>,
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Path(path_post): Path<PathPost>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     let images =
@@ -239,17 +293,31 @@ pub fn list_post_images_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn change_post_image(
-    State(pool): State<PgPool>, user: User, Path(post_image_path): Path<PathPostImage>,
-    Json(body): Json<ChangePostImageInput>,
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, client: ClientInfo,
+    Path(post_image_path): Path<PathPostImage>, Json(body): Json<ChangePostImageInput>,
 ) -> Result<impl IntoApiResponse, AppError> {
-    let mut conn = pool.acquire().await?;
+    let mut tx = pool.begin().await?;
+    let before =
+        crate::post_images::get_post_image(&mut tx, post_image_path.post_image_id, user.account_id)
+            .await?;
     let post_image = crate::post_images::change_post_image(
-        &mut conn,
+        &mut tx,
         post_image_path.post_image_id,
         user.account_id,
         body,
     )
     .await?;
+    let actor = user.actor(client);
+    audit::changed(
+        &mut tx,
+        &actor,
+        "blogs.post_image",
+        post_image.id,
+        &before,
+        &post_image,
+    )
+    .await?;
+    tx.commit().await?;
     Ok((StatusCode::OK, Json(post_image)))
 }
 
@@ -263,15 +331,26 @@ pub fn change_post_image_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn delete_post_image(
-    State(pool): State<PgPool>, user: User, Path(post_image_path): Path<PathPostImage>,
+    State(pool): State<PgPool>, user: Authorized<DeleteContent>, client: ClientInfo,
+    Path(post_image_path): Path<PathPostImage>,
 ) -> Result<impl IntoApiResponse, AppError> {
-    let mut conn = pool.acquire().await?;
-    crate::post_images::delete_post_image(
-        &mut conn,
+    let mut tx = pool.begin().await?;
+    let post_image = crate::post_images::delete_post_image(
+        &mut tx,
         post_image_path.post_image_id,
         user.account_id,
     )
     .await?;
+    let actor = user.actor(client);
+    audit::deleted(
+        &mut tx,
+        &actor,
+        "blogs.post_image",
+        post_image.id,
+        &post_image,
+    )
+    .await?;
+    tx.commit().await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -91,3 +91,45 @@ async fn test_delete_category(pool: sqlx::PgPool) {
 
     assert_eq!(response.status(), 204);
 }
+
+#[sqlx::test(
+    migrations = "../../migrations",
+    fixtures("account", "viewer", "categories")
+)]
+async fn test_viewer_can_only_read_categories(pool: sqlx::PgPool) {
+    let token = authenticate_user_with_password(
+        &pool,
+        uuid::uuid!("263b6188-aac6-45e9-9a2c-4728fdfd7ea1"),
+        "viewer@consub.io".into(),
+        "123456".into(),
+    )
+    .await
+    .unwrap()
+    .token;
+
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{address}/categories"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .post(format!("{address}/categories"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({
+            "name": "Test Category",
+            "locale": "en-US",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 403);
+}
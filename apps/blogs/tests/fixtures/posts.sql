@@ -0,0 +1,6 @@
+UPDATE accounts.users SET display_name = 'Thiago Varela', bio = 'Writes about Rust.',
+	social_links = '[{"name": "GitHub", "url": "https://github.com/thiagovarela"}]'
+	WHERE id = 'ad38ffbe-dabe-43a1-b63a-4028e23090eb';
+
+INSERT INTO blogs.posts (id,account_id,author_id,title,slug,body_json,body_html,body_text,locale,published_at) VALUES
+	 ('5b0a1c1e-3b8e-4f4e-9d59-3f0b7a2c1d10','263b6188-aac6-45e9-9a2c-4728fdfd7ea1','ad38ffbe-dabe-43a1-b63a-4028e23090eb','Hello World','hello-world','{}','<p>Hello</p>','Hello','en-US','2023-01-01 00:00:00');
@@ -0,0 +1,6 @@
+INSERT INTO accounts.users (id,account_id,email,role) VALUES
+	 ('0186d5c2-3f1a-4b7e-9c2d-5e8f1a2b3c4d','263b6188-aac6-45e9-9a2c-4728fdfd7ea1', 'viewer@consub.io', 'viewer');
+
+-- password: 123456
+INSERT INTO accounts.passwords (user_id,hash_password) VALUES
+	 ('0186d5c2-3f1a-4b7e-9c2d-5e8f1a2b3c4d','$argon2id$v=19$m=4096,t=3,p=1$jbrAa6jwSsVfuIT8Fys3ew$g7y2yWMX/Lq9WGiwCo210v5so7qX8Eo6X67PpJCACT4');
@@ -0,0 +1,25 @@
+use blogs::public_routes;
+use shared::testing::test_app;
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "posts"))]
+async fn test_public_posts_embed_their_author(pool: sqlx::PgPool) {
+    let address = test_app(pool, public_routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{address}/posts"))
+        .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let posts = response.json::<Vec<serde_json::Value>>().await.unwrap();
+    assert_eq!(posts.len(), 1);
+    let author = &posts[0]["author"];
+    assert_eq!(author["id"], "ad38ffbe-dabe-43a1-b63a-4028e23090eb");
+    assert_eq!(author["display_name"], "Thiago Varela");
+    assert_eq!(author["social_links"][0]["name"], "GitHub");
+    assert!(author.get("email").is_none());
+}
@@ -5,3 +5,11 @@ mod router;
 
 pub use public_router::public_routes;
 pub use router::routes;
+
+/// Accounts turn clippings on in their settings.
+pub struct Clippings;
+
+impl accounts::FeatureFlag for Clippings {
+    const KEY: &'static str = "clipping_enabled";
+    const DESCRIPTION: &'static str = "Clippings, curated links to content published elsewhere.";
+}
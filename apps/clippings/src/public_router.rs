@@ -1,4 +1,4 @@
-use accounts::Account;
+use accounts::{Account, Enabled};
 use aide::{
     axum::{routing::get_with, IntoApiResponse, ApiRouter},
     transform::TransformOperation,
@@ -16,10 +16,12 @@ use crate::{
     categories::{Category, CategoryQuery},
     items::{ClippingItem, ClippingItemQuery},
     router::{PathCategory, PathItem},
+    Clippings,
 };
 
 pub async fn list_clipping_items(
-    State(pool): State<PgPool>, account: Account, Query(query): Query<ClippingItemQuery>,
+    State(pool): State<PgPool>, account: Account, _feature: Enabled<Clippings>,
+    Query(query): Query<ClippingItemQuery>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     let items = crate::items::public_list_clipping_items(&mut conn, account.id, query).await?;
@@ -34,7 +36,8 @@ pub fn list_clipping_items_docs(op: TransformOperation) -> TransformOperation {
 }
 
 pub async fn list_categories(
-    State(pool): State<PgPool>, account: Account, Query(query): Query<CategoryQuery>,
+    State(pool): State<PgPool>, account: Account, _feature: Enabled<Clippings>,
+    Query(query): Query<CategoryQuery>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     let categories = crate::categories::list_categories(&mut conn, account.id, query).await?;
@@ -49,7 +52,8 @@ pub fn list_categories_docs(op: TransformOperation) -> TransformOperation {
 }
 
 pub async fn get_category(
-    State(pool): State<PgPool>, account: Account, Path(path): Path<PathCategory>,
+    State(pool): State<PgPool>, account: Account, _feature: Enabled<Clippings>,
+    Path(path): Path<PathCategory>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     let category =
@@ -65,7 +69,8 @@ pub fn get_category_docs(op: TransformOperation) -> TransformOperation {
 }
 
 pub async fn get_clipping_item(
-    State(pool): State<PgPool>, account: Account, Path(path): Path<PathItem>,
+    State(pool): State<PgPool>, account: Account, _feature: Enabled<Clippings>,
+    Path(path): Path<PathItem>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     let item = crate::items::get_clipping_item(&mut conn, account.id, path.item_id).await?;
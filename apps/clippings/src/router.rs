@@ -1,6 +1,9 @@
-use accounts::User;
-use aide::axum::ApiRouter;
+use accounts::{
+    permissions::{DeleteContent, ReadContent, WriteContent},
+    Authorized, Enabled,
+};
 use aide::axum::routing::{delete_with, patch_with, post_with};
+use aide::axum::ApiRouter;
 use aide::{
     axum::{routing::get_with, IntoApiResponse},
     transform::TransformOperation,
@@ -11,7 +14,7 @@ use axum::http::StatusCode;
 use axum::{debug_handler, Json};
 use axum_extra::extract::Query as ExtraQuery;
 use schemars::JsonSchema;
-use shared::{AppError, AppState};
+use shared::{audit, client::ClientInfo, AppError, AppState};
 use sqlx::PgPool;
 use uuid::Uuid;
 use validator::Validate;
@@ -20,6 +23,7 @@ use crate::categories::{Category, CategoryQuery, ChangeCategoryInput, CreateCate
 use crate::items::{
     ChangeClippingItemInput, ClippingItem, ClippingItemQuery, CreateClippingItemInput,
 };
+use crate::Clippings;
 
 #[derive(Debug, serde::Deserialize, JsonSchema)]
 pub struct PathCategory {
@@ -33,10 +37,21 @@ pub struct PathItem {
 
 #[debug_handler]
 pub async fn create_category(
-    State(pool): State<PgPool>, user: User, Json(body): Json<CreateCategoryInput>,
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, _feature: Enabled<Clippings>,
+    client: ClientInfo, Json(body): Json<CreateCategoryInput>,
 ) -> Result<impl IntoApiResponse, AppError> {
-    let mut conn = pool.acquire().await?;
-    let category = crate::categories::create_category(&mut conn, user.account_id, body).await?;
+    let mut tx = pool.begin().await?;
+    let category = crate::categories::create_category(&mut tx, user.account_id, body).await?;
+    let actor = user.actor(client);
+    audit::created(
+        &mut tx,
+        &actor,
+        "clippings.category",
+        category.id,
+        &category,
+    )
+    .await?;
+    tx.commit().await?;
     Ok((StatusCode::CREATED, Json(category)))
 }
 
@@ -50,7 +65,8 @@ pub fn create_category_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn list_categories(
-    State(pool): State<PgPool>, user: User, Query(query): Query<CategoryQuery>,
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, _feature: Enabled<Clippings>,
+    Query(query): Query<CategoryQuery>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     let categories = crate::categories::list_categories(&mut conn, user.account_id, query).await?;
@@ -68,7 +84,8 @@ pub fn list_categories_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn get_category(
-    State(pool): State<PgPool>, user: User, Path(path): Path<PathCategory>,
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, _feature: Enabled<Clippings>,
+    Path(path): Path<PathCategory>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     let category =
@@ -85,13 +102,26 @@ pub fn get_category_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn change_category(
-    State(pool): State<PgPool>, user: User, Path(path): Path<PathCategory>,
-    Json(body): Json<ChangeCategoryInput>,
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, _feature: Enabled<Clippings>,
+    client: ClientInfo, Path(path): Path<PathCategory>, Json(body): Json<ChangeCategoryInput>,
 ) -> Result<impl IntoApiResponse, AppError> {
-    let mut conn = pool.acquire().await?;
+    let mut tx = pool.begin().await?;
+    let before =
+        crate::categories::get_category_by_id(&mut tx, path.category_id, user.account_id).await?;
     let category =
-        crate::categories::change_category(&mut conn, path.category_id, user.account_id, body)
+        crate::categories::change_category(&mut tx, path.category_id, user.account_id, body)
             .await?;
+    let actor = user.actor(client);
+    audit::changed(
+        &mut tx,
+        &actor,
+        "clippings.category",
+        category.id,
+        &before,
+        &category,
+    )
+    .await?;
+    tx.commit().await?;
     Ok((StatusCode::OK, Json(category)))
 }
 
@@ -105,10 +135,22 @@ pub fn change_category_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn delete_category(
-    State(pool): State<PgPool>, user: User, Path(path): Path<PathCategory>,
+    State(pool): State<PgPool>, user: Authorized<DeleteContent>, _feature: Enabled<Clippings>,
+    client: ClientInfo, Path(path): Path<PathCategory>,
 ) -> Result<impl IntoApiResponse, AppError> {
-    let mut conn = pool.acquire().await?;
-    crate::categories::delete_category(&mut conn, path.category_id, user.account_id).await?;
+    let mut tx = pool.begin().await?;
+    let category =
+        crate::categories::delete_category(&mut tx, path.category_id, user.account_id).await?;
+    let actor = user.actor(client);
+    audit::deleted(
+        &mut tx,
+        &actor,
+        "clippings.category",
+        category.id,
+        &category,
+    )
+    .await?;
+    tx.commit().await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -122,12 +164,21 @@ pub fn delete_category_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn create_clipping_item(
-    State(pool): State<PgPool>, user: User, Json(body): Json<CreateClippingItemInput>,
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, _feature: Enabled<Clippings>,
+    client: ClientInfo, Json(body): Json<CreateClippingItemInput>,
 ) -> Result<impl IntoApiResponse, AppError> {
     body.validate()?;
-    let mut conn = pool.acquire().await?;
-    let item =
-        crate::items::create_clipping_item(&mut conn, user.account_id, user.id, body).await?;
+    let mut tx = pool.begin().await?;
+    let item = crate::items::create_clipping_item(&mut tx, user.account_id, user.id, body).await?;
+    audit::created(
+        &mut tx,
+        &user.actor(client),
+        "clippings.item",
+        item.id,
+        &item,
+    )
+    .await?;
+    tx.commit().await?;
     Ok((StatusCode::CREATED, Json(item)))
 }
 
@@ -141,7 +192,8 @@ pub fn create_clipping_item_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn list_clipping_items(
-    State(pool): State<PgPool>, user: User, ExtraQuery(query): ExtraQuery<ClippingItemQuery>,
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, _feature: Enabled<Clippings>,
+    ExtraQuery(query): ExtraQuery<ClippingItemQuery>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     let items = crate::items::list_clipping_items(&mut conn, user.account_id, query).await?;
@@ -157,7 +209,8 @@ pub fn list_clipping_items_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn get_clipping_item(
-    State(pool): State<PgPool>, user: User, Path(path): Path<PathItem>,
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, _feature: Enabled<Clippings>,
+    Path(path): Path<PathItem>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let mut conn = pool.acquire().await?;
     let item = crate::items::get_clipping_item(&mut conn, user.account_id, path.item_id).await?;
@@ -173,12 +226,23 @@ pub fn get_clipping_item_docs(op: TransformOperation) -> TransformOperation {
 
 #[debug_handler]
 pub async fn change_clipping_item(
-    State(pool): State<PgPool>, user: User, Path(path): Path<PathItem>,
-    Json(body): Json<ChangeClippingItemInput>,
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, _feature: Enabled<Clippings>,
+    client: ClientInfo, Path(path): Path<PathItem>, Json(body): Json<ChangeClippingItemInput>,
 ) -> Result<impl IntoApiResponse, AppError> {
-    let mut conn = pool.acquire().await?;
+    let mut tx = pool.begin().await?;
+    let before = crate::items::get_clipping_item(&mut tx, user.account_id, path.item_id).await?;
     let item =
-        crate::items::change_clipping_item(&mut conn, user.account_id, path.item_id, body).await?;
+        crate::items::change_clipping_item(&mut tx, user.account_id, path.item_id, body).await?;
+    audit::changed(
+        &mut tx,
+        &user.actor(client),
+        "clippings.item",
+        item.id,
+        &before,
+        &item,
+    )
+    .await?;
+    tx.commit().await?;
     Ok((StatusCode::OK, Json(item)))
 }
 
@@ -229,5 +293,5 @@ pub fn routes() -> ApiRouter<AppState> {
         .api_route(
             "/items/:item_id",
             patch_with(change_clipping_item, change_clipping_item_docs),
-        )        
+        )
 }
@@ -1,5 +1,5 @@
 use accounts::authenticate_user_with_password;
-use clippings::routes;
+use clippings::{public_routes, routes};
 use shared::testing::test_app;
 
 async fn test_token(pool: &sqlx::PgPool) -> String {
@@ -91,3 +91,32 @@ async fn test_delete_category(pool: sqlx::PgPool) {
 
     assert_eq!(response.status(), 204);
 }
+
+#[sqlx::test(
+    migrations = "../../migrations",
+    fixtures("account", "categories", "clippings_disabled")
+)]
+async fn test_clippings_are_gated_by_their_feature_flag(pool: sqlx::PgPool) {
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool.clone(), routes()).await;
+    let public_address = test_app(pool, public_routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{address}/categories"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+
+    let response = client
+        .get(format!("{public_address}/categories"))
+        .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}
@@ -0,0 +1,2 @@
+UPDATE accounts.accounts SET feature_flags = '{"clipping_enabled": false}'
+	WHERE id = '263b6188-aac6-45e9-9a2c-4728fdfd7ea1';
@@ -0,0 +1,131 @@
+use chrono::NaiveDateTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct LeadNote {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub lead_id: Uuid,
+    pub body: String,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateLeadNoteInput {
+    pub body: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ChangeLeadNoteInput {
+    pub body: Option<String>,
+}
+
+/// Notes are only created when the lead belongs to the given account.
+pub async fn create_lead_note(
+    conn: &mut PgConnection, account_id: Uuid, lead_id: Uuid, user_id: Uuid,
+    input: CreateLeadNoteInput,
+) -> Result<LeadNote, sqlx::Error> {
+    sqlx::query_as!(
+        LeadNote,
+        r#"
+        INSERT INTO leads.lead_notes (user_id, lead_id, body)
+        SELECT $3, id, $4 FROM leads.leads
+        WHERE account_id = $1 AND id = $2
+        RETURNING *
+        "#,
+        account_id,
+        lead_id,
+        user_id,
+        input.body,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn list_lead_notes(
+    conn: &mut PgConnection, account_id: Uuid, lead_id: Uuid,
+) -> Result<Vec<LeadNote>, sqlx::Error> {
+    sqlx::query_as!(
+        LeadNote,
+        r#"
+        SELECT ln.* FROM leads.lead_notes ln
+        INNER JOIN leads.leads l ON l.id = ln.lead_id
+        WHERE l.account_id = $1
+        AND l.id = $2
+        ORDER BY ln.id
+        "#,
+        account_id,
+        lead_id
+    )
+    .fetch_all(conn)
+    .await
+}
+
+pub async fn get_lead_note(
+    conn: &mut PgConnection, account_id: Uuid, lead_id: Uuid, id: Uuid,
+) -> Result<LeadNote, sqlx::Error> {
+    sqlx::query_as!(
+        LeadNote,
+        r#"
+        SELECT ln.* FROM leads.lead_notes ln
+        INNER JOIN leads.leads l ON l.id = ln.lead_id
+        WHERE l.account_id = $1
+        AND l.id = $2
+        AND ln.id = $3
+        "#,
+        account_id,
+        lead_id,
+        id
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn change_lead_note(
+    conn: &mut PgConnection, account_id: Uuid, lead_id: Uuid, id: Uuid, input: ChangeLeadNoteInput,
+) -> Result<LeadNote, sqlx::Error> {
+    sqlx::query_as!(
+        LeadNote,
+        r#"
+        UPDATE leads.lead_notes
+        SET body = COALESCE($4, body)
+        FROM leads.leads
+        WHERE leads.lead_notes.id = $3
+        AND leads.lead_notes.lead_id = leads.leads.id
+        AND leads.leads.id = $2
+        AND leads.leads.account_id = $1
+        RETURNING leads.lead_notes.*
+        "#,
+        account_id,
+        lead_id,
+        id,
+        input.body,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn delete_lead_note(
+    conn: &mut PgConnection, account_id: Uuid, lead_id: Uuid, id: Uuid,
+) -> Result<LeadNote, sqlx::Error> {
+    sqlx::query_as!(
+        LeadNote,
+        r#"
+        DELETE FROM leads.lead_notes
+        USING leads.leads
+        WHERE leads.lead_notes.id = $3
+        AND leads.lead_notes.lead_id = leads.leads.id
+        AND leads.leads.id = $2
+        AND leads.leads.account_id = $1
+        RETURNING leads.lead_notes.*
+        "#,
+        account_id,
+        lead_id,
+        id
+    )
+    .fetch_one(conn)
+    .await
+}
@@ -0,0 +1,185 @@
+use chrono::NaiveDateTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use shared::pagination::CursorPagination;
+use sqlx::PgConnection;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+/// The statuses a lead can move through, `new` is the default when the lead is submitted.
+pub const LEAD_STATUSES: [&str; 5] = ["new", "contacted", "qualified", "won", "lost"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Lead {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub account_id: Uuid,
+    pub assigned_to_id: Option<Uuid>,
+    pub archived_at: Option<NaiveDateTime>,
+    pub status: String,
+    pub data: Option<serde_json::Value>,
+    pub updated_at: NaiveDateTime,
+}
+
+/// CreateLeadInput is what a public contact form submits.
+/// The data is stored as is, so each site can define its own form fields.
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreateLeadInput {
+    pub data: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct ChangeLeadStatusInput {
+    #[validate(custom = "validate_lead_status")]
+    pub status: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct AssignLeadInput {
+    /// The user to assign the lead to, null removes the current assignee.
+    pub assigned_to_id: Option<Uuid>,
+}
+
+#[serde_as]
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct LeadQuery {
+    pub status: Option<String>,
+    pub assigned_to_id: Option<Uuid>,
+    #[serde_as(as = "DisplayFromStr")]
+    #[schemars(with = "bool")]
+    #[serde(default)]
+    /// Lists archived leads instead of the active ones. Default is false.
+    pub archived: bool,
+
+    #[serde(default, flatten)]
+    pub pagination: CursorPagination,
+}
+
+fn validate_lead_status(status: &str) -> Result<(), ValidationError> {
+    if LEAD_STATUSES.contains(&status) {
+        return Ok(());
+    }
+    Err(ValidationError::new("invalid_lead_status"))
+}
+
+pub async fn create_lead(
+    conn: &mut PgConnection, account_id: Uuid, input: CreateLeadInput,
+) -> Result<Lead, sqlx::Error> {
+    sqlx::query_as!(
+        Lead,
+        r#"
+        INSERT INTO leads.leads (account_id, data)
+        VALUES ($1, $2)
+        RETURNING *
+        "#,
+        account_id,
+        input.data,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn get_lead(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid,
+) -> Result<Lead, sqlx::Error> {
+    sqlx::query_as!(
+        Lead,
+        r#"
+        SELECT * FROM leads.leads
+        WHERE account_id = $1 AND id = $2
+        "#,
+        account_id,
+        id
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn list_leads(
+    conn: &mut PgConnection, account_id: Uuid, query: LeadQuery,
+) -> Result<Vec<Lead>, sqlx::Error> {
+    sqlx::query_as!(
+        Lead,
+        r#"
+        SELECT * FROM leads.leads
+        WHERE account_id = $1
+        AND ($2::text IS NULL OR status = $2)
+        AND ($3::uuid IS NULL OR assigned_to_id = $3)
+        AND (archived_at IS NOT NULL) = $4
+        AND ($5::text IS NULL OR id < $5::uuid)
+        ORDER BY id DESC LIMIT $6
+        "#,
+        account_id,
+        query.status,
+        query.assigned_to_id,
+        query.archived,
+        query.pagination.after,
+        query.pagination.take
+    )
+    .fetch_all(conn)
+    .await
+}
+
+pub async fn change_lead_status(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid, input: ChangeLeadStatusInput,
+) -> Result<Lead, sqlx::Error> {
+    sqlx::query_as!(
+        Lead,
+        r#"
+        UPDATE leads.leads
+        SET status = $3
+        WHERE account_id = $1 AND id = $2
+        RETURNING *
+        "#,
+        account_id,
+        id,
+        input.status,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Assigns the lead to a user, the user must belong to the same account as the lead.
+pub async fn assign_lead(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid, input: AssignLeadInput,
+) -> Result<Lead, sqlx::Error> {
+    sqlx::query_as!(
+        Lead,
+        r#"
+        UPDATE leads.leads
+        SET assigned_to_id = $3
+        WHERE account_id = $1 AND id = $2
+        AND ($3::uuid IS NULL OR EXISTS (
+            SELECT 1 FROM accounts.users
+            WHERE id = $3 AND account_id = $1 AND removed_at IS NULL
+        ))
+        RETURNING *
+        "#,
+        account_id,
+        id,
+        input.assigned_to_id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Archives or unarchives a lead, archiving keeps the original archived_at if it was already set.
+pub async fn set_lead_archived(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid, archived: bool,
+) -> Result<Lead, sqlx::Error> {
+    sqlx::query_as!(
+        Lead,
+        r#"
+        UPDATE leads.leads
+        SET archived_at = CASE WHEN $3 THEN COALESCE(archived_at, NOW()) ELSE NULL END
+        WHERE account_id = $1 AND id = $2
+        RETURNING *
+        "#,
+        account_id,
+        id,
+        archived,
+    )
+    .fetch_one(conn)
+    .await
+}
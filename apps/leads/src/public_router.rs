@@ -0,0 +1,31 @@
+use accounts::{scopes::LeadsWrite, ScopedAccount};
+use aide::{
+    axum::{routing::post_with, ApiRouter, IntoApiResponse},
+    transform::TransformOperation,
+};
+use axum::http::StatusCode;
+use axum::{extract::State, Json};
+use shared::{AppError, AppState};
+use sqlx::PgPool;
+
+use crate::leads::{CreateLeadInput, Lead};
+
+pub async fn create_lead(
+    State(pool): State<PgPool>, account: ScopedAccount<LeadsWrite>,
+    Json(input): Json<CreateLeadInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let lead = crate::leads::create_lead(&mut conn, account.id, input).await?;
+    Ok((StatusCode::CREATED, Json(lead)))
+}
+
+pub fn create_lead_docs(op: TransformOperation) -> TransformOperation {
+    op.id("create_lead")
+        .description("Submit a lead, usually from a contact form")
+        .response::<201, Json<Lead>>()
+        .tag("leads")
+}
+
+pub fn public_routes() -> ApiRouter<AppState> {
+    ApiRouter::new().api_route("/", post_with(create_lead, create_lead_docs))
+}
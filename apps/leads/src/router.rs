@@ -0,0 +1,326 @@
+use accounts::{
+    permissions::{ManageLeads, ReadContent},
+    Authorized,
+};
+use aide::axum::routing::{delete_with, get_with, patch_with, post_with};
+use aide::axum::{ApiRouter, IntoApiResponse};
+use aide::transform::TransformOperation;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::{debug_handler, Json};
+use schemars::JsonSchema;
+use shared::{AppError, AppState};
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::lead_notes::{ChangeLeadNoteInput, CreateLeadNoteInput, LeadNote};
+use crate::leads::{AssignLeadInput, ChangeLeadStatusInput, Lead, LeadQuery};
+
+/// routes for leads
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PathLead {
+    pub lead_id: Uuid,
+}
+
+fn lead_not_found(e: sqlx::Error) -> AppError {
+    match e {
+        sqlx::Error::RowNotFound => AppError::NotFound("Lead not found".into()),
+        e => e.into(),
+    }
+}
+
+#[debug_handler]
+pub async fn list_leads(
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Query(query): Query<LeadQuery>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let leads = crate::leads::list_leads(&mut conn, user.account_id, query).await?;
+    Ok((StatusCode::OK, Json(leads)))
+}
+
+pub fn list_leads_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_leads")
+        .description("List leads, filtered by status, assignee and whether they are archived")
+        .response::<200, Json<Vec<Lead>>>()
+        .security_requirement("Bearer")
+        .tag("leads")
+}
+
+#[debug_handler]
+pub async fn get_lead(
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Path(path): Path<PathLead>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let lead = crate::leads::get_lead(&mut conn, user.account_id, path.lead_id)
+        .await
+        .map_err(lead_not_found)?;
+    Ok((StatusCode::OK, Json(lead)))
+}
+
+pub fn get_lead_docs(op: TransformOperation) -> TransformOperation {
+    op.id("get_lead")
+        .description("Get a single lead")
+        .response::<200, Json<Lead>>()
+        .security_requirement("Bearer")
+        .tag("leads")
+}
+
+#[debug_handler]
+pub async fn change_lead_status(
+    State(pool): State<PgPool>, user: Authorized<ManageLeads>, Path(path): Path<PathLead>,
+    Json(body): Json<ChangeLeadStatusInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    let mut conn = pool.acquire().await?;
+    let lead = crate::leads::change_lead_status(&mut conn, user.account_id, path.lead_id, body)
+        .await
+        .map_err(lead_not_found)?;
+    Ok((StatusCode::OK, Json(lead)))
+}
+
+pub fn change_lead_status_docs(op: TransformOperation) -> TransformOperation {
+    op.id("change_lead_status")
+        .description("Change the status of a lead")
+        .response::<200, Json<Lead>>()
+        .security_requirement("Bearer")
+        .tag("leads")
+}
+
+#[debug_handler]
+pub async fn assign_lead(
+    State(pool): State<PgPool>, user: Authorized<ManageLeads>, Path(path): Path<PathLead>,
+    Json(body): Json<AssignLeadInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    // No row is updated when the lead is missing or the assignee is a user of another account.
+    let lead = match crate::leads::assign_lead(&mut conn, user.account_id, path.lead_id, body).await
+    {
+        Err(sqlx::Error::RowNotFound) => {
+            crate::leads::get_lead(&mut conn, user.account_id, path.lead_id)
+                .await
+                .map_err(lead_not_found)?;
+            return Err(AppError::BadRequest(
+                "The assignee is not a user of the account".into(),
+            ));
+        }
+        lead => lead?,
+    };
+    Ok((StatusCode::OK, Json(lead)))
+}
+
+pub fn assign_lead_docs(op: TransformOperation) -> TransformOperation {
+    op.id("assign_lead")
+        .description("Assign a lead to a user of the account")
+        .response::<200, Json<Lead>>()
+        .security_requirement("Bearer")
+        .tag("leads")
+}
+
+#[debug_handler]
+pub async fn archive_lead(
+    State(pool): State<PgPool>, user: Authorized<ManageLeads>, Path(path): Path<PathLead>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let lead = crate::leads::set_lead_archived(&mut conn, user.account_id, path.lead_id, true)
+        .await
+        .map_err(lead_not_found)?;
+    Ok((StatusCode::OK, Json(lead)))
+}
+
+pub fn archive_lead_docs(op: TransformOperation) -> TransformOperation {
+    op.id("archive_lead")
+        .description("Archive a lead")
+        .response::<200, Json<Lead>>()
+        .security_requirement("Bearer")
+        .tag("leads")
+}
+
+#[debug_handler]
+pub async fn unarchive_lead(
+    State(pool): State<PgPool>, user: Authorized<ManageLeads>, Path(path): Path<PathLead>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let lead = crate::leads::set_lead_archived(&mut conn, user.account_id, path.lead_id, false)
+        .await
+        .map_err(lead_not_found)?;
+    Ok((StatusCode::OK, Json(lead)))
+}
+
+pub fn unarchive_lead_docs(op: TransformOperation) -> TransformOperation {
+    op.id("unarchive_lead")
+        .description("Unarchive a lead")
+        .response::<200, Json<Lead>>()
+        .security_requirement("Bearer")
+        .tag("leads")
+}
+
+/// routes for lead notes
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PathLeadNote {
+    pub lead_id: Uuid,
+    pub note_id: Uuid,
+}
+
+fn lead_note_not_found(e: sqlx::Error) -> AppError {
+    match e {
+        sqlx::Error::RowNotFound => AppError::NotFound("Lead note not found".into()),
+        e => e.into(),
+    }
+}
+
+#[debug_handler]
+pub async fn create_lead_note(
+    State(pool): State<PgPool>, user: Authorized<ManageLeads>, Path(path): Path<PathLead>,
+    Json(body): Json<CreateLeadNoteInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let note = crate::lead_notes::create_lead_note(
+        &mut conn,
+        user.account_id,
+        path.lead_id,
+        user.id,
+        body,
+    )
+    .await
+    .map_err(lead_not_found)?;
+    Ok((StatusCode::CREATED, Json(note)))
+}
+
+pub fn create_lead_note_docs(op: TransformOperation) -> TransformOperation {
+    op.id("create_lead_note")
+        .description("Add a note to a lead")
+        .response::<201, Json<LeadNote>>()
+        .security_requirement("Bearer")
+        .tag("leads")
+}
+
+#[debug_handler]
+pub async fn list_lead_notes(
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Path(path): Path<PathLead>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    crate::leads::get_lead(&mut conn, user.account_id, path.lead_id)
+        .await
+        .map_err(lead_not_found)?;
+    let notes =
+        crate::lead_notes::list_lead_notes(&mut conn, user.account_id, path.lead_id).await?;
+    Ok((StatusCode::OK, Json(notes)))
+}
+
+pub fn list_lead_notes_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_lead_notes")
+        .description("List the notes of a lead")
+        .response::<200, Json<Vec<LeadNote>>>()
+        .security_requirement("Bearer")
+        .tag("leads")
+}
+
+#[debug_handler]
+pub async fn get_lead_note(
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Path(path): Path<PathLeadNote>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let note =
+        crate::lead_notes::get_lead_note(&mut conn, user.account_id, path.lead_id, path.note_id)
+            .await
+            .map_err(lead_note_not_found)?;
+    Ok((StatusCode::OK, Json(note)))
+}
+
+pub fn get_lead_note_docs(op: TransformOperation) -> TransformOperation {
+    op.id("get_lead_note")
+        .description("Get a single lead note")
+        .response::<200, Json<LeadNote>>()
+        .security_requirement("Bearer")
+        .tag("leads")
+}
+
+#[debug_handler]
+pub async fn change_lead_note(
+    State(pool): State<PgPool>, user: Authorized<ManageLeads>, Path(path): Path<PathLeadNote>,
+    Json(body): Json<ChangeLeadNoteInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let note = crate::lead_notes::change_lead_note(
+        &mut conn,
+        user.account_id,
+        path.lead_id,
+        path.note_id,
+        body,
+    )
+    .await
+    .map_err(lead_note_not_found)?;
+    Ok((StatusCode::OK, Json(note)))
+}
+
+pub fn change_lead_note_docs(op: TransformOperation) -> TransformOperation {
+    op.id("change_lead_note")
+        .description("Change a lead note")
+        .response::<200, Json<LeadNote>>()
+        .security_requirement("Bearer")
+        .tag("leads")
+}
+
+#[debug_handler]
+pub async fn delete_lead_note(
+    State(pool): State<PgPool>, user: Authorized<ManageLeads>, Path(path): Path<PathLeadNote>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    crate::lead_notes::delete_lead_note(&mut conn, user.account_id, path.lead_id, path.note_id)
+        .await
+        .map_err(lead_note_not_found)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn delete_lead_note_docs(op: TransformOperation) -> TransformOperation {
+    op.id("delete_lead_note")
+        .description("Delete a lead note")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("leads")
+}
+
+pub fn routes() -> ApiRouter<AppState> {
+    ApiRouter::new()
+        .api_route("/", get_with(list_leads, list_leads_docs))
+        .api_route("/:lead_id", get_with(get_lead, get_lead_docs))
+        .api_route(
+            "/:lead_id/status",
+            patch_with(change_lead_status, change_lead_status_docs),
+        )
+        .api_route(
+            "/:lead_id/assignee",
+            patch_with(assign_lead, assign_lead_docs),
+        )
+        .api_route(
+            "/:lead_id/archive",
+            post_with(archive_lead, archive_lead_docs),
+        )
+        .api_route(
+            "/:lead_id/archive",
+            delete_with(unarchive_lead, unarchive_lead_docs),
+        )
+        .api_route(
+            "/:lead_id/notes",
+            get_with(list_lead_notes, list_lead_notes_docs),
+        )
+        .api_route(
+            "/:lead_id/notes",
+            post_with(create_lead_note, create_lead_note_docs),
+        )
+        .api_route(
+            "/:lead_id/notes/:note_id",
+            get_with(get_lead_note, get_lead_note_docs),
+        )
+        .api_route(
+            "/:lead_id/notes/:note_id",
+            patch_with(change_lead_note, change_lead_note_docs),
+        )
+        .api_route(
+            "/:lead_id/notes/:note_id",
+            delete_with(delete_lead_note, delete_lead_note_docs),
+        )
+}
@@ -0,0 +1,7 @@
+INSERT INTO leads.leads (id,account_id,status,data,archived_at) VALUES
+	 ('0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1a01','263b6188-aac6-45e9-9a2c-4728fdfd7ea1','new','{"name": "Jane Doe", "email": "jane@example.com"}',NULL),
+	 ('0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1a02','263b6188-aac6-45e9-9a2c-4728fdfd7ea1','contacted','{"name": "John Doe", "email": "john@example.com"}',NULL),
+	 ('0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1a03','263b6188-aac6-45e9-9a2c-4728fdfd7ea1','lost','{"name": "Old Lead"}','2023-03-01 10:00:00');
+
+INSERT INTO leads.lead_notes (id,user_id,lead_id,body) VALUES
+	 ('0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1b01','ad38ffbe-dabe-43a1-b63a-4028e23090eb','0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1a01','Called, no answer.');
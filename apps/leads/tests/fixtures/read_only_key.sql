@@ -0,0 +1,3 @@
+-- api key: csk_readonly_0123456789abcdefghijklmnopqrstuv
+INSERT INTO accounts.api_keys (id,account_id,name,prefix,key_hash,scopes) VALUES
+	 ('0186d5c9-2c3b-4d4e-9f50-6b7c8d9e0f1a','263b6188-aac6-45e9-9a2c-4728fdfd7ea1','Read only','csk_readonly','72ffe4e70f10d2d4c95c36f221def47fd899a50593e0fdf93aa5db3e05a3cf4b','{public:read}');
@@ -0,0 +1,263 @@
+use accounts::authenticate_user_with_password;
+use leads::{public_routes, routes};
+use shared::testing::test_app;
+
+async fn test_token(pool: &sqlx::PgPool) -> String {
+    let access_token = authenticate_user_with_password(
+        pool,
+        uuid::uuid!("263b6188-aac6-45e9-9a2c-4728fdfd7ea1"),
+        "thiagovarela@consub.io".into(),
+        "123456".into(),
+    )
+    .await
+    .unwrap();
+    access_token.token
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_submit_a_public_lead(pool: sqlx::PgPool) {
+    let address = test_app(pool, public_routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{address}/"))
+        .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+        .json(&serde_json::json!({
+            "data": {
+                "name": "Jane Doe",
+                "email": "jane@example.com",
+                "message": "I would like to know more."
+            }
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+
+    let vl = response.json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(vl["status"], "new");
+    assert_eq!(vl["data"]["email"], "jane@example.com");
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "read_only_key"))]
+async fn test_submit_a_lead_requires_the_leads_scope(pool: sqlx::PgPool) {
+    let address = test_app(pool, public_routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let lead = serde_json::json!({ "data": { "email": "jane@example.com" } });
+
+    let response = client
+        .post(format!("{address}/"))
+        .header("X-API-KEY", "csk_readonly_0123456789abcdefghijklmnopqrstuv")
+        .json(&lead)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+
+    let response = client
+        .post(format!("{address}/"))
+        .header("X-API-KEY", "csk_unknown_0123456789abcdefghijklmnopqrstuv")
+        .json(&lead)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "leads"))]
+async fn test_list_leads_by_status_and_archived(pool: sqlx::PgPool) {
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{address}/?status=contacted"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+
+    let vl = response.json::<Vec<serde_json::Value>>().await.unwrap();
+    assert_eq!(vl.len(), 1);
+    assert_eq!(vl[0]["id"], "0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1a02");
+
+    let response = client
+        .get(format!("{address}/?archived=true"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+
+    let vl = response.json::<Vec<serde_json::Value>>().await.unwrap();
+    assert_eq!(vl.len(), 1);
+    assert_eq!(vl[0]["id"], "0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1a03");
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "leads"))]
+async fn test_change_status_assign_and_archive_a_lead(pool: sqlx::PgPool) {
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+    let lead_url = format!("{address}/0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1a01");
+
+    let response = client
+        .patch(format!("{lead_url}/status"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "status": "qualified" }))
+        .send()
+        .await
+        .unwrap();
+
+    let vl = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(vl["status"], "qualified");
+
+    let response = client
+        .patch(format!("{lead_url}/assignee"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "assigned_to_id": "ad38ffbe-dabe-43a1-b63a-4028e23090eb" }))
+        .send()
+        .await
+        .unwrap();
+
+    let vl = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(vl["assigned_to_id"], "ad38ffbe-dabe-43a1-b63a-4028e23090eb");
+
+    let response = client
+        .post(format!("{lead_url}/archive"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+
+    let vl = response.json::<serde_json::Value>().await.unwrap();
+    assert!(!vl["archived_at"].is_null());
+
+    let response = client
+        .delete(format!("{lead_url}/archive"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+
+    let vl = response.json::<serde_json::Value>().await.unwrap();
+    assert!(vl["archived_at"].is_null());
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "leads"))]
+async fn test_create_and_delete_lead_notes(pool: sqlx::PgPool) {
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+    let notes_url = format!("{address}/0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1a01/notes");
+
+    let response = client
+        .post(&notes_url)
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "body": "Sent the pricing sheet." }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+
+    let response = client
+        .get(&notes_url)
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+
+    let vl = response.json::<Vec<serde_json::Value>>().await.unwrap();
+    assert_eq!(vl.len(), 2);
+
+    let response = client
+        .delete(format!(
+            "{notes_url}/{}",
+            "0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1b01"
+        ))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 204);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "leads"))]
+async fn test_missing_lead_and_unknown_assignee(pool: sqlx::PgPool) {
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool.clone(), routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{address}/0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1aff"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    let response = client
+        .patch(format!(
+            "{address}/0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1aff/assignee"
+        ))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "assigned_to_id": "ad38ffbe-dabe-43a1-b63a-4028e23090eb" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    let response = client
+        .get(format!(
+            "{address}/0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1aff/notes"
+        ))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+
+    // A user of another account, or no user at all.
+    let response = client
+        .patch(format!(
+            "{address}/0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1a01/assignee"
+        ))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "assigned_to_id": "0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1aff" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    // A user removed from the account.
+    sqlx::query(
+        "INSERT INTO accounts.users (id, account_id, email, role, removed_at) VALUES ('0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1a0f', '263b6188-aac6-45e9-9a2c-4728fdfd7ea1', 'removed@consub.io', 'author', NOW())",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+    let response = client
+        .patch(format!(
+            "{address}/0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1a01/assignee"
+        ))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "assigned_to_id": "0186d5a2-41c5-4b1c-9ad4-5b7f0e5c1a0f" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
@@ -0,0 +1,156 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use shared::{pagination::CursorPagination, AppError};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::images::{account_directory, ulid};
+
+/// A document, audio or video of the media library.
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct File {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub account_id: Uuid,
+    /// The name of the file when it was uploaded.
+    pub file_name: String,
+    pub content_type: String,
+    /// In bytes.
+    pub size: i64,
+    /// The SHA-256 of the content, in hex.
+    pub checksum: String,
+    /// Where the file is stored, its url is the CDN path followed by it.
+    pub path: String,
+    pub metadata: serde_json::Value,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateFileInput {
+    pub file_name: String,
+    pub content_type: String,
+    pub size: i64,
+    pub checksum: String,
+    pub path: String,
+    pub metadata: serde_json::Value,
+}
+
+#[derive(Debug, schemars::JsonSchema, Deserialize)]
+pub struct FileQuery {
+    /// The start of the content type, e.g. `video/` or `application/pdf`.
+    pub content_type: Option<String>,
+
+    #[serde(default, flatten)]
+    pub pagination: CursorPagination,
+}
+
+/// Where the files of the account are stored, `{account}/files/{file}` with their ids as lowercase ulids.
+/// It isn't an image directory, the collection of orphaned images skips it.
+pub fn file_directory(account_id: Uuid, file_id: Uuid) -> String {
+    format!("{}/files/{}", account_directory(account_id), ulid(file_id))
+}
+
+/// The name the file is stored with, its original name without anything unsafe in a url or a header.
+pub fn storage_file_name(file_name: &str) -> String {
+    let file_name = file_name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let file_name: String = file_name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '_' | '-' => c,
+            _ => '-',
+        })
+        .collect();
+    match file_name.trim_matches(['.', '-']) {
+        "" => "file".to_string(),
+        file_name => file_name.to_string(),
+    }
+}
+
+/// The metadata sent with an upload, it must be a JSON object.
+pub fn parse_metadata(metadata: &str) -> Result<serde_json::Value, AppError> {
+    match serde_json::from_str(metadata) {
+        Ok(metadata @ serde_json::Value::Object(_)) => Ok(metadata),
+        _ => Err(AppError::BadRequest(
+            "The metadata must be a JSON object".into(),
+        )),
+    }
+}
+
+pub async fn create_file(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid, input: CreateFileInput,
+) -> Result<File, sqlx::Error> {
+    sqlx::query_as!(
+        File,
+        r#"
+        INSERT INTO media.files (id, account_id, file_name, content_type, size, checksum, path, metadata)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING *
+        "#,
+        id,
+        account_id,
+        input.file_name,
+        input.content_type,
+        input.size,
+        input.checksum,
+        input.path,
+        input.metadata,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn get_file(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid,
+) -> Result<File, sqlx::Error> {
+    sqlx::query_as!(
+        File,
+        r#"
+        SELECT * FROM media.files
+        WHERE account_id = $1 AND id = $2
+        "#,
+        account_id,
+        id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn list_files(
+    conn: &mut PgConnection, account_id: Uuid, query: FileQuery,
+) -> Result<Vec<File>, sqlx::Error> {
+    sqlx::query_as!(
+        File,
+        r#"
+        SELECT * FROM media.files
+        WHERE account_id = $1
+        AND ($2::text IS NULL OR starts_with(content_type, $2::text))
+        AND ($3::text IS NULL OR id > $3::uuid)
+        ORDER BY id LIMIT $4
+        "#,
+        account_id,
+        query.content_type,
+        query.pagination.after,
+        query.pagination.take,
+    )
+    .fetch_all(conn)
+    .await
+}
+
+/// Deletes the file, it's removed from the storage by the caller.
+pub async fn delete_file(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid,
+) -> Result<File, sqlx::Error> {
+    sqlx::query_as!(
+        File,
+        r#"
+        DELETE FROM media.files
+        WHERE account_id = $1 AND id = $2
+        RETURNING *
+        "#,
+        account_id,
+        id,
+    )
+    .fetch_one(conn)
+    .await
+}
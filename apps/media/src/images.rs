@@ -3,6 +3,7 @@ use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use shared::pagination::CursorPagination;
 use sqlx::PgConnection;
+use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
@@ -23,10 +24,23 @@ pub struct ImageSet {
     pub image_id: Uuid,
     pub size: String,
     pub path: String,
+    /// The file extension, e.g. `webp`. Empty on images resized by the admin before uploading.
+    pub format: Option<String>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
+#[derive(Debug, Clone)]
+pub struct CreateImageSetInput {
+    pub size: String,
+    pub path: String,
+    pub format: String,
+    pub width: i32,
+    pub height: i32,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
 pub struct ImageResponse {
     id: Uuid,
@@ -56,6 +70,71 @@ pub struct ImageQuery {
     pub pagination: CursorPagination,
 }
 
+pub(crate) fn ulid(id: Uuid) -> String {
+    rusty_ulid::Ulid::from(id.as_u128())
+        .to_string()
+        .to_lowercase()
+}
+
+/// Where the files of the images of an account are stored, its id as a lowercase ulid.
+pub fn account_directory(account_id: Uuid) -> String {
+    ulid(account_id)
+}
+
+/// Where the files of an image are stored, `{account}/{image}` with their ids as lowercase ulids.
+pub fn image_directory(account_id: Uuid, image_id: Uuid) -> String {
+    format!("{}/{}", account_directory(account_id), ulid(image_id))
+}
+
+/// The id of the image stored in a directory of the account directory.
+pub fn image_id_from_directory(name: &str) -> Option<Uuid> {
+    let ulid: rusty_ulid::Ulid = name.trim_end_matches('/').to_uppercase().parse().ok()?;
+    Some(Uuid::from_u128(u128::from(ulid)))
+}
+
+/// Whether the image id, a ulid, was generated within the last minutes.
+/// Tells the age of the files of an image which row isn't written yet, or was never written.
+pub fn is_recent_image_id(id: Uuid, minutes: i32) -> bool {
+    let created_at = rusty_ulid::Ulid::from(id.as_u128()).timestamp();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as u64)
+        .unwrap_or_default();
+    now.saturating_sub(created_at) < minutes as u64 * 60_000
+}
+
+/// Where an image is used, it can't be deleted until detached.
+#[derive(Debug, Clone, Default)]
+pub struct ImageReferences {
+    pub post_images: i64,
+    pub page_images: i64,
+    pub avatars: i64,
+}
+
+impl ImageReferences {
+    pub fn is_empty(&self) -> bool {
+        self.post_images == 0 && self.page_images == 0 && self.avatars == 0
+    }
+}
+
+impl std::fmt::Display for ImageReferences {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let references: Vec<String> = [
+            (self.post_images, "post"),
+            (self.page_images, "page"),
+            (self.avatars, "user avatar"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, name)| match count {
+            1 => format!("1 {name}"),
+            count => format!("{count} {name}s"),
+        })
+        .collect();
+        write!(f, "{}", references.join(", "))
+    }
+}
+
 pub async fn create_image(
     conn: &mut PgConnection, account_id: Uuid, id: Uuid,
 ) -> Result<Image, sqlx::Error> {
@@ -89,6 +168,39 @@ pub async fn get_image(
     .await
 }
 
+pub async fn find_image(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid,
+) -> Result<Option<Image>, sqlx::Error> {
+    sqlx::query_as!(
+        Image,
+        r#"
+        SELECT * FROM media.images
+        WHERE account_id = $1 AND id = $2
+        "#,
+        account_id,
+        id,
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// Whether the image was uploaded within the last minutes, by the clock of the database.
+pub async fn is_recent_image(
+    conn: &mut PgConnection, id: Uuid, minutes: i32,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT created_at > LOCALTIMESTAMP - make_interval(mins => $2) as "recent!"
+        FROM media.images
+        WHERE id = $1
+        "#,
+        id,
+        minutes,
+    )
+    .fetch_one(conn)
+    .await
+}
+
 pub async fn change_image(
     conn: &mut PgConnection, account_id: Uuid, id: Uuid, input: ChangeImageInput,
 ) -> Result<Image, sqlx::Error> {
@@ -110,24 +222,165 @@ pub async fn change_image(
     .await
 }
 
+/// Deletes the image and its image set, the files are removed from the storage by the caller.
+pub async fn delete_image(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid,
+) -> Result<Image, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM media.images_set
+        WHERE image_id = (SELECT id FROM media.images WHERE account_id = $1 AND id = $2)
+        "#,
+        account_id,
+        id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query_as!(
+        Image,
+        r#"
+        DELETE FROM media.images
+        WHERE account_id = $1 AND id = $2
+        RETURNING *
+        "#,
+        account_id,
+        id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Counts the posts, pages and user avatars of the account using the image.
+pub async fn get_image_references(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid,
+) -> Result<ImageReferences, sqlx::Error> {
+    let references = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM blogs.post_images pi
+                JOIN blogs.posts p ON (p.id = pi.post_id)
+                WHERE p.account_id = $1 AND pi.media_id = $2) as "post_images!",
+            (SELECT COUNT(*) FROM pages.page_images pi
+                JOIN pages.pages p ON (p.id = pi.page_id)
+                WHERE p.account_id = $1 AND pi.media_id = $2) as "page_images!",
+            (SELECT COUNT(*) FROM accounts.users
+                WHERE account_id = $1 AND avatar_media_id = $2) as "avatars!"
+        "#,
+        account_id,
+        id,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(ImageReferences {
+        post_images: references.post_images,
+        page_images: references.page_images,
+        avatars: references.avatars,
+    })
+}
+
+/// Removes the image from the posts and pages of the account, and from the avatars of its users.
+pub async fn detach_image(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM blogs.post_images pi
+        USING blogs.posts p
+        WHERE p.id = pi.post_id AND p.account_id = $1 AND pi.media_id = $2
+        "#,
+        account_id,
+        id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM pages.page_images pi
+        USING pages.pages p
+        WHERE p.id = pi.page_id AND p.account_id = $1 AND pi.media_id = $2
+        "#,
+        account_id,
+        id,
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE accounts.users
+        SET avatar_media_id = NULL
+        WHERE account_id = $1 AND avatar_media_id = $2
+        "#,
+        account_id,
+        id,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn create_image_set(
-    conn: &mut PgConnection, image_id: Uuid, size: String, path: String,
+    conn: &mut PgConnection, image_id: Uuid, input: CreateImageSetInput,
 ) -> Result<ImageSet, sqlx::Error> {
     sqlx::query_as!(
         ImageSet,
         r#"
-        INSERT INTO media.images_set (image_id, size, path)
-        VALUES ($1, $2, $3)
+        INSERT INTO media.images_set (image_id, size, path, format, width, height)
+        VALUES ($1, $2, $3, $4, $5, $6)
         RETURNING *
         "#,
         image_id,
-        size,
-        path,
+        input.size,
+        input.path,
+        input.format,
+        input.width,
+        input.height,
     )
     .fetch_one(conn)
     .await
 }
 
+/// The uploaded original, images resized by the admin before uploading have none.
+pub async fn get_original_image_set(
+    conn: &mut PgConnection, image_id: Uuid,
+) -> Result<Option<ImageSet>, sqlx::Error> {
+    sqlx::query_as!(
+        ImageSet,
+        r#"
+        SELECT * FROM media.images_set
+        WHERE image_id = $1 AND size = 'original' AND format IS NOT NULL
+        ORDER BY id
+        LIMIT 1
+        "#,
+        image_id
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// Removes the generated variants of an image, keeping the uploaded original.
+pub async fn delete_image_variants(
+    conn: &mut PgConnection, image_id: Uuid, original_format: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM media.images_set
+        WHERE image_id = $1
+        AND NOT (size = 'original' AND format IS NOT DISTINCT FROM $2)
+        "#,
+        image_id,
+        original_format,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn get_image_set(
     conn: &mut PgConnection, image_id: Uuid,
 ) -> Result<Vec<ImageSet>, sqlx::Error> {
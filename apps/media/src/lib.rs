@@ -1,6 +1,14 @@
+mod files;
 mod images;
+mod orphans;
+mod presets;
 mod public_router;
 mod router;
+mod transforms;
+mod uploads;
+mod variants;
 
+pub use orphans::CollectOrphanedImages;
 pub use public_router::public_routes;
 pub use router::routes;
+pub use variants::GenerateImageVariants;
@@ -0,0 +1,74 @@
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use shared::AppState;
+use uuid::Uuid;
+use workers::Job;
+
+use crate::images::{
+    account_directory, find_image, get_image_set, image_id_from_directory, is_recent_image,
+    is_recent_image_id,
+};
+
+/// The files of images uploaded within these minutes are kept, their rows may not be written yet.
+const UPLOAD_GRACE_MINUTES: i32 = 60;
+
+/// Removes the files of the account no image refers to, left by failed uploads and deletions.
+/// The files of images uploaded within the last hour are kept, their rows may not be written yet.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CollectOrphanedImages {
+    pub account_id: Uuid,
+}
+
+#[axum::async_trait]
+impl Job for CollectOrphanedImages {
+    const NAME: &'static str = "media.collect_orphaned_images";
+
+    fn account_id(&self) -> Option<Uuid> {
+        Some(self.account_id)
+    }
+
+    async fn run(self, state: &AppState) -> Result<(), anyhow::Error> {
+        let mut removed = 0;
+
+        let account_directory = format!("{}/", account_directory(self.account_id));
+        let mut directories = state.opendal.list(&account_directory).await?;
+        while let Some(directory) = directories.try_next().await? {
+            let image_id = match image_id_from_directory(directory.name()) {
+                Some(image_id) => image_id,
+                None => continue,
+            };
+
+            let mut conn = state.db_pool.acquire().await?;
+            match find_image(&mut conn, self.account_id, image_id).await? {
+                None => {
+                    // Without a row, the upload time is the one of the id.
+                    if is_recent_image_id(image_id, UPLOAD_GRACE_MINUTES) {
+                        continue;
+                    }
+                    state.opendal.remove_all(directory.path()).await?;
+                    removed += 1;
+                }
+                Some(image) => {
+                    if is_recent_image(&mut conn, image.id, UPLOAD_GRACE_MINUTES).await? {
+                        continue;
+                    }
+                    let image_set = get_image_set(&mut conn, image.id).await?;
+                    let mut files = state.opendal.list(directory.path()).await?;
+                    while let Some(file) = files.try_next().await? {
+                        // Transformed images are only removed with their image.
+                        if file.path().ends_with('/') {
+                            continue;
+                        }
+                        if !image_set.iter().any(|set| set.path.ends_with(file.path())) {
+                            state.opendal.delete(file.path()).await?;
+                            removed += 1;
+                        }
+                    }
+                }
+            }
+        }
+
+        tracing::info!(account_id = %self.account_id, removed, "orphaned image files collected");
+        Ok(())
+    }
+}
@@ -0,0 +1,182 @@
+use chrono::NaiveDateTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use shared::AppError;
+use sqlx::PgConnection;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::transforms::{image_format, Transform};
+
+/// How a transformed image fits both a width and a height.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema, sqlx::Type,
+)]
+#[sqlx(type_name = "text", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    /// Within the width and height, keeping the aspect ratio. Images aren't upscaled.
+    #[default]
+    Contain,
+    /// Covers the width and height, keeping the aspect ratio and cropping the center.
+    Cover,
+    /// Stretched to the width and height.
+    Fill,
+}
+
+impl Fit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Fit::Contain => "contain",
+            Fit::Cover => "cover",
+            Fit::Fill => "fill",
+        }
+    }
+}
+
+/// A transformation allowed on the images of the account.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ImagePreset {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub account_id: Uuid,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub fit: Fit,
+    /// `jpg`, `png`, `gif` or `webp`, the format of the original when not set.
+    pub format: Option<String>,
+    /// The quality of JPEG and WebP, the default quality when not set.
+    pub quality: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// At least a width or a height, `cover` and `fill` need both.
+#[derive(Debug, Deserialize, Validate, JsonSchema)]
+pub struct CreateImagePresetInput {
+    #[validate(range(min = 1, max = 4096))]
+    pub width: Option<i32>,
+    #[validate(range(min = 1, max = 4096))]
+    pub height: Option<i32>,
+    #[serde(default)]
+    pub fit: Fit,
+    /// `jpg`, `png`, `gif` or `webp`, the format of the original by default.
+    pub format: Option<String>,
+    /// The quality of JPEG and WebP, from 1 to 100.
+    #[validate(range(min = 1, max = 100))]
+    pub quality: Option<i32>,
+}
+
+pub async fn create_image_preset(
+    conn: &mut PgConnection, account_id: Uuid, input: CreateImagePresetInput,
+) -> Result<ImagePreset, AppError> {
+    match (input.width, input.height, input.fit) {
+        (None, None, _) => {
+            return Err(AppError::BadRequest(
+                "The preset needs a width or a height".into(),
+            ))
+        }
+        (None, _, Fit::Cover | Fit::Fill) | (_, None, Fit::Cover | Fit::Fill) => {
+            return Err(AppError::BadRequest(format!(
+                "The {} fit needs a width and a height",
+                input.fit.as_str()
+            )))
+        }
+        _ => {}
+    }
+    let format = input.format.as_deref().map(image_format).transpose()?;
+
+    let preset = sqlx::query_as!(
+        ImagePreset,
+        r#"
+        INSERT INTO media.image_presets (account_id, width, height, fit, format, quality)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (
+            account_id, COALESCE(width, 0), COALESCE(height, 0), fit,
+            COALESCE(format, ''), COALESCE(quality, 0)
+        ) DO NOTHING
+        RETURNING id, account_id, width, height, fit as "fit: Fit", format, quality,
+            created_at, updated_at
+        "#,
+        account_id,
+        input.width,
+        input.height,
+        input.fit as Fit,
+        format,
+        input.quality,
+    )
+    .fetch_optional(conn)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("The preset was already added".into()))?;
+
+    Ok(preset)
+}
+
+pub async fn list_image_presets(
+    conn: &mut PgConnection, account_id: Uuid,
+) -> Result<Vec<ImagePreset>, sqlx::Error> {
+    sqlx::query_as!(
+        ImagePreset,
+        r#"
+        SELECT id, account_id, width, height, fit as "fit: Fit", format, quality,
+            created_at, updated_at
+        FROM media.image_presets
+        WHERE account_id = $1
+        ORDER BY width NULLS FIRST, height NULLS FIRST, fit, format NULLS FIRST, quality NULLS FIRST
+        "#,
+        account_id,
+    )
+    .fetch_all(conn)
+    .await
+}
+
+/// The preset allowing the transformation of an original, if any. A preset without a format or
+/// a quality allows the ones of the original and the default quality.
+pub async fn find_image_preset(
+    conn: &mut PgConnection, account_id: Uuid, transform: &Transform, original_format: &str,
+    default_quality: u8,
+) -> Result<Option<ImagePreset>, sqlx::Error> {
+    sqlx::query_as!(
+        ImagePreset,
+        r#"
+        SELECT id, account_id, width, height, fit as "fit: Fit", format, quality,
+            created_at, updated_at
+        FROM media.image_presets
+        WHERE account_id = $1
+        AND width IS NOT DISTINCT FROM $2
+        AND height IS NOT DISTINCT FROM $3
+        AND fit = $4
+        AND COALESCE(format, $5) = $6
+        AND COALESCE(quality, $7) = $8
+        LIMIT 1
+        "#,
+        account_id,
+        transform.width.map(|width| width as i32),
+        transform.height.map(|height| height as i32),
+        transform.fit as Fit,
+        original_format,
+        transform.format,
+        default_quality as i32,
+        transform.quality as i32,
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+pub async fn delete_image_preset(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid,
+) -> Result<ImagePreset, sqlx::Error> {
+    sqlx::query_as!(
+        ImagePreset,
+        r#"
+        DELETE FROM media.image_presets
+        WHERE account_id = $1 AND id = $2
+        RETURNING id, account_id, width, height, fit as "fit: Fit", format, quality,
+            created_at, updated_at
+        "#,
+        account_id,
+        id,
+    )
+    .fetch_one(conn)
+    .await
+}
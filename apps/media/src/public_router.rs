@@ -3,15 +3,26 @@ use aide::{
     axum::{routing::get_with, IntoApiResponse, ApiRouter},
     transform::TransformOperation,
 };
-use axum::http::StatusCode;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::{body::StreamBody, response::IntoResponse};
 use axum::{
     extract::{Path, State},
     Json,
 };
-use shared::{AppError, AppState};
+use axum_extra::extract::Query;
+use shared::{AppError, AppState, OpendalUploader};
 use sqlx::PgPool;
 
-use crate::router::{ImageResponse, PathImage};
+use crate::files::storage_file_name;
+use crate::images::{get_original_image_set, image_directory};
+use crate::presets::find_image_preset;
+use crate::router::{file_not_found, image_not_found, ImageResponse, PathFile, PathImage};
+use crate::transforms::{Transform, TransformQuery, TRANSFORMS};
+use crate::variants::{IMAGE_QUALITY, ORIGINAL_SIZE};
+
+/// Transformed images and files never change, the same transformation of an original gives the
+/// same file and a file is replaced by uploading another one.
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
 
 pub async fn get_image(
     State(pool): State<PgPool>, account: Account, Path(path): Path<PathImage>,
@@ -31,7 +42,148 @@ pub fn get_image_docs(op: TransformOperation) -> TransformOperation {
         .tag("media")
 }
 
+fn is_not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+pub async fn transform_image(
+    State(pool): State<PgPool>, State(opendal): State<OpendalUploader>, account: Account,
+    Path(path): Path<PathImage>, Query(query): Query<TransformQuery>, headers: HeaderMap,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let image = crate::images::get_image(&mut conn, account.id, path.image_id)
+        .await
+        .map_err(image_not_found)?;
+    let original = get_original_image_set(&mut conn, image.id)
+        .await?
+        .ok_or_else(|| AppError::NotFound("The image has no original to transform".into()))?;
+    let original_format = original.format.unwrap_or_default();
+
+    let transform = Transform::from_query(query, &original_format, *IMAGE_QUALITY)?;
+    if find_image_preset(
+        &mut conn,
+        account.id,
+        &transform,
+        &original_format,
+        *IMAGE_QUALITY,
+    )
+    .await?
+    .is_none()
+    {
+        return Err(AppError::Forbidden(
+            "The transformation is not one of the image presets".into(),
+        ));
+    }
+    drop(conn);
+
+    let file_name = transform.file_name();
+    let etag = format!("\"{}-{file_name}\"", image.id.simple());
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, IMMUTABLE_CACHE_CONTROL.to_string()),
+    ];
+    if is_not_modified(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let directory = image_directory(account.id, image.id);
+    let file_path = format!("{directory}/transforms/{file_name}");
+    let data = match opendal.read(&file_path).await {
+        Ok(data) => data,
+        Err(e) if e.kind() == opendal::ErrorKind::NotFound => {
+            let _permit = TRANSFORMS.acquire().await?;
+            let original_path = format!("{directory}/{ORIGINAL_SIZE}.{original_format}");
+            let original = opendal.read(&original_path).await?;
+            let options = transform.clone();
+            let data = tokio::task::spawn_blocking(move || {
+                crate::transforms::transform_image(&original, &options)
+            })
+            .await??;
+            opendal.write(&file_path, data.clone()).await?;
+            data
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok((
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, transform.content_type())],
+        cache_headers,
+        data,
+    )
+        .into_response())
+}
+
+pub fn transform_image_docs(op: TransformOperation) -> TransformOperation {
+    op.id("transform_image")
+        .description("Get the image resized, cropped or converted. Only the sizes of the image presets of the account are allowed.")
+        .response::<200, Vec<u8>>()
+        .response::<304, ()>()
+        .tag("media")
+}
+
+/// Streams the file from the storage, for clients which can't reach the CDN.
+pub async fn download_file(
+    State(pool): State<PgPool>, State(opendal): State<OpendalUploader>, account: Account,
+    Path(path): Path<PathFile>, headers: HeaderMap,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let file = crate::files::get_file(&mut conn, account.id, path.file_id)
+        .await
+        .map_err(file_not_found)?;
+    drop(conn);
+
+    let etag = format!("\"{}\"", file.checksum);
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, IMMUTABLE_CACHE_CONTROL.to_string()),
+    ];
+    if is_not_modified(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let reader = opendal.reader(&file.path).await?;
+    let file_name = storage_file_name(&file.file_name);
+    Ok((
+        StatusCode::OK,
+        [
+            (header::CONTENT_TYPE, file.content_type),
+            (header::CONTENT_LENGTH, file.size.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("inline; filename=\"{file_name}\""),
+            ),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        cache_headers,
+        StreamBody::new(reader),
+    )
+        .into_response())
+}
+
+pub fn download_file_docs(op: TransformOperation) -> TransformOperation {
+    op.id("download_file")
+        .description("Download the file through Consub instead of the CDN")
+        .response::<200, Vec<u8>>()
+        .response::<304, ()>()
+        .tag("media")
+}
+
 pub fn public_routes() -> ApiRouter<AppState> {
     ApiRouter::new()
         .api_route("/images/:image_id", get_with(get_image, get_image_docs))        
+        .api_route(
+            "/images/:image_id/transform",
+            get_with(transform_image, transform_image_docs),
+        )
+        .api_route(
+            "/files/:file_id/download",
+            get_with(download_file, download_file_docs),
+        )
 }
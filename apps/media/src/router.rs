@@ -1,4 +1,7 @@
-use accounts::User;
+use accounts::{
+    permissions::{ManageMedia, ReadContent},
+    Authorized,
+};
 
 use axum::{
     debug_handler,
@@ -8,24 +11,35 @@ use axum::{
 };
 use axum_extra::extract::Query;
 
-use aide::axum::{routing::{get_with, post_with}, ApiRouter};
-use aide::{
-    axum::{IntoApiResponse},
-    transform::TransformOperation,
+use aide::axum::{
+    routing::{delete_with, get_with, post_with},
+    ApiRouter,
 };
+use aide::{axum::IntoApiResponse, transform::TransformOperation};
 
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use shared::{AppError, AppState, OpendalUploader};
+use shared::{audit, client::ClientInfo, AppError, AppState, OpendalUploader};
 use sqlx::PgPool;
 use uuid::Uuid;
+use validator::Validate;
 
+use crate::files::{
+    create_file, file_directory, parse_metadata, storage_file_name, CreateFileInput, File,
+    FileQuery,
+};
 use crate::images::{
-    create_image, create_image_set, get_image, get_image_set, ChangeImageInput, Image, ImageQuery,
-    ImageSet,
+    create_image, create_image_set, detach_image, get_image, get_image_references, get_image_set,
+    image_directory, ChangeImageInput, CreateImageSetInput, Image, ImageQuery, ImageSet,
 };
+use crate::orphans::CollectOrphanedImages;
+use crate::presets::{CreateImagePresetInput, ImagePreset};
+use crate::uploads::{
+    file_content_type, get_upload_limits, invalid_upload, read_field, sniff_image, store_field,
+};
+use crate::variants::{GenerateImageVariants, ORIGINAL_SIZE};
 
-static IMAGES_CDN_PATH: Lazy<String> = Lazy::new(|| {
+pub(crate) static IMAGES_CDN_PATH: Lazy<String> = Lazy::new(|| {
     std::env::var("CDN_PATH")
         .expect("CDN_PATH environment variable is not set. Please set it to the CDN path.")
 });
@@ -39,63 +53,77 @@ pub struct ImageResponse {
 
 #[debug_handler(state = AppState)]
 pub async fn upload_image(
-    State(pool): State<PgPool>, State(opendal): State<OpendalUploader>, user: User,
-    mut multipart: Multipart,
+    State(pool): State<PgPool>, State(opendal): State<OpendalUploader>,
+    user: Authorized<ManageMedia>, client: ClientInfo, mut multipart: Multipart,
 ) -> Result<impl IntoApiResponse, AppError> {
-    let id = rusty_ulid::generate_ulid_bytes();
-    let id = uuid::Uuid::from_slice(&id).unwrap();
     let account_id = user.account_id;
+    let id = Uuid::from_bytes(rusty_ulid::generate_ulid_bytes());
 
     let mut conn = pool.acquire().await?;
-    let image = create_image(&mut conn, account_id, id).await?;
+    let limits = get_upload_limits(&mut conn, account_id).await?;
     drop(conn);
 
-    let account_id_ulid = rusty_ulid::Ulid::from(account_id.as_u128())
-        .to_string()
-        .to_lowercase();
-    let image_id_ulid = rusty_ulid::Ulid::from(image.id.as_u128())
-        .to_string()
-        .to_lowercase();
-
-    while let Some(field) = multipart.next_field().await.unwrap() {
-        let name = field.name().unwrap().to_string();
-
-        let extension = match field.content_type().unwrap() {
-            "image/jpeg" => "jpg",
-            "image/png" => "png",
-            "image/gif" => "gif",
-            "image/webp" => "webp",
-            _ => continue,
-        };
+    // Only the first field is read, it's the original. The variants are generated from it in the background.
+    let mut field = multipart
+        .next_field()
+        .await
+        .map_err(invalid_upload)?
+        .ok_or_else(|| AppError::BadRequest("Upload an image".into()))?;
+    let data = read_field(&mut field, &limits).await?;
+    let original = sniff_image(&data, &limits)?;
 
-        let cdn_path = &*IMAGES_CDN_PATH;
-        let file_path = format!("{account_id_ulid}/{image_id_ulid}/{name}.{extension}");
-        let path = format!("{cdn_path}/{file_path}");
+    let directory = image_directory(account_id, id);
+    let file_path = format!("{directory}/{ORIGINAL_SIZE}.{}", original.format);
+    opendal.write(&file_path, data).await?;
 
-        let data = field.bytes().await.unwrap();
+    let saved = async {
+        let mut tx = pool.begin().await?;
+        let image = create_image(&mut tx, account_id, id).await?;
+        let input = CreateImageSetInput {
+            size: ORIGINAL_SIZE.to_string(),
+            path: format!("{}/{file_path}", &*IMAGES_CDN_PATH),
+            format: original.format.to_string(),
+            width: original.width as i32,
+            height: original.height as i32,
+        };
+        let image_set = vec![create_image_set(&mut tx, image.id, input).await?];
+        let job = GenerateImageVariants {
+            account_id,
+            image_id: image.id,
+            directory,
+            format: original.format.to_string(),
+        };
+        workers::enqueue(&mut tx, &job).await?;
 
-        opendal.write(&file_path, data).await.unwrap();
-        let mut conn = pool.acquire().await?;
-        create_image_set(&mut conn, image.id, name, path).await?;
+        let response = ImageResponse { image, image_set };
+        audit::created(&mut tx, &user.actor(client), "media.image", id, &response).await?;
+        tx.commit().await?;
+        Ok::<_, AppError>(response)
     }
+    .await;
 
-    let mut conn = pool.acquire().await?;
-    let image = get_image(&mut conn, account_id, id).await?;
-    let image_set = get_image_set(&mut conn, image.id).await?;
-
-    Ok((StatusCode::OK, Json(ImageResponse { image, image_set })))
+    // A failed upload leaves no rows, nor the stored original.
+    match saved {
+        Ok(response) => Ok((StatusCode::OK, Json(response))),
+        Err(e) => {
+            if let Err(delete_error) = opendal.delete(&file_path).await {
+                tracing::warn!(error = %delete_error, file_path, "failed to remove the original of a failed upload");
+            }
+            Err(e)
+        }
+    }
 }
 
 pub fn upload_image_docs(op: TransformOperation) -> TransformOperation {
     op.id("upload_image")
-        .description("Upload image to media library")
+        .description("Upload an original image, within the limits of the account plan. Its variants are generated in the background.")
         .response::<201, Json<ImageResponse>>()
         .tag("media")
 }
 
 #[debug_handler]
 pub async fn list_images(
-    State(pool): State<PgPool>, user: User, Query(query): Query<ImageQuery>,
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Query(query): Query<ImageQuery>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let account_id = user.account_id;
     let mut conn = pool.acquire().await?;
@@ -116,14 +144,111 @@ pub struct PathImage {
     pub image_id: Uuid,
 }
 
+pub(crate) fn image_not_found(e: sqlx::Error) -> AppError {
+    match e {
+        sqlx::Error::RowNotFound => AppError::NotFound("Image not found".into()),
+        e => e.into(),
+    }
+}
+
+#[derive(Debug, Default, Deserialize, schemars::JsonSchema)]
+pub struct DeleteImageQuery {
+    /// Removes the image from the posts, pages and user avatars using it, instead of refusing.
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[debug_handler(state = AppState)]
+pub async fn delete_image(
+    State(pool): State<PgPool>, State(opendal): State<OpendalUploader>,
+    user: Authorized<ManageMedia>, client: ClientInfo, Path(path): Path<PathImage>,
+    Query(query): Query<DeleteImageQuery>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let account_id = user.account_id;
+    let mut tx = pool.begin().await?;
+    let image = get_image(&mut tx, account_id, path.image_id)
+        .await
+        .map_err(image_not_found)?;
+
+    let references = get_image_references(&mut tx, account_id, image.id).await?;
+    if !references.is_empty() {
+        if !query.force {
+            return Err(AppError::BadRequest(format!(
+                "The image is used by {references}, delete it with force to detach it"
+            )));
+        }
+        detach_image(&mut tx, account_id, image.id).await?;
+    }
+
+    let image_set = get_image_set(&mut tx, image.id).await?;
+    crate::images::delete_image(&mut tx, account_id, image.id).await?;
+    let response = ImageResponse { image, image_set };
+    audit::deleted(
+        &mut tx,
+        &user.actor(client),
+        "media.image",
+        response.image.id,
+        &response,
+    )
+    .await?;
+    tx.commit().await?;
+
+    // Files left behind are removed by the collection of orphaned files.
+    let directory = format!("{}/", image_directory(account_id, response.image.id));
+    if let Err(e) = opendal.remove_all(&directory).await {
+        tracing::warn!(error = %e, directory, "failed to remove the files of a deleted image");
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn delete_image_docs(op: TransformOperation) -> TransformOperation {
+    op.id("delete_image")
+        .description("Delete the image and its files. Refused while posts, pages or user avatars use it, unless forced.")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("media")
+}
+
+#[debug_handler]
+pub async fn collect_orphaned_images(
+    State(pool): State<PgPool>, user: Authorized<ManageMedia>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let job = CollectOrphanedImages {
+        account_id: user.account_id,
+    };
+    let job = workers::enqueue(&mut conn, &job).await?;
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+pub fn collect_orphaned_images_docs(op: TransformOperation) -> TransformOperation {
+    op.id("collect_orphaned_images")
+        .description("Queue the removal of the stored files no image refers to")
+        .response::<202, Json<workers::QueuedJob>>()
+        .security_requirement("Bearer")
+        .tag("media")
+}
+
 #[debug_handler]
 pub async fn change_image(
-    State(pool): State<PgPool>, user: User, Path(path): Path<PathImage>,
-    Json(body): Json<ChangeImageInput>,
+    State(pool): State<PgPool>, user: Authorized<ManageMedia>, client: ClientInfo,
+    Path(path): Path<PathImage>, Json(body): Json<ChangeImageInput>,
 ) -> Result<impl IntoApiResponse, AppError> {
     let account_id = user.account_id;
-    let mut conn = pool.acquire().await?;
-    let image = crate::images::change_image(&mut conn, account_id, path.image_id, body).await?;
+    let mut tx = pool.begin().await?;
+    let before = get_image(&mut tx, account_id, path.image_id).await?;
+    let image = crate::images::change_image(&mut tx, account_id, path.image_id, body).await?;
+    audit::changed(
+        &mut tx,
+        &user.actor(client),
+        "media.image",
+        image.id,
+        &before,
+        &image,
+    )
+    .await?;
+    tx.commit().await?;
 
     Ok((StatusCode::OK, Json(image)))
 }
@@ -135,9 +260,322 @@ pub fn change_image_docs(op: TransformOperation) -> TransformOperation {
         .tag("media")
 }
 
+#[debug_handler]
+pub async fn list_image_presets(
+    State(pool): State<PgPool>, user: Authorized<ReadContent>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let presets = crate::presets::list_image_presets(&mut conn, user.account_id).await?;
+    Ok((StatusCode::OK, Json(presets)))
+}
+
+pub fn list_image_presets_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_image_presets")
+        .description("List the transformations allowed on the images of the account")
+        .response::<200, Json<Vec<ImagePreset>>>()
+        .security_requirement("Bearer")
+        .tag("media")
+}
+
+#[debug_handler]
+pub async fn create_image_preset(
+    State(pool): State<PgPool>, user: Authorized<ManageMedia>, client: ClientInfo,
+    Json(body): Json<CreateImagePresetInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    let mut tx = pool.begin().await?;
+    let preset = crate::presets::create_image_preset(&mut tx, user.account_id, body).await?;
+    audit::created(
+        &mut tx,
+        &user.actor(client),
+        "media.image_preset",
+        preset.id,
+        &preset,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok((StatusCode::CREATED, Json(preset)))
+}
+
+pub fn create_image_preset_docs(op: TransformOperation) -> TransformOperation {
+    op.id("create_image_preset")
+        .description("Allow a transformation of the images, with a width, a height or both, and a format and a quality")
+        .response::<201, Json<ImagePreset>>()
+        .security_requirement("Bearer")
+        .tag("media")
+}
+
+#[derive(Debug, Clone, Deserialize, schemars::JsonSchema)]
+pub struct PathImagePreset {
+    pub image_preset_id: Uuid,
+}
+
+#[debug_handler]
+pub async fn delete_image_preset(
+    State(pool): State<PgPool>, user: Authorized<ManageMedia>, client: ClientInfo,
+    Path(path): Path<PathImagePreset>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    let preset =
+        crate::presets::delete_image_preset(&mut tx, user.account_id, path.image_preset_id)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound => AppError::NotFound("Image preset not found".into()),
+                e => e.into(),
+            })?;
+    audit::deleted(
+        &mut tx,
+        &user.actor(client),
+        "media.image_preset",
+        preset.id,
+        &preset,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn delete_image_preset_docs(op: TransformOperation) -> TransformOperation {
+    op.id("delete_image_preset")
+        .description(
+            "Remove an allowed transformation, its transformed images are kept in the storage",
+        )
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("media")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct FileResponse {
+    #[serde(flatten)]
+    pub file: File,
+    /// The public url of the file on the CDN.
+    pub url: String,
+}
+
+impl From<File> for FileResponse {
+    fn from(file: File) -> Self {
+        let url = format!("{}/{}", &*IMAGES_CDN_PATH, file.path);
+        FileResponse { file, url }
+    }
+}
+
+#[debug_handler(state = AppState)]
+pub async fn upload_file(
+    State(pool): State<PgPool>, State(opendal): State<OpendalUploader>,
+    user: Authorized<ManageMedia>, client: ClientInfo, mut multipart: Multipart,
+) -> Result<impl IntoApiResponse, AppError> {
+    let account_id = user.account_id;
+    let id = Uuid::from_bytes(rusty_ulid::generate_ulid_bytes());
+
+    let mut conn = pool.acquire().await?;
+    let limits = get_upload_limits(&mut conn, account_id).await?;
+    drop(conn);
+
+    // The `file` field is the content, streamed to the storage as it's read, the optional
+    // `metadata` field a JSON object kept with it.
+    let mut file_path = None;
+    let saved = async {
+        let mut upload = None;
+        let mut metadata = serde_json::json!({});
+        while let Some(mut field) = multipart.next_field().await.map_err(invalid_upload)? {
+            let name = field.name().unwrap_or_default().to_string();
+            match name.as_str() {
+                "file" if upload.is_some() => {
+                    return Err(AppError::BadRequest("Upload a single file".into()));
+                }
+                "file" => {
+                    let file_name = field
+                        .file_name()
+                        .map(str::to_string)
+                        .ok_or_else(|| AppError::BadRequest("The file needs a name".into()))?;
+                    let content_type = file_content_type(field.content_type())?;
+                    let path = format!(
+                        "{}/{}",
+                        file_directory(account_id, id),
+                        storage_file_name(&file_name)
+                    );
+                    let stored = store_field(
+                        &opendal,
+                        &path,
+                        &mut field,
+                        &content_type,
+                        limits.max_upload_size(&content_type),
+                    )
+                    .await?;
+                    file_path = Some(path.clone());
+                    upload = Some((file_name, content_type, path, stored));
+                }
+                "metadata" => {
+                    metadata = parse_metadata(&field.text().await.map_err(invalid_upload)?)?;
+                }
+                _ => continue,
+            }
+        }
+        let (file_name, content_type, path, stored) =
+            upload.ok_or_else(|| AppError::BadRequest("Upload a file".into()))?;
+
+        let input = CreateFileInput {
+            file_name,
+            content_type,
+            size: stored.size,
+            checksum: stored.checksum,
+            path,
+            metadata,
+        };
+        let mut tx = pool.begin().await?;
+        let file = create_file(&mut tx, account_id, id, input).await?;
+        audit::created(&mut tx, &user.actor(client), "media.file", file.id, &file).await?;
+        tx.commit().await?;
+        Ok::<_, AppError>(FileResponse::from(file))
+    }
+    .await;
+
+    // A failed upload leaves no row, nor the stored file.
+    match saved {
+        Ok(response) => Ok((StatusCode::CREATED, Json(response))),
+        Err(e) => {
+            if let Some(file_path) = file_path {
+                if let Err(delete_error) = opendal.delete(&file_path).await {
+                    tracing::warn!(error = %delete_error, file_path, "failed to remove the file of a failed upload");
+                }
+            }
+            Err(e)
+        }
+    }
+}
+
+pub fn upload_file_docs(op: TransformOperation) -> TransformOperation {
+    op.id("upload_file")
+        .description(
+            "Upload a document, audio or video file, within the limits of the account plan. Its content must match its content type",
+        )
+        .response::<201, Json<FileResponse>>()
+        .security_requirement("Bearer")
+        .tag("media")
+}
+
+#[debug_handler]
+pub async fn list_files(
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Query(query): Query<FileQuery>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let files = crate::files::list_files(&mut conn, user.account_id, query).await?;
+    let files: Vec<FileResponse> = files.into_iter().map(FileResponse::from).collect();
+
+    Ok((StatusCode::OK, Json(files)))
+}
+
+pub fn list_files_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_files")
+        .description("List the files uploaded to the media library")
+        .response::<200, Json<Vec<FileResponse>>>()
+        .security_requirement("Bearer")
+        .tag("media")
+}
+
+#[derive(Debug, Clone, Deserialize, schemars::JsonSchema)]
+pub struct PathFile {
+    pub file_id: Uuid,
+}
+
+pub(crate) fn file_not_found(e: sqlx::Error) -> AppError {
+    match e {
+        sqlx::Error::RowNotFound => AppError::NotFound("File not found".into()),
+        e => e.into(),
+    }
+}
+
+#[debug_handler]
+pub async fn get_file(
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Path(path): Path<PathFile>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let file = crate::files::get_file(&mut conn, user.account_id, path.file_id)
+        .await
+        .map_err(file_not_found)?;
+
+    Ok((StatusCode::OK, Json(FileResponse::from(file))))
+}
+
+pub fn get_file_docs(op: TransformOperation) -> TransformOperation {
+    op.id("get_file")
+        .description("Get the file in the media library")
+        .response::<200, Json<FileResponse>>()
+        .security_requirement("Bearer")
+        .tag("media")
+}
+
+#[debug_handler(state = AppState)]
+pub async fn delete_file(
+    State(pool): State<PgPool>, State(opendal): State<OpendalUploader>,
+    user: Authorized<ManageMedia>, client: ClientInfo, Path(path): Path<PathFile>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    let file = crate::files::delete_file(&mut tx, user.account_id, path.file_id)
+        .await
+        .map_err(file_not_found)?;
+    audit::deleted(&mut tx, &user.actor(client), "media.file", file.id, &file).await?;
+    tx.commit().await?;
+
+    let directory = format!("{}/", file_directory(user.account_id, file.id));
+    if let Err(e) = opendal.remove_all(&directory).await {
+        tracing::warn!(error = %e, directory, "failed to remove a deleted file");
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn delete_file_docs(op: TransformOperation) -> TransformOperation {
+    op.id("delete_file")
+        .description("Delete the file from the media library and the storage")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("media")
+}
+
 pub fn routes() -> ApiRouter<AppState> {
+    // Images are read whole to be decoded, files are streamed to the storage within the
+    // limits of the account plan, which can be larger than the default body limit.
+    let images = ApiRouter::new()
+        .api_route(
+            "/images",
+            post_with(upload_image, upload_image_docs).get_with(list_images, list_image_docs),
+        )
+        .layer(DefaultBodyLimit::max(50 * 1000 * 1000));
+    let files = ApiRouter::new()
+        .api_route(
+            "/files",
+            post_with(upload_file, upload_file_docs).get_with(list_files, list_files_docs),
+        )
+        .layer(DefaultBodyLimit::disable());
+
     ApiRouter::new()
-        .api_route("/images", post_with(upload_image, upload_image_docs))
-        .api_route("/images", get_with(list_images, list_image_docs))
-        .layer(DefaultBodyLimit::max(50 * 1000 * 1000))        
+        .api_route(
+            "/images/:image_id",
+            delete_with(delete_image, delete_image_docs),
+        )
+        .api_route(
+            "/orphans/collect",
+            post_with(collect_orphaned_images, collect_orphaned_images_docs),
+        )
+        .api_route(
+            "/image-presets",
+            get_with(list_image_presets, list_image_presets_docs),
+        )
+        .api_route(
+            "/image-presets",
+            post_with(create_image_preset, create_image_preset_docs),
+        )
+        .api_route(
+            "/image-presets/:image_preset_id",
+            delete_with(delete_image_preset, delete_image_preset_docs),
+        )
+        .api_route("/files/:file_id", get_with(get_file, get_file_docs))
+        .api_route(
+            "/files/:file_id",
+            delete_with(delete_file, delete_file_docs),
+        )
+        .merge(images)
+        .merge(files)
 }
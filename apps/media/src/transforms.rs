@@ -0,0 +1,211 @@
+use image::imageops::FilterType;
+use once_cell::sync::Lazy;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use shared::AppError;
+use tokio::sync::Semaphore;
+
+use crate::presets::Fit;
+use crate::variants::encode;
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct TransformQuery {
+    /// The width in pixels.
+    pub w: Option<u32>,
+    /// The height in pixels.
+    pub h: Option<u32>,
+    #[serde(default)]
+    pub fit: Fit,
+    /// `jpg`, `png`, `gif` or `webp`, the format of the original by default.
+    pub format: Option<String>,
+    /// The quality of JPEG and WebP, from 1 to 100.
+    pub q: Option<u8>,
+}
+
+/// The transformations running at once, `IMAGE_TRANSFORM_CONCURRENCY` (the number of CPUs by
+/// default). Each decodes a whole original, the other requests wait for their turn.
+pub(crate) static TRANSFORMS: Lazy<Semaphore> = Lazy::new(|| {
+    let concurrency = std::env::var("IMAGE_TRANSFORM_CONCURRENCY")
+        .ok()
+        .and_then(|concurrency| concurrency.parse::<usize>().ok())
+        .or_else(|| std::thread::available_parallelism().ok().map(usize::from))
+        .unwrap_or(2);
+    Semaphore::new(concurrency.max(1))
+});
+
+/// The extension of a supported format.
+pub fn image_format(format: &str) -> Result<&'static str, AppError> {
+    match format {
+        "jpg" | "jpeg" => Ok("jpg"),
+        "png" => Ok("png"),
+        "gif" => Ok("gif"),
+        "webp" => Ok("webp"),
+        format => Err(AppError::BadRequest(format!(
+            "The format {format} is not supported"
+        ))),
+    }
+}
+
+/// A transformation of an original, validated from the query.
+#[derive(Debug, Clone)]
+pub struct Transform {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fit: Fit,
+    pub format: &'static str,
+    pub quality: u8,
+}
+
+impl Transform {
+    pub fn from_query(
+        query: TransformQuery, original_format: &str, default_quality: u8,
+    ) -> Result<Self, AppError> {
+        let format = image_format(query.format.as_deref().unwrap_or(original_format))?;
+
+        match (query.w, query.h, query.fit) {
+            (None, None, _) => Err(AppError::BadRequest("Set a width or a height".into())),
+            (None, _, Fit::Cover | Fit::Fill) | (_, None, Fit::Cover | Fit::Fill) => {
+                Err(AppError::BadRequest(format!(
+                    "The {} fit needs a width and a height",
+                    query.fit.as_str()
+                )))
+            }
+            (width, height, fit) => Ok(Transform {
+                width,
+                height,
+                fit,
+                format,
+                quality: query.q.unwrap_or(default_quality).clamp(1, 100),
+            }),
+        }
+    }
+
+    /// The file name of the transformed image, the same transformation always has the same name.
+    pub fn file_name(&self) -> String {
+        let dimension = |dimension: Option<u32>| {
+            dimension.map_or_else(|| "auto".to_string(), |dimension| dimension.to_string())
+        };
+        format!(
+            "{}x{}-{}-q{}.{}",
+            dimension(self.width),
+            dimension(self.height),
+            self.fit.as_str(),
+            self.quality,
+            self.format
+        )
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self.format {
+            "jpg" => "image/jpeg",
+            "png" => "image/png",
+            "gif" => "image/gif",
+            _ => "image/webp",
+        }
+    }
+}
+
+/// Decodes, resizes and encodes the original. CPU bound, run it on the blocking threads.
+pub fn transform_image(original: &[u8], transform: &Transform) -> Result<Vec<u8>, anyhow::Error> {
+    let image = image::load_from_memory(original)?;
+    let image = match (transform.fit, transform.width, transform.height) {
+        (Fit::Cover, Some(width), Some(height)) => {
+            image.resize_to_fill(width, height, FilterType::Lanczos3)
+        }
+        (Fit::Fill, Some(width), Some(height)) => {
+            image.resize_exact(width, height, FilterType::Lanczos3)
+        }
+        (_, width, height) => {
+            let width = width.unwrap_or(u32::MAX);
+            let height = height.unwrap_or(u32::MAX);
+            if image.width() <= width && image.height() <= height {
+                image
+            } else {
+                image.resize(width, height, FilterType::Lanczos3)
+            }
+        }
+    };
+
+    encode(&image, transform.format, transform.quality)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(w: Option<u32>, h: Option<u32>, fit: Fit) -> TransformQuery {
+        TransformQuery {
+            w,
+            h,
+            fit,
+            format: None,
+            q: None,
+        }
+    }
+
+    #[test]
+    fn test_from_query_defaults_to_the_original() {
+        let transform =
+            Transform::from_query(query(Some(320), None, Fit::Contain), "png", 80).unwrap();
+        assert_eq!(transform.width, Some(320));
+        assert_eq!(transform.height, None);
+        assert_eq!(transform.format, "png");
+        assert_eq!(transform.quality, 80);
+        assert_eq!(transform.content_type(), "image/png");
+    }
+
+    #[test]
+    fn test_from_query_normalizes_the_format_and_quality() {
+        let transform = Transform::from_query(
+            TransformQuery {
+                format: Some("jpeg".into()),
+                q: Some(0),
+                ..query(None, Some(200), Fit::Contain)
+            },
+            "png",
+            80,
+        )
+        .unwrap();
+        assert_eq!(transform.format, "jpg");
+        assert_eq!(transform.quality, 1);
+        assert_eq!(transform.content_type(), "image/jpeg");
+    }
+
+    #[test]
+    fn test_from_query_refuses_invalid_transforms() {
+        let invalid = [
+            query(None, None, Fit::Contain),
+            query(Some(320), None, Fit::Cover),
+            query(None, Some(200), Fit::Fill),
+            TransformQuery {
+                format: Some("svg".into()),
+                ..query(Some(320), None, Fit::Contain)
+            },
+        ];
+        for query in invalid {
+            assert!(matches!(
+                Transform::from_query(query, "png", 80),
+                Err(AppError::BadRequest(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_file_name() {
+        let transform = Transform::from_query(
+            TransformQuery {
+                format: Some("webp".into()),
+                q: Some(60),
+                ..query(Some(320), Some(200), Fit::Cover)
+            },
+            "jpg",
+            80,
+        )
+        .unwrap();
+        assert_eq!(transform.file_name(), "320x200-cover-q60.webp");
+
+        let transform =
+            Transform::from_query(query(None, Some(200), Fit::Contain), "jpg", 80).unwrap();
+        assert_eq!(transform.file_name(), "autox200-contain-q80.jpg");
+    }
+}
@@ -0,0 +1,311 @@
+use std::io::Cursor;
+
+use axum::extract::multipart::{Field, MultipartError};
+use image::{ImageFormat, ImageReader};
+use opendal::{Operator, Writer};
+use sha2::{Digest, Sha256};
+use shared::AppError;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+/// The largest original an account can upload, set by its plan in `media.plan_upload_limits`.
+#[derive(Debug, Clone)]
+pub struct UploadLimits {
+    pub max_file_size: i64,
+    pub max_width: i32,
+    pub max_height: i32,
+    /// The largest document uploaded as a file.
+    pub max_document_size: i64,
+    /// The largest audio or video file.
+    pub max_media_size: i64,
+}
+
+impl UploadLimits {
+    /// The largest file of the content type, images uploaded as files keep the image limit.
+    pub fn max_upload_size(&self, content_type: &str) -> i64 {
+        if content_type.starts_with("audio/") || content_type.starts_with("video/") {
+            self.max_media_size
+        } else if content_type.starts_with("image/") {
+            self.max_file_size
+        } else {
+            self.max_document_size
+        }
+    }
+}
+
+/// The limits of the free plan, for plans without limits of their own.
+impl Default for UploadLimits {
+    fn default() -> Self {
+        UploadLimits {
+            max_file_size: 10_000_000,
+            max_width: 6000,
+            max_height: 6000,
+            max_document_size: 25_000_000,
+            max_media_size: 250_000_000,
+        }
+    }
+}
+
+pub async fn get_upload_limits(
+    conn: &mut PgConnection, account_id: Uuid,
+) -> Result<UploadLimits, sqlx::Error> {
+    let limits = sqlx::query_as!(
+        UploadLimits,
+        r#"
+        SELECT l.max_file_size, l.max_width, l.max_height, l.max_document_size, l.max_media_size
+        FROM accounts.accounts a
+        JOIN media.plan_upload_limits l ON (l.plan = a.plan)
+        WHERE a.id = $1
+        "#,
+        account_id,
+    )
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(limits.unwrap_or_default())
+}
+
+pub fn invalid_upload(e: MultipartError) -> AppError {
+    AppError::BadRequest(format!("The upload could not be read: {e}"))
+}
+
+fn too_large(max_size: i64) -> AppError {
+    AppError::BadRequest(format!(
+        "The upload is larger than {} MB",
+        max_size as f64 / 1_000_000.0
+    ))
+}
+
+/// Reads the field, refusing it as soon as it's larger than the limit.
+pub async fn read_field(field: &mut Field<'_>, limits: &UploadLimits) -> Result<Vec<u8>, AppError> {
+    let mut data = Vec::new();
+    while let Some(chunk) = field.chunk().await.map_err(invalid_upload)? {
+        if (data.len() + chunk.len()) as i64 > limits.max_file_size {
+            return Err(too_large(limits.max_file_size));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data)
+}
+
+/// How many of the first bytes of a file are sniffed.
+const SNIFF_LENGTH: usize = 512;
+
+/// A file written to the storage, with the size and SHA-256 checksum of its content.
+#[derive(Debug, Clone)]
+pub struct StoredFile {
+    pub size: i64,
+    pub checksum: String,
+}
+
+/// Streams the field to the storage as it's read, sniffing its first bytes against the
+/// content type and refusing it as soon as it's larger than `max_size`.
+/// Nothing is left in the storage when it's refused.
+pub async fn store_field(
+    opendal: &Operator, path: &str, field: &mut Field<'_>, content_type: &str, max_size: i64,
+) -> Result<StoredFile, AppError> {
+    let mut writer = opendal.writer(path).await?;
+    let written = write_field(&mut writer, field, content_type, max_size).await;
+    let closed = match written {
+        Ok(stored) => writer.close().await.map(|_| stored).map_err(AppError::from),
+        Err(e) => Err(e),
+    };
+    if closed.is_err() {
+        if let Err(abort_error) = writer.abort().await {
+            tracing::warn!(error = %abort_error, path, "failed to abort the write of a refused upload");
+        }
+    }
+    closed
+}
+
+async fn write_field(
+    writer: &mut Writer, field: &mut Field<'_>, content_type: &str, max_size: i64,
+) -> Result<StoredFile, AppError> {
+    let mut size = 0;
+    let mut hasher = Sha256::new();
+    // The first bytes are held until there are enough of them to sniff.
+    let mut head = Vec::new();
+    let mut sniffed = false;
+    while let Some(chunk) = field.chunk().await.map_err(invalid_upload)? {
+        size += chunk.len() as i64;
+        if size > max_size {
+            return Err(too_large(max_size));
+        }
+        hasher.update(&chunk);
+        if sniffed {
+            writer.append(chunk).await?;
+            continue;
+        }
+        head.extend_from_slice(&chunk);
+        if head.len() >= SNIFF_LENGTH {
+            sniff_file(&head, content_type)?;
+            sniffed = true;
+            writer.append(std::mem::take(&mut head)).await?;
+        }
+    }
+    if size == 0 {
+        return Err(AppError::BadRequest("The file is empty".into()));
+    }
+    if !sniffed {
+        sniff_file(&head, content_type)?;
+        writer.append(head).await?;
+    }
+
+    Ok(StoredFile {
+        size,
+        checksum: format!("{:x}", hasher.finalize()),
+    })
+}
+
+/// The documents, audio and video files can be, images are uploaded as files too.
+/// Types a browser would run, like HTML or SVG, are refused.
+const FILE_CONTENT_TYPES: &[&str] = &[
+    "application/pdf",
+    "application/zip",
+    "application/epub+zip",
+    "application/msword",
+    "application/vnd.ms-excel",
+    "application/vnd.ms-powerpoint",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    "application/vnd.oasis.opendocument.text",
+    "application/vnd.oasis.opendocument.spreadsheet",
+    "application/vnd.oasis.opendocument.presentation",
+    "text/plain",
+    "text/csv",
+    "image/jpeg",
+    "image/png",
+    "image/gif",
+    "image/webp",
+];
+
+/// The content type of an uploaded file, without its parameters.
+pub fn file_content_type(content_type: Option<&str>) -> Result<String, AppError> {
+    let content_type = content_type
+        .and_then(|content_type| content_type.split(';').next())
+        .map(|content_type| content_type.trim().to_lowercase())
+        .unwrap_or_default();
+
+    let is_media = ["audio/", "video/"]
+        .iter()
+        .any(|prefix| content_type.starts_with(prefix) && content_type.len() > prefix.len());
+    if is_media || FILE_CONTENT_TYPES.contains(&content_type.as_str()) {
+        Ok(content_type)
+    } else if content_type.is_empty() {
+        Err(AppError::BadRequest("The file needs a content type".into()))
+    } else {
+        Err(AppError::BadRequest(format!(
+            "Files of type {content_type} can't be uploaded"
+        )))
+    }
+}
+
+/// Refuses a file whose first bytes don't match its content type, so a file can't be served
+/// as another type than the one it is.
+pub fn sniff_file(head: &[u8], content_type: &str) -> Result<(), AppError> {
+    let zip = head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06");
+    let ole = head.starts_with(&[0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1]);
+    let matches = match content_type {
+        "application/pdf" => head.starts_with(b"%PDF-"),
+        "application/zip"
+        | "application/epub+zip"
+        | "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+        | "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+        | "application/vnd.openxmlformats-officedocument.presentationml.presentation"
+        | "application/vnd.oasis.opendocument.text"
+        | "application/vnd.oasis.opendocument.spreadsheet"
+        | "application/vnd.oasis.opendocument.presentation" => zip,
+        "application/msword" | "application/vnd.ms-excel" | "application/vnd.ms-powerpoint" => ole,
+        "text/plain" | "text/csv" => is_text(head),
+        "image/jpeg" | "image/png" | "image/gif" | "image/webp" => {
+            image::guess_format(head).ok() == ImageFormat::from_mime_type(content_type)
+        }
+        content_type
+            if content_type.starts_with("audio/") || content_type.starts_with("video/") =>
+        {
+            is_audio_or_video(head)
+        }
+        _ => false,
+    };
+    if matches {
+        Ok(())
+    } else {
+        Err(AppError::BadRequest(format!(
+            "The content of the file is not {content_type}"
+        )))
+    }
+}
+
+/// UTF-8 without NUL bytes, the last character may be cut by the end of the sniffed bytes.
+fn is_text(head: &[u8]) -> bool {
+    let valid = match std::str::from_utf8(head) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    valid && !head.contains(&0)
+}
+
+/// The containers and streams of the usual audio and video formats.
+fn is_audio_or_video(head: &[u8]) -> bool {
+    let riff = |kind: &[u8]| head.starts_with(b"RIFF") && head.get(8..12) == Some(kind);
+    // MP4, M4A, MOV and 3GP
+    head.get(4..8) == Some(b"ftyp".as_slice())
+        // WebM and Matroska
+        || head.starts_with(&[0x1a, 0x45, 0xdf, 0xa3])
+        || head.starts_with(b"OggS")
+        || head.starts_with(b"fLaC")
+        || head.starts_with(b"ID3")
+        // MP3 and AAC frames
+        || (head.len() > 1 && head[0] == 0xff && head[1] & 0xe0 == 0xe0)
+        || riff(b"WAVE")
+        || riff(b"AVI ")
+        || (head.starts_with(b"FORM") && matches!(head.get(8..12), Some(b"AIFF" | b"AIFC")))
+        // MPEG program streams
+        || head.starts_with(&[0x00, 0x00, 0x01, 0xba])
+        || head.starts_with(&[0x00, 0x00, 0x01, 0xb3])
+        // ASF, WMA and WMV
+        || head.starts_with(&[0x30, 0x26, 0xb2, 0x75])
+        || head.starts_with(b"#!AMR")
+        || head.starts_with(b"FLV")
+}
+
+/// An uploaded image, its format is told by its first bytes rather than its content type.
+#[derive(Debug, Clone)]
+pub struct SniffedImage {
+    /// The file extension of the format.
+    pub format: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub fn sniff_image(data: &[u8], limits: &UploadLimits) -> Result<SniffedImage, AppError> {
+    let (image_format, format) = match image::guess_format(data) {
+        Ok(ImageFormat::Jpeg) => (ImageFormat::Jpeg, "jpg"),
+        Ok(ImageFormat::Png) => (ImageFormat::Png, "png"),
+        Ok(ImageFormat::Gif) => (ImageFormat::Gif, "gif"),
+        Ok(ImageFormat::WebP) => (ImageFormat::WebP, "webp"),
+        _ => {
+            return Err(AppError::BadRequest(
+                "The image must be a JPEG, PNG, GIF or WebP".into(),
+            ))
+        }
+    };
+
+    let (width, height) = ImageReader::with_format(Cursor::new(data), image_format)
+        .into_dimensions()
+        .map_err(|_| AppError::BadRequest("The image could not be read".into()))?;
+
+    if width as i64 > limits.max_width as i64 || height as i64 > limits.max_height as i64 {
+        return Err(AppError::BadRequest(format!(
+            "The image is larger than {}x{} pixels",
+            limits.max_width, limits.max_height
+        )));
+    }
+
+    Ok(SniffedImage {
+        format,
+        width,
+        height,
+    })
+}
@@ -0,0 +1,265 @@
+use std::io::Cursor;
+
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, DynamicImage, ImageFormat};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use shared::AppState;
+use uuid::Uuid;
+use workers::Job;
+
+use crate::images::{create_image_set, delete_image_variants, find_image, CreateImageSetInput};
+use crate::router::IMAGES_CDN_PATH;
+
+/// The size of the uploaded image in the image set.
+pub const ORIGINAL_SIZE: &str = "original";
+
+const DEFAULT_IMAGE_VARIANTS: &str = "thumb:320,medium:960,large:1920";
+
+/// The sizes generated from the originals, `IMAGE_VARIANTS` lists them as `name:width`.
+static IMAGE_VARIANTS: Lazy<Vec<ImageVariant>> = Lazy::new(|| {
+    let variants =
+        std::env::var("IMAGE_VARIANTS").unwrap_or_else(|_| DEFAULT_IMAGE_VARIANTS.to_string());
+    variants
+        .split(',')
+        .map(|variant| {
+            let (name, width) = variant.trim().split_once(':')?;
+            Some(ImageVariant {
+                name: name.to_string(),
+                width: width.parse().ok()?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()
+        .expect("IMAGE_VARIANTS must be a comma separated list of name:width")
+});
+
+/// The quality (1 to 100) of the lossy encodings, JPEG and WebP.
+pub(crate) static IMAGE_QUALITY: Lazy<u8> = Lazy::new(|| {
+    std::env::var("IMAGE_QUALITY")
+        .ok()
+        .and_then(|quality| quality.parse::<u8>().ok())
+        .unwrap_or(80)
+        .clamp(1, 100)
+});
+
+#[derive(Debug, Clone)]
+pub struct ImageVariant {
+    pub name: String,
+    pub width: u32,
+}
+
+struct EncodedImage {
+    size: String,
+    format: &'static str,
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+/// Generates the variants of an uploaded original, in its format and in WebP.
+/// Variants aren't upscaled, the ones wider than the original are skipped.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GenerateImageVariants {
+    pub account_id: Uuid,
+    pub image_id: Uuid,
+    /// Where the files of the image are stored, the original is `{directory}/original.{format}`.
+    pub directory: String,
+    pub format: String,
+}
+
+#[axum::async_trait]
+impl Job for GenerateImageVariants {
+    const NAME: &'static str = "media.generate_image_variants";
+
+    fn account_id(&self) -> Option<Uuid> {
+        Some(self.account_id)
+    }
+
+    async fn run(self, state: &AppState) -> Result<(), anyhow::Error> {
+        // The image was deleted before its turn.
+        let mut conn = state.db_pool.acquire().await?;
+        if find_image(&mut conn, self.account_id, self.image_id)
+            .await?
+            .is_none()
+        {
+            return Ok(());
+        }
+        drop(conn);
+
+        let original_path = format!("{}/{ORIGINAL_SIZE}.{}", self.directory, self.format);
+        let original = state.opendal.read(&original_path).await?;
+
+        let format = self.format.clone();
+        let encoded = tokio::task::spawn_blocking(move || {
+            encode_variants(&original, &format, &IMAGE_VARIANTS, *IMAGE_QUALITY)
+        })
+        .await??;
+
+        let cdn_path = &*IMAGES_CDN_PATH;
+        let mut image_set = Vec::with_capacity(encoded.len());
+        for image in encoded {
+            let file_path = format!("{}/{}.{}", self.directory, image.size, image.format);
+            state.opendal.write(&file_path, image.data).await?;
+            image_set.push(CreateImageSetInput {
+                size: image.size,
+                path: format!("{cdn_path}/{file_path}"),
+                format: image.format.to_string(),
+                width: image.width as i32,
+                height: image.height as i32,
+            });
+        }
+
+        // Retries replace the variants of the previous attempts.
+        let mut tx = state.db_pool.begin().await?;
+        delete_image_variants(&mut tx, self.image_id, &self.format).await?;
+        for input in image_set {
+            create_image_set(&mut tx, self.image_id, input).await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+/// Decodes the original and encodes each variant, plus the original itself in WebP.
+/// It's CPU bound, run it on the blocking threads. Animated GIFs keep their first frame.
+fn encode_variants(
+    original: &[u8], format: &str, variants: &[ImageVariant], quality: u8,
+) -> Result<Vec<EncodedImage>, anyhow::Error> {
+    let image = image::load_from_memory(original)?;
+    let formats = match format {
+        "webp" => vec!["webp"],
+        "jpg" => vec!["jpg", "webp"],
+        "png" => vec!["png", "webp"],
+        "gif" => vec!["gif", "webp"],
+        _ => anyhow::bail!("unsupported image format {format}"),
+    };
+
+    let encoded_image = |image: &DynamicImage, size: &str, format: &'static str| {
+        Ok::<_, anyhow::Error>(EncodedImage {
+            size: size.to_string(),
+            format,
+            width: image.width(),
+            height: image.height(),
+            data: encode(image, format, quality)?,
+        })
+    };
+
+    let mut encoded = Vec::new();
+    if format != "webp" {
+        encoded.push(encoded_image(&image, ORIGINAL_SIZE, "webp")?);
+    }
+
+    for variant in variants
+        .iter()
+        .filter(|variant| variant.width < image.width())
+    {
+        let resized = image.resize(variant.width, u32::MAX, FilterType::Lanczos3);
+        for format in &formats {
+            encoded.push(encoded_image(&resized, &variant.name, format)?);
+        }
+    }
+
+    Ok(encoded)
+}
+
+/// Encodes the image in the format of the extension, the quality applies to JPEG and WebP.
+pub(crate) fn encode(
+    image: &DynamicImage, format: &str, quality: u8,
+) -> Result<Vec<u8>, anyhow::Error> {
+    let data = match format {
+        "webp" => {
+            let image = if image.color().has_alpha() {
+                DynamicImage::ImageRgba8(image.to_rgba8())
+            } else {
+                DynamicImage::ImageRgb8(image.to_rgb8())
+            };
+            webp::Encoder::from_image(&image)
+                .map_err(|e| anyhow::anyhow!("webp encoder: {e}"))?
+                .encode(quality as f32)
+                .to_vec()
+        }
+        "jpg" => {
+            let mut data = Vec::new();
+            let image = DynamicImage::ImageRgb8(image.to_rgb8());
+            JpegEncoder::new_with_quality(&mut data, quality).encode_image(&image)?;
+            data
+        }
+        _ => {
+            let mut data = Cursor::new(Vec::new());
+            let image = DynamicImage::ImageRgba8(image.to_rgba8());
+            let format = ImageFormat::from_extension(format)
+                .ok_or_else(|| anyhow::anyhow!("unsupported image format {format}"))?;
+            image.write_to(&mut data, format)?;
+            data.into_inner()
+        }
+    };
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variants() -> Vec<ImageVariant> {
+        [("small", 10), ("medium", 30), ("same", 40), ("large", 60)]
+            .into_iter()
+            .map(|(name, width)| ImageVariant {
+                name: name.to_string(),
+                width,
+            })
+            .collect()
+    }
+
+    fn original(format: &str) -> Vec<u8> {
+        encode(&DynamicImage::new_rgb8(40, 20), format, 80).unwrap()
+    }
+
+    fn sizes(encoded: &[EncodedImage]) -> Vec<(&str, &str, u32, u32)> {
+        encoded
+            .iter()
+            .map(|image| (image.size.as_str(), image.format, image.width, image.height))
+            .collect()
+    }
+
+    #[test]
+    fn test_encode_variants_adds_the_original_in_webp() {
+        let encoded = encode_variants(&original("png"), "png", &variants(), 80).unwrap();
+        assert_eq!(
+            sizes(&encoded),
+            vec![
+                ("original", "webp", 40, 20),
+                ("small", "png", 10, 5),
+                ("small", "webp", 10, 5),
+                ("medium", "png", 30, 15),
+                ("medium", "webp", 30, 15),
+            ]
+        );
+        for image in &encoded {
+            let format = image::guess_format(&image.data).unwrap();
+            assert_eq!(
+                format.extensions_str()[0].replace("jpeg", "jpg"),
+                image.format
+            );
+        }
+    }
+
+    #[test]
+    fn test_encode_variants_of_a_webp_original() {
+        let encoded = encode_variants(&original("webp"), "webp", &variants(), 80).unwrap();
+        assert_eq!(
+            sizes(&encoded),
+            vec![("small", "webp", 10, 5), ("medium", "webp", 30, 15)]
+        );
+    }
+
+    #[test]
+    fn test_encode_variants_never_upscales() {
+        let variants = [ImageVariant {
+            name: "large".to_string(),
+            width: 1920,
+        }];
+        let encoded = encode_variants(&original("jpg"), "jpg", &variants, 80).unwrap();
+        assert_eq!(sizes(&encoded), vec![("original", "webp", 40, 20)]);
+    }
+}
@@ -0,0 +1,242 @@
+use accounts::authenticate_user_with_password;
+use media::routes;
+use shared::testing::test_app;
+
+const FILE_ID: &str = "01870000-0000-0000-0000-000000000002";
+
+async fn test_token(pool: &sqlx::PgPool) -> String {
+    let access_token = authenticate_user_with_password(
+        pool,
+        uuid::uuid!("263b6188-aac6-45e9-9a2c-4728fdfd7ea1"),
+        "thiagovarela@consub.io".into(),
+        "123456".into(),
+    )
+    .await
+    .unwrap();
+    access_token.token
+}
+
+fn upload_form(data: &[u8], file_name: &str, content_type: &str) -> reqwest::multipart::Form {
+    let part = reqwest::multipart::Part::bytes(data.to_vec())
+        .file_name(file_name.to_string())
+        .mime_str(content_type)
+        .unwrap();
+    reqwest::multipart::Form::new()
+        .text("metadata", r#"{"title":"Quarterly report"}"#)
+        .part("file", part)
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_upload_a_file(pool: sqlx::PgPool) {
+    std::env::set_var("CDN_PATH", "https://cdn.consub.io");
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{address}/files"))
+        .header("Authorization", format!("Bearer {token}"))
+        .multipart(upload_form(
+            b"%PDF-1.4 hello world pdf",
+            "Q1 report.pdf",
+            "application/pdf",
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    let file = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(file["file_name"], "Q1 report.pdf");
+    assert_eq!(file["content_type"], "application/pdf");
+    assert_eq!(file["size"], 24);
+    assert_eq!(
+        file["checksum"],
+        "4ba209189b5712d75f72557333faf3d20381e61c7d3c2301d9cf771e5fd74457"
+    );
+    assert_eq!(file["metadata"]["title"], "Quarterly report");
+    assert!(file["url"]
+        .as_str()
+        .unwrap()
+        .starts_with("https://cdn.consub.io/"));
+    assert!(file["url"].as_str().unwrap().ends_with("/Q1-report.pdf"));
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_upload_a_file_of_a_refused_type(pool: sqlx::PgPool) {
+    std::env::set_var("CDN_PATH", "https://cdn.consub.io");
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool.clone(), routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{address}/files"))
+        .header("Authorization", format!("Bearer {token}"))
+        .multipart(upload_form(b"<html></html>", "page.html", "text/html"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let files: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM media.files")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(files, 0);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_upload_a_file_not_matching_its_content_type(pool: sqlx::PgPool) {
+    std::env::set_var("CDN_PATH", "https://cdn.consub.io");
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool.clone(), routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{address}/files"))
+        .header("Authorization", format!("Bearer {token}"))
+        .multipart(upload_form(
+            b"<html><script></script></html>",
+            "report.pdf",
+            "application/pdf",
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(
+        body["error"],
+        "The content of the file is not application/pdf"
+    );
+
+    let files: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM media.files")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(files, 0);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_files_have_their_own_size_limits(pool: sqlx::PgPool) {
+    std::env::set_var("CDN_PATH", "https://cdn.consub.io");
+    let token = test_token(&pool).await;
+
+    sqlx::query(
+        "UPDATE media.plan_upload_limits SET max_file_size = 100, max_document_size = 1000, max_media_size = 10000 WHERE plan = 'free'",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let address = test_app(pool.clone(), routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let audio = [b"ID3".as_slice(), &[0; 5000]].concat();
+    let response = client
+        .post(format!("{address}/files"))
+        .header("Authorization", format!("Bearer {token}"))
+        .multipart(upload_form(&audio, "episode.mp3", "audio/mpeg"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let file = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(file["size"], 5003);
+
+    let audio = [b"ID3".as_slice(), &[0; 20000]].concat();
+    let response = client
+        .post(format!("{address}/files"))
+        .header("Authorization", format!("Bearer {token}"))
+        .multipart(upload_form(&audio, "episode.mp3", "audio/mpeg"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let document = [b"%PDF-1.4 ".as_slice(), &[b'a'; 5000]].concat();
+    let response = client
+        .post(format!("{address}/files"))
+        .header("Authorization", format!("Bearer {token}"))
+        .multipart(upload_form(&document, "report.pdf", "application/pdf"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let files: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM media.files")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(files, 1);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_upload_a_file_larger_than_the_image_limit(pool: sqlx::PgPool) {
+    std::env::set_var("CDN_PATH", "https://cdn.consub.io");
+    let token = test_token(&pool).await;
+
+    sqlx::query(
+        "UPDATE media.plan_upload_limits SET max_document_size = 100000000 WHERE plan = 'free'",
+    )
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let document = [b"%PDF-1.4 ".as_slice(), &vec![b'a'; 60_000_000]].concat();
+    let response = client
+        .post(format!("{address}/files"))
+        .header("Authorization", format!("Bearer {token}"))
+        .multipart(upload_form(&document, "report.pdf", "application/pdf"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+    let file = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(file["size"], 60_000_009);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "files"))]
+async fn test_delete_a_file(pool: sqlx::PgPool) {
+    std::env::set_var("CDN_PATH", "https://cdn.consub.io");
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{address}/files/{FILE_ID}"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .delete(format!("{address}/files/{FILE_ID}"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    let response = client
+        .get(format!("{address}/files/{FILE_ID}"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}
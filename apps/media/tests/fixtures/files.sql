@@ -0,0 +1,2 @@
+INSERT INTO media.files (id,account_id,file_name,content_type,"size",checksum,"path") VALUES
+	 ('01870000-0000-0000-0000-000000000002','263b6188-aac6-45e9-9a2c-4728fdfd7ea1','report.pdf','application/pdf',24,'4ba209189b5712d75f72557333faf3d20381e61c7d3c2301d9cf771e5fd74457','167dgrhap68qmsmb2753yztzn1/files/01gw0000000000000000000002/report.pdf');
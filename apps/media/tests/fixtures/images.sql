@@ -0,0 +1,11 @@
+INSERT INTO media.images (id,account_id) VALUES
+	 ('01870000-0000-0000-0000-000000000001','263b6188-aac6-45e9-9a2c-4728fdfd7ea1');
+
+INSERT INTO media.images_set (image_id,"size","path","format",width,height) VALUES
+	 ('01870000-0000-0000-0000-000000000001','original','https://cdn.consub.io/167dgrhap68qmsmb2753yztzn1/01gw0000000000000000000001/original.jpg','jpg',1200,800);
+
+INSERT INTO blogs.posts (id,account_id,author_id,title,slug,body_json,body_html,body_text,locale,published_at) VALUES
+	 ('5b0a1c1e-3b8e-4f4e-9d59-3f0b7a2c1d10','263b6188-aac6-45e9-9a2c-4728fdfd7ea1','ad38ffbe-dabe-43a1-b63a-4028e23090eb','Hello World','hello-world','{}','<p>Hello</p>','Hello','en-US','2023-01-01 00:00:00');
+
+INSERT INTO blogs.post_images (post_id,image_type,media_id) VALUES
+	 ('5b0a1c1e-3b8e-4f4e-9d59-3f0b7a2c1d10','cover','01870000-0000-0000-0000-000000000001');
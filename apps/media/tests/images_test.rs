@@ -0,0 +1,268 @@
+use accounts::authenticate_user_with_password;
+use media::{public_routes, routes};
+use shared::testing::test_app;
+
+const IMAGE_ID: &str = "01870000-0000-0000-0000-000000000001";
+
+/// A 1x1 PNG.
+const PNG: &[u8] = &[
+    0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+    0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x02, 0x00, 0x00, 0x00, 0x90, 0x77, 0x53,
+    0xde, 0x00, 0x00, 0x00, 0x0c, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0xf8, 0xcf, 0xc0, 0x00,
+    0x00, 0x03, 0x01, 0x01, 0x00, 0xc9, 0xfe, 0x92, 0xef, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4e,
+    0x44, 0xae, 0x42, 0x60, 0x82,
+];
+
+async fn test_token(pool: &sqlx::PgPool) -> String {
+    let access_token = authenticate_user_with_password(
+        pool,
+        uuid::uuid!("263b6188-aac6-45e9-9a2c-4728fdfd7ea1"),
+        "thiagovarela@consub.io".into(),
+        "123456".into(),
+    )
+    .await
+    .unwrap();
+    access_token.token
+}
+
+fn upload_form(data: &[u8], content_type: &str) -> reqwest::multipart::Form {
+    let part = reqwest::multipart::Part::bytes(data.to_vec())
+        .file_name("image")
+        .mime_str(content_type)
+        .unwrap();
+    reqwest::multipart::Form::new().part("original", part)
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_upload_an_image(pool: sqlx::PgPool) {
+    std::env::set_var("CDN_PATH", "https://cdn.consub.io");
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    // The format is told by the content, not the content type.
+    let response = client
+        .post(format!("{address}/images"))
+        .header("Authorization", format!("Bearer {token}"))
+        .multipart(upload_form(PNG, "image/jpeg"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let image = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(image["image_set"][0]["size"], "original");
+    assert_eq!(image["image_set"][0]["format"], "png");
+    assert_eq!(image["image_set"][0]["width"], 1);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_upload_a_file_which_is_not_an_image(pool: sqlx::PgPool) {
+    std::env::set_var("CDN_PATH", "https://cdn.consub.io");
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool.clone(), routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{address}/images"))
+        .header("Authorization", format!("Bearer {token}"))
+        .multipart(upload_form(b"<html></html>", "image/png"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    let images: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM media.images")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(images, 0);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "images"))]
+async fn test_delete_an_image_in_use_is_refused(pool: sqlx::PgPool) {
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .delete(format!("{address}/images/{IMAGE_ID}"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "images"))]
+async fn test_force_delete_an_image_detaches_it(pool: sqlx::PgPool) {
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool.clone(), routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .delete(format!("{address}/images/{IMAGE_ID}?force=true"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 204);
+
+    let post_images: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM blogs.post_images")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(post_images, 0);
+
+    let response = client
+        .delete(format!("{address}/images/{IMAGE_ID}"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "images"))]
+async fn test_transform_an_image_without_a_preset_is_refused(pool: sqlx::PgPool) {
+    let address = test_app(pool, public_routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{address}/images/{IMAGE_ID}/transform?w=320"))
+        .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 403);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_transform_an_image_of_a_preset(pool: sqlx::PgPool) {
+    std::env::set_var("CDN_PATH", "https://cdn.consub.io");
+    let token = test_token(&pool).await;
+
+    let app = aide::axum::ApiRouter::new()
+        .nest("/admin", routes())
+        .merge(public_routes());
+    let address = test_app(pool, app).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{address}/admin/images"))
+        .header("Authorization", format!("Bearer {token}"))
+        .multipart(upload_form(PNG, "image/png"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let image = response.json::<serde_json::Value>().await.unwrap();
+    let image_id = image["id"].as_str().unwrap();
+
+    let response = client
+        .post(format!("{address}/admin/image-presets"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({ "width": 100, "format": "webp" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 201);
+
+    let transform = |query: &'static str| {
+        client
+            .get(format!("{address}/images/{image_id}/transform?{query}"))
+            .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+    };
+
+    // The preset only allows WebP, neither the format of the original nor another quality.
+    let response = transform("w=100").send().await.unwrap();
+    assert_eq!(response.status(), 403);
+    let response = transform("w=100&format=webp&q=10").send().await.unwrap();
+    assert_eq!(response.status(), 403);
+
+    let response = transform("w=100&format=webp").send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "image/webp");
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+    assert_eq!(&response.bytes().await.unwrap()[8..12], b"WEBP");
+
+    let response = transform("w=100&format=webp")
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 304);
+    assert_eq!(response.headers()["etag"], etag.as_str());
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_collect_orphaned_images(pool: sqlx::PgPool) {
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{address}/orphans/collect"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 202);
+
+    let job = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(job["name"], "media.collect_orphaned_images");
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_collect_orphaned_images_keeps_recent_uploads(pool: sqlx::PgPool) {
+    use shared::testing::{test_dns_resolver, test_mailer, test_opendal_uploader};
+    use workers::Job;
+
+    let state = shared::AppState {
+        db_pool: pool,
+        opendal: test_opendal_uploader(),
+        mailer: test_mailer(),
+        dns: test_dns_resolver(),
+    };
+
+    // Neither image has a row, the first one was uploaded in 2023.
+    let account_directory = "167dgrhap68qmsmb2753yztzn1";
+    let old_upload = format!("{account_directory}/01gw000000000000000000007z/original.png");
+    let recent_upload = format!(
+        "{account_directory}/{}/original.png",
+        rusty_ulid::generate_ulid_string().to_lowercase()
+    );
+    state
+        .opendal
+        .write(&old_upload, PNG.to_vec())
+        .await
+        .unwrap();
+    state
+        .opendal
+        .write(&recent_upload, PNG.to_vec())
+        .await
+        .unwrap();
+
+    media::CollectOrphanedImages {
+        account_id: uuid::uuid!("263b6188-aac6-45e9-9a2c-4728fdfd7ea1"),
+    }
+    .run(&state)
+    .await
+    .unwrap();
+
+    assert!(!state.opendal.is_exist(&old_upload).await.unwrap());
+    assert!(state.opendal.is_exist(&recent_upload).await.unwrap());
+}
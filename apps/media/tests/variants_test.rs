@@ -0,0 +1,81 @@
+use std::io::Cursor;
+
+use media::GenerateImageVariants;
+use shared::AppState;
+use workers::{enqueue, Runner};
+
+const ACCOUNT_ID: uuid::Uuid = uuid::uuid!("263b6188-aac6-45e9-9a2c-4728fdfd7ea1");
+const IMAGE_ID: uuid::Uuid = uuid::uuid!("01870000-0000-0000-0000-000000000001");
+const IMAGE_DIRECTORY: &str = "167dgrhap68qmsmb2753yztzn1/01gw0000000000000000000001";
+
+fn test_state(pool: &sqlx::PgPool) -> AppState {
+    AppState {
+        db_pool: pool.clone(),
+        opendal: shared::testing::test_opendal_uploader(),
+        mailer: shared::testing::test_mailer(),
+        dns: shared::testing::test_dns_resolver(),
+    }
+}
+
+/// A JPEG original, 400 pixels wide: only the thumb variant (320) is smaller.
+fn original_jpg() -> Vec<u8> {
+    let mut data = Cursor::new(Vec::new());
+    image::DynamicImage::new_rgb8(400, 200)
+        .write_to(&mut data, image::ImageFormat::Jpeg)
+        .unwrap();
+    data.into_inner()
+}
+
+async fn image_set(pool: &sqlx::PgPool) -> Vec<(String, String, i32)> {
+    sqlx::query_as(
+        "SELECT size, format, width FROM media.images_set WHERE image_id = $1 ORDER BY size, format",
+    )
+    .bind(IMAGE_ID)
+    .fetch_all(pool)
+    .await
+    .unwrap()
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "images"))]
+async fn test_retried_job_replaces_the_variants(pool: sqlx::PgPool) {
+    std::env::set_var("CDN_PATH", "https://cdn.consub.io");
+    let state = test_state(&pool);
+    state
+        .opendal
+        .write(&format!("{IMAGE_DIRECTORY}/original.jpg"), original_jpg())
+        .await
+        .unwrap();
+    let runner = Runner::new(state).register::<GenerateImageVariants>();
+
+    // The variants of a previous attempt, which failed before finishing.
+    sqlx::query(
+        "INSERT INTO media.images_set (image_id, size, path, format, width, height)
+        VALUES ($1, 'thumb', 'https://cdn.consub.io/stale.jpg', 'jpg', 1, 1)",
+    )
+    .bind(IMAGE_ID)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let job = GenerateImageVariants {
+        account_id: ACCOUNT_ID,
+        image_id: IMAGE_ID,
+        directory: IMAGE_DIRECTORY.to_string(),
+        format: "jpg".to_string(),
+    };
+    let expected = vec![
+        ("original".to_string(), "jpg".to_string(), 1200),
+        ("original".to_string(), "webp".to_string(), 400),
+        ("thumb".to_string(), "jpg".to_string(), 320),
+        ("thumb".to_string(), "webp".to_string(), 320),
+    ];
+
+    for _ in 0..2 {
+        let mut conn = pool.acquire().await.unwrap();
+        enqueue(&mut conn, &job).await.unwrap();
+        drop(conn);
+
+        assert!(runner.run_next().await.unwrap());
+        assert_eq!(image_set(&pool).await, expected);
+    }
+}
@@ -0,0 +1,7 @@
+mod page_images;
+mod pages;
+mod public_router;
+mod router;
+
+pub use public_router::public_routes;
+pub use router::routes;
@@ -0,0 +1,129 @@
+use chrono::NaiveDateTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PageImage {
+    pub id: Uuid,
+    pub page_id: Uuid,
+    pub image_type: String,
+    pub media_id: Uuid,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct CreatePageImageInput {
+    pub image_type: String,
+    pub media_id: Uuid,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct ChangePageImageInput {
+    pub image_type: Option<String>,
+}
+
+/// Page images are only created when the page belongs to the given account.
+pub async fn create_page_image(
+    conn: &mut PgConnection, account_id: Uuid, page_id: Uuid, input: CreatePageImageInput,
+) -> Result<PageImage, sqlx::Error> {
+    sqlx::query_as!(
+        PageImage,
+        r#"
+        INSERT INTO pages.page_images (page_id, image_type, media_id)
+        SELECT id, $3, $4 FROM pages.pages
+        WHERE id = $1 AND account_id = $2
+        RETURNING *
+        "#,
+        page_id,
+        account_id,
+        input.image_type,
+        input.media_id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn change_page_image(
+    conn: &mut PgConnection, account_id: Uuid, page_id: Uuid, page_image_id: Uuid,
+    input: ChangePageImageInput,
+) -> Result<PageImage, sqlx::Error> {
+    sqlx::query_as!(
+        PageImage,
+        r#"
+        UPDATE pages.page_images
+        SET image_type = COALESCE($4, image_type)
+        FROM pages.pages
+        WHERE pages.page_images.id = $3
+        AND pages.page_images.page_id = pages.pages.id
+        AND pages.pages.id = $2
+        AND pages.pages.account_id = $1
+        RETURNING pages.page_images.*
+        "#,
+        account_id,
+        page_id,
+        page_image_id,
+        input.image_type,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn delete_page_image(
+    conn: &mut PgConnection, account_id: Uuid, page_id: Uuid, page_image_id: Uuid,
+) -> Result<PageImage, sqlx::Error> {
+    sqlx::query_as!(
+        PageImage,
+        r#"
+        DELETE FROM pages.page_images
+        USING pages.pages
+        WHERE pages.page_images.id = $3
+        AND pages.page_images.page_id = pages.pages.id
+        AND pages.pages.id = $2
+        AND pages.pages.account_id = $1
+        RETURNING pages.page_images.*
+        "#,
+        account_id,
+        page_id,
+        page_image_id
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn list_page_images(
+    conn: &mut PgConnection, account_id: Uuid, page_id: Uuid,
+) -> Result<Vec<PageImage>, sqlx::Error> {
+    sqlx::query_as!(
+        PageImage,
+        r#"
+        SELECT pi.* FROM pages.page_images pi
+        INNER JOIN pages.pages p ON p.id = pi.page_id
+        WHERE p.account_id = $1
+        AND p.id = $2
+        "#,
+        account_id,
+        page_id
+    )
+    .fetch_all(conn)
+    .await
+}
+
+pub async fn list_page_images_by_page_ids(
+    conn: &mut PgConnection, account_id: Uuid, page_ids: Vec<Uuid>,
+) -> Result<Vec<PageImage>, sqlx::Error> {
+    sqlx::query_as!(
+        PageImage,
+        r#"
+        SELECT pi.* FROM pages.page_images pi
+        INNER JOIN pages.pages p ON p.id = pi.page_id
+        WHERE p.account_id = $1
+        AND p.id = ANY($2)
+        "#,
+        account_id,
+        &page_ids
+    )
+    .fetch_all(conn)
+    .await
+}
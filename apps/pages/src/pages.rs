@@ -0,0 +1,360 @@
+use chrono::NaiveDateTime;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use shared::pagination::CursorPagination;
+use sqlx::PgConnection;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Page {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub account_id: Uuid,
+    pub author_id: Uuid,
+    pub title: String,
+    pub slug: String,
+    pub short_description: Option<String>,
+    pub keywords: Vec<String>,
+    pub body_json: serde_json::Value,
+    pub body_html: String,
+    pub body_text: String,
+    pub locale: String,
+    pub translation_of: Option<Uuid>,
+    pub published_at: Option<NaiveDateTime>,
+    pub updated_at: NaiveDateTime,
+}
+
+/// PageTranslation is a summary of a page in another locale,
+/// enough for a frontend to link to it (e.g. hreflang alternates).
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PageTranslation {
+    #[serde(skip)]
+    pub page_id: Uuid,
+    pub id: Uuid,
+    pub title: String,
+    pub slug: String,
+    pub locale: String,
+}
+
+#[derive(Debug, serde::Deserialize, Validate, JsonSchema)]
+pub struct CreatePageInput {
+    pub title: String,
+    pub slug: Option<String>,
+    pub short_description: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    pub body_json: serde_json::Value,
+    pub body_html: String,
+    pub body_text: String,
+    pub locale: String,
+    pub translation_of: Option<Uuid>,
+    pub published_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, serde::Deserialize, Validate, JsonSchema)]
+pub struct ChangePageInput {
+    pub title: Option<String>,
+    pub slug: Option<String>,
+    pub short_description: Option<String>,
+    pub keywords: Option<Vec<String>>,
+    pub body_json: Option<serde_json::Value>,
+    pub body_html: Option<String>,
+    pub body_text: Option<String>,
+    pub locale: Option<String>,
+    pub translation_of: Option<Uuid>,
+    pub published_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, serde::Deserialize, Validate, JsonSchema)]
+pub struct PageQuery {
+    pub slug: Option<String>,
+    pub locale: Option<String>,
+    pub translation_of: Option<Uuid>,
+    #[serde(default, rename = "keyword")]
+    pub keywords: Vec<String>,
+
+    #[serde(default, flatten)]
+    pub pagination: CursorPagination,
+}
+
+/// Whether a page can be linked as a translation of another: it must be a page of the account,
+/// other than the page itself (None when it's being created).
+pub async fn is_translatable_page(
+    conn: &mut PgConnection, account_id: Uuid, translation_of: Uuid, page_id: Option<Uuid>,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM pages.pages
+            WHERE id = $1
+            AND account_id = $2
+            AND ($3::uuid IS NULL OR id <> $3)
+        ) as "exists!"
+        "#,
+        translation_of,
+        account_id,
+        page_id,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn create_page(
+    conn: &mut PgConnection, account_id: Uuid, author_id: Uuid, input: CreatePageInput,
+) -> Result<Page, sqlx::Error> {
+    let slug = slug::slugify(input.slug.as_deref().unwrap_or(&input.title));
+    sqlx::query_as!(
+        Page,
+        r#"
+        INSERT INTO pages.pages (
+            account_id, author_id, title, slug, short_description, keywords,
+            body_json, body_html, body_text, locale, translation_of, published_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        RETURNING *
+        "#,
+        account_id,
+        author_id,
+        input.title,
+        slug,
+        input.short_description,
+        &input.keywords,
+        input.body_json,
+        input.body_html,
+        input.body_text,
+        input.locale,
+        input.translation_of,
+        input.published_at,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn change_page(
+    conn: &mut PgConnection, account_id: Uuid, page_id: Uuid, input: ChangePageInput,
+) -> Result<Page, sqlx::Error> {
+    sqlx::query_as!(
+        Page,
+        r#"
+        UPDATE pages.pages
+        SET title = COALESCE($3, title),
+            slug = COALESCE($4, slug),
+            short_description = COALESCE($5, short_description),
+            keywords = COALESCE($6, keywords),
+            body_json = COALESCE($7, body_json),
+            body_html = COALESCE($8, body_html),
+            body_text = COALESCE($9, body_text),
+            locale = COALESCE($10, locale),
+            translation_of = COALESCE($11, translation_of),
+            published_at = COALESCE($12, published_at)
+        WHERE id = $1
+            AND account_id = $2
+        RETURNING *
+        "#,
+        page_id,
+        account_id,
+        input.title,
+        input.slug.map(|s| slug::slugify(&s)),
+        input.short_description,
+        input.keywords.as_deref(),
+        input.body_json,
+        input.body_html,
+        input.body_text,
+        input.locale,
+        input.translation_of,
+        input.published_at,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Deletes a page with its images, translations pointing to it are unlinked.
+pub async fn delete_page(
+    conn: &mut PgConnection, account_id: Uuid, page_id: Uuid,
+) -> Result<Page, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM pages.page_images
+        USING pages.pages
+        WHERE pages.page_images.page_id = pages.pages.id
+        AND pages.pages.id = $1
+        AND pages.pages.account_id = $2
+        "#,
+        page_id,
+        account_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE pages.pages
+        SET translation_of = NULL
+        WHERE translation_of = $1
+        AND account_id = $2
+        "#,
+        page_id,
+        account_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    sqlx::query_as!(
+        Page,
+        r#"
+        DELETE FROM pages.pages
+        WHERE id = $1
+        AND account_id = $2
+        RETURNING *
+        "#,
+        page_id,
+        account_id
+    )
+    .fetch_one(&mut *conn)
+    .await
+}
+
+pub async fn get_page(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid,
+) -> Result<Page, sqlx::Error> {
+    sqlx::query_as!(
+        Page,
+        r#"
+        SELECT * FROM pages.pages
+        WHERE account_id = $1 AND id = $2
+        "#,
+        account_id,
+        id
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn list_pages(
+    conn: &mut PgConnection, account_id: Uuid, query: PageQuery,
+) -> Result<Vec<Page>, sqlx::Error> {
+    sqlx::query_as!(
+        Page,
+        r#"
+        SELECT * FROM pages.pages
+        WHERE account_id = $1
+        AND ($2::text IS NULL OR slug = $2)
+        AND ($3::text IS NULL OR locale = $3)
+        AND ($4::uuid IS NULL OR translation_of = $4)
+        AND (array_length($5::text[], 1) IS NULL OR keywords && $5)
+        AND ($6::text IS NULL OR id > $6::uuid)
+        ORDER BY id LIMIT $7
+        "#,
+        account_id,
+        query.slug,
+        query.locale,
+        query.translation_of,
+        &query.keywords,
+        query.pagination.after,
+        query.pagination.take
+    )
+    .fetch_all(conn)
+    .await
+}
+
+/// Lists every page linked to the given page as a translation,
+/// which are the original page and all of the original's translations.
+pub async fn list_page_translations(
+    conn: &mut PgConnection, account_id: Uuid, page_id: Uuid,
+) -> Result<Vec<Page>, sqlx::Error> {
+    sqlx::query_as!(
+        Page,
+        r#"
+        SELECT p.* FROM pages.pages p
+        INNER JOIN pages.pages origin ON origin.account_id = p.account_id
+        WHERE origin.account_id = $1
+        AND origin.id = $2
+        AND p.id <> origin.id
+        AND (
+            p.id = COALESCE(origin.translation_of, origin.id)
+            OR p.translation_of = COALESCE(origin.translation_of, origin.id)
+        )
+        ORDER BY p.locale
+        "#,
+        account_id,
+        page_id
+    )
+    .fetch_all(conn)
+    .await
+}
+
+pub async fn public_list_pages(
+    conn: &mut PgConnection, account_id: Uuid, query: PageQuery,
+) -> Result<Vec<Page>, sqlx::Error> {
+    sqlx::query_as!(
+        Page,
+        r#"
+        SELECT * FROM pages.pages
+        WHERE account_id = $1
+        AND ($2::text IS NULL OR slug = $2)
+        AND ($3::text IS NULL OR locale = $3)
+        AND (array_length($4::text[], 1) IS NULL OR keywords && $4)
+        AND published_at IS NOT NULL AND published_at <= NOW()
+        AND ($5::text IS NULL OR id > $5::uuid)
+        ORDER BY id LIMIT $6
+        "#,
+        account_id,
+        query.slug,
+        query.locale,
+        &query.keywords,
+        query.pagination.after,
+        query.pagination.take
+    )
+    .fetch_all(conn)
+    .await
+}
+
+/// Finds a published page by its slug, when a locale isn't given any locale matches.
+pub async fn public_get_page_by_slug(
+    conn: &mut PgConnection, account_id: Uuid, slug: String, locale: Option<String>,
+) -> Result<Page, sqlx::Error> {
+    sqlx::query_as!(
+        Page,
+        r#"
+        SELECT * FROM pages.pages
+        WHERE account_id = $1
+        AND slug = $2
+        AND ($3::text IS NULL OR locale = $3)
+        AND published_at IS NOT NULL AND published_at <= NOW()
+        ORDER BY id LIMIT 1
+        "#,
+        account_id,
+        slug,
+        locale
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Same as list_page_translations, but for many pages at once and only published translations.
+pub async fn public_list_translations_by_page_ids(
+    conn: &mut PgConnection, account_id: Uuid, page_ids: Vec<Uuid>,
+) -> Result<Vec<PageTranslation>, sqlx::Error> {
+    sqlx::query_as!(
+        PageTranslation,
+        r#"
+        SELECT origin.id as page_id, p.id, p.title, p.slug, p.locale
+        FROM pages.pages origin
+        INNER JOIN pages.pages p ON p.account_id = origin.account_id
+        WHERE origin.account_id = $1
+        AND origin.id = ANY($2)
+        AND p.id <> origin.id
+        AND (
+            p.id = COALESCE(origin.translation_of, origin.id)
+            OR p.translation_of = COALESCE(origin.translation_of, origin.id)
+        )
+        AND p.published_at IS NOT NULL AND p.published_at <= NOW()
+        ORDER BY p.locale
+        "#,
+        account_id,
+        &page_ids
+    )
+    .fetch_all(conn)
+    .await
+}
@@ -0,0 +1,139 @@
+use accounts::Account;
+use aide::{
+    axum::{routing::get_with, ApiRouter, IntoApiResponse},
+    transform::TransformOperation,
+};
+use axum::http::StatusCode;
+
+use axum::{
+    debug_handler,
+    extract::{Path, State},
+    Json,
+};
+use axum_extra::extract::Query;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use shared::{AppError, AppState};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    page_images::PageImage,
+    pages::{Page, PageQuery, PageTranslation},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PublicPage {
+    #[serde(flatten)]
+    pub page: Page,
+    pub images: Vec<PageImage>,
+    pub translations: Vec<PageTranslation>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PathSlug {
+    pub slug: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct PageSlugQuery {
+    pub locale: Option<String>,
+}
+
+/// Loads the images and the published translations of each page.
+async fn public_pages(
+    pool: &PgPool, account_id: Uuid, pages: Vec<Page>,
+) -> Result<Vec<PublicPage>, AppError> {
+    let page_ids: Vec<Uuid> = pages.iter().map(|p| p.id).collect();
+
+    let page_images = {
+        let mut conn = pool.acquire().await?;
+        crate::page_images::list_page_images_by_page_ids(&mut conn, account_id, page_ids.clone())
+            .await?
+    };
+
+    let page_translations = {
+        let mut conn = pool.acquire().await?;
+        crate::pages::public_list_translations_by_page_ids(&mut conn, account_id, page_ids).await?
+    };
+
+    let pages = pages
+        .into_iter()
+        .map(|page| {
+            let images = page_images
+                .iter()
+                .filter(|image| image.page_id == page.id)
+                .cloned()
+                .collect();
+            let translations = page_translations
+                .iter()
+                .filter(|translation| translation.page_id == page.id)
+                .cloned()
+                .collect();
+            PublicPage {
+                page,
+                images,
+                translations,
+            }
+        })
+        .collect();
+
+    Ok(pages)
+}
+
+#[debug_handler]
+pub async fn list_pages(
+    State(pool): State<PgPool>, account: Account, Query(query): Query<PageQuery>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let pages = {
+        let mut conn = pool.acquire().await?;
+        crate::pages::public_list_pages(&mut conn, account.id, query).await?
+    };
+
+    let pages = public_pages(&pool, account.id, pages).await?;
+
+    Ok((StatusCode::OK, Json(pages)))
+}
+
+pub fn list_pages_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_pages")
+        .description("List published pages")
+        .response::<200, Json<Vec<PublicPage>>>()
+        .tag("pages")
+}
+
+#[debug_handler]
+pub async fn get_page_by_slug(
+    State(pool): State<PgPool>, account: Account, Path(path): Path<PathSlug>,
+    Query(query): Query<PageSlugQuery>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let page = {
+        let mut conn = pool.acquire().await?;
+        crate::pages::public_get_page_by_slug(&mut conn, account.id, path.slug, query.locale)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::RowNotFound => AppError::NotFound("Page not found".into()),
+                e => e.into(),
+            })?
+    };
+
+    let page = public_pages(&pool, account.id, vec![page])
+        .await?
+        .pop()
+        .ok_or_else(|| AppError::NotFound("Page not found".into()))?;
+
+    Ok((StatusCode::OK, Json(page)))
+}
+
+pub fn get_page_by_slug_docs(op: TransformOperation) -> TransformOperation {
+    op.id("get_page_by_slug")
+        .description("Get a published page by its slug, optionally for a given locale")
+        .response::<200, Json<PublicPage>>()
+        .tag("pages")
+}
+
+pub fn public_routes() -> ApiRouter<AppState> {
+    ApiRouter::new()
+        .api_route("/", get_with(list_pages, list_pages_docs))
+        .api_route("/:slug", get_with(get_page_by_slug, get_page_by_slug_docs))
+}
@@ -0,0 +1,302 @@
+use accounts::{
+    permissions::{DeleteContent, ReadContent, WriteContent},
+    Authorized,
+};
+use aide::axum::routing::{delete_with, get_with, patch_with, post_with};
+use aide::axum::{ApiRouter, IntoApiResponse};
+use aide::transform::TransformOperation;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::{debug_handler, Json};
+use axum_extra::extract::Query as ExtraQuery;
+use schemars::JsonSchema;
+use shared::{AppError, AppState};
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::page_images::{ChangePageImageInput, CreatePageImageInput, PageImage};
+use crate::pages::{ChangePageInput, CreatePageInput, Page, PageQuery};
+
+/// routes for pages
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PathPage {
+    pub page_id: Uuid,
+}
+
+fn page_not_found(e: sqlx::Error) -> AppError {
+    match e {
+        sqlx::Error::RowNotFound => AppError::NotFound("Page not found".into()),
+        e => e.into(),
+    }
+}
+
+fn page_image_not_found(e: sqlx::Error) -> AppError {
+    match e {
+        sqlx::Error::RowNotFound => AppError::NotFound("Page image not found".into()),
+        e => e.into(),
+    }
+}
+
+/// Refuses to link a page as a translation of a page of another account, or of itself.
+async fn check_translation_of(
+    conn: &mut sqlx::PgConnection, account_id: Uuid, translation_of: Option<Uuid>,
+    page_id: Option<Uuid>,
+) -> Result<(), AppError> {
+    if let Some(translation_of) = translation_of {
+        if !crate::pages::is_translatable_page(conn, account_id, translation_of, page_id).await? {
+            return Err(AppError::BadRequest(
+                "The page to translate is not another page of the account".into(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[debug_handler]
+pub async fn create_page(
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, Json(body): Json<CreatePageInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    body.validate()?;
+    let mut conn = pool.acquire().await?;
+    check_translation_of(&mut conn, user.account_id, body.translation_of, None).await?;
+    let page = crate::pages::create_page(&mut conn, user.account_id, user.id, body).await?;
+    Ok((StatusCode::CREATED, Json(page)))
+}
+
+pub fn create_page_docs(op: TransformOperation) -> TransformOperation {
+    op.id("create_page")
+        .description("Create a new page")
+        .response::<201, Json<Page>>()
+        .security_requirement("Bearer")
+        .tag("pages")
+}
+
+#[debug_handler]
+pub async fn list_pages(
+    State(pool): State<PgPool>, user: Authorized<ReadContent>,
+    ExtraQuery(query): ExtraQuery<PageQuery>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let pages = crate::pages::list_pages(&mut conn, user.account_id, query).await?;
+    Ok((StatusCode::OK, Json(pages)))
+}
+
+pub fn list_pages_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_pages")
+        .description("List pages")
+        .response::<200, Json<Vec<Page>>>()
+        .security_requirement("Bearer")
+        .tag("pages")
+}
+
+#[debug_handler]
+pub async fn get_page(
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Path(path_page): Path<PathPage>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let page = crate::pages::get_page(&mut conn, user.account_id, path_page.page_id)
+        .await
+        .map_err(page_not_found)?;
+    Ok((StatusCode::OK, Json(page)))
+}
+
+pub fn get_page_docs(op: TransformOperation) -> TransformOperation {
+    op.id("get_page")
+        .description("Get a single page")
+        .response::<200, Json<Page>>()
+        .security_requirement("Bearer")
+        .tag("pages")
+}
+
+#[debug_handler]
+pub async fn change_page(
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, Path(path_page): Path<PathPage>,
+    Json(body): Json<ChangePageInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    check_translation_of(
+        &mut conn,
+        user.account_id,
+        body.translation_of,
+        Some(path_page.page_id),
+    )
+    .await?;
+    let page = crate::pages::change_page(&mut conn, user.account_id, path_page.page_id, body)
+        .await
+        .map_err(page_not_found)?;
+    Ok((StatusCode::OK, Json(page)))
+}
+
+pub fn change_page_docs(op: TransformOperation) -> TransformOperation {
+    op.id("change_page")
+        .description("Change a page")
+        .response::<200, Json<Page>>()
+        .security_requirement("Bearer")
+        .tag("pages")
+}
+
+#[debug_handler]
+pub async fn delete_page(
+    State(pool): State<PgPool>, user: Authorized<DeleteContent>, Path(path_page): Path<PathPage>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut tx = pool.begin().await?;
+    crate::pages::delete_page(&mut tx, user.account_id, path_page.page_id)
+        .await
+        .map_err(page_not_found)?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn delete_page_docs(op: TransformOperation) -> TransformOperation {
+    op.id("delete_page")
+        .description("Delete a page, its images and unlink its translations")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("pages")
+}
+
+#[debug_handler]
+pub async fn list_page_translations(
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Path(path_page): Path<PathPage>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let pages =
+        crate::pages::list_page_translations(&mut conn, user.account_id, path_page.page_id).await?;
+    Ok((StatusCode::OK, Json(pages)))
+}
+
+pub fn list_page_translations_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_page_translations")
+        .description("List the pages linked to a page as translations")
+        .response::<200, Json<Vec<Page>>>()
+        .security_requirement("Bearer")
+        .tag("pages")
+}
+
+/// routes for page images
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PathPageImage {
+    pub page_id: Uuid,
+    pub page_image_id: Uuid,
+}
+
+#[debug_handler]
+pub async fn create_page_image(
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, Path(path_page): Path<PathPage>,
+    Json(body): Json<CreatePageImageInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let image =
+        crate::page_images::create_page_image(&mut conn, user.account_id, path_page.page_id, body)
+            .await
+            .map_err(page_not_found)?;
+    Ok((StatusCode::CREATED, Json(image)))
+}
+
+pub fn create_page_image_docs(op: TransformOperation) -> TransformOperation {
+    op.id("create_page_image")
+        .description("Create page image")
+        .response::<201, Json<PageImage>>()
+        .security_requirement("Bearer")
+        .tag("pages")
+}
+
+#[debug_handler]
+pub async fn list_page_images(
+    State(pool): State<PgPool>, user: Authorized<ReadContent>, Path(path_page): Path<PathPage>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let images =
+        crate::page_images::list_page_images(&mut conn, user.account_id, path_page.page_id).await?;
+    Ok((StatusCode::OK, Json(images)))
+}
+
+pub fn list_page_images_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_page_images")
+        .description("List page images")
+        .response::<200, Json<Vec<PageImage>>>()
+        .security_requirement("Bearer")
+        .tag("pages")
+}
+
+#[debug_handler]
+pub async fn change_page_image(
+    State(pool): State<PgPool>, user: Authorized<WriteContent>, Path(path): Path<PathPageImage>,
+    Json(body): Json<ChangePageImageInput>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let page_image = crate::page_images::change_page_image(
+        &mut conn,
+        user.account_id,
+        path.page_id,
+        path.page_image_id,
+        body,
+    )
+    .await
+    .map_err(page_image_not_found)?;
+    Ok((StatusCode::OK, Json(page_image)))
+}
+
+pub fn change_page_image_docs(op: TransformOperation) -> TransformOperation {
+    op.id("change_page_image")
+        .description("Change page image")
+        .response::<200, Json<PageImage>>()
+        .security_requirement("Bearer")
+        .tag("pages")
+}
+
+#[debug_handler]
+pub async fn delete_page_image(
+    State(pool): State<PgPool>, user: Authorized<DeleteContent>, Path(path): Path<PathPageImage>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    crate::page_images::delete_page_image(
+        &mut conn,
+        user.account_id,
+        path.page_id,
+        path.page_image_id,
+    )
+    .await
+    .map_err(page_image_not_found)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn delete_page_image_docs(op: TransformOperation) -> TransformOperation {
+    op.id("delete_page_image")
+        .description("Delete page image")
+        .response::<204, ()>()
+        .security_requirement("Bearer")
+        .tag("pages")
+}
+
+pub fn routes() -> ApiRouter<AppState> {
+    ApiRouter::new()
+        .api_route("/", post_with(create_page, create_page_docs))
+        .api_route("/", get_with(list_pages, list_pages_docs))
+        .api_route("/:page_id", get_with(get_page, get_page_docs))
+        .api_route("/:page_id", patch_with(change_page, change_page_docs))
+        .api_route("/:page_id", delete_with(delete_page, delete_page_docs))
+        .api_route(
+            "/:page_id/translations",
+            get_with(list_page_translations, list_page_translations_docs),
+        )
+        .api_route(
+            "/:page_id/images",
+            get_with(list_page_images, list_page_images_docs),
+        )
+        .api_route(
+            "/:page_id/images",
+            post_with(create_page_image, create_page_image_docs),
+        )
+        .api_route(
+            "/:page_id/images/:page_image_id",
+            patch_with(change_page_image, change_page_image_docs),
+        )
+        .api_route(
+            "/:page_id/images/:page_image_id",
+            delete_with(delete_page_image, delete_page_image_docs),
+        )
+}
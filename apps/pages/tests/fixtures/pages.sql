@@ -0,0 +1,4 @@
+INSERT INTO pages.pages (id,account_id,author_id,title,slug,keywords,body_json,body_html,body_text,locale,translation_of,published_at) VALUES
+	 ('0186d5b4-7c2e-4f7a-8d3e-2a1c0b9e4d01','263b6188-aac6-45e9-9a2c-4728fdfd7ea1','ad38ffbe-dabe-43a1-b63a-4028e23090eb','About','about','{company}','{}','<p>About us</p>','About us','en-US',NULL,'2023-03-01 10:00:00'),
+	 ('0186d5b4-7c2e-4f7a-8d3e-2a1c0b9e4d02','263b6188-aac6-45e9-9a2c-4728fdfd7ea1','ad38ffbe-dabe-43a1-b63a-4028e23090eb','Sobre','sobre','{company}','{}','<p>Sobre nós</p>','Sobre nós','pt-BR','0186d5b4-7c2e-4f7a-8d3e-2a1c0b9e4d01','2023-03-01 10:00:00'),
+	 ('0186d5b4-7c2e-4f7a-8d3e-2a1c0b9e4d03','263b6188-aac6-45e9-9a2c-4728fdfd7ea1','ad38ffbe-dabe-43a1-b63a-4028e23090eb','Pricing','pricing','{}','{}','<p>Pricing</p>','Pricing','en-US',NULL,NULL);
@@ -0,0 +1,143 @@
+use accounts::authenticate_user_with_password;
+use pages::{public_routes, routes};
+use shared::testing::test_app;
+
+async fn test_token(pool: &sqlx::PgPool) -> String {
+    let access_token = authenticate_user_with_password(
+        pool,
+        uuid::uuid!("263b6188-aac6-45e9-9a2c-4728fdfd7ea1"),
+        "thiagovarela@consub.io".into(),
+        "123456".into(),
+    )
+    .await
+    .unwrap();
+    access_token.token
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_create_a_page(pool: sqlx::PgPool) {
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{address}/"))
+        .header("Authorization", format!("Bearer {token}"))
+        .json(&serde_json::json!({
+            "title": "About Us",
+            "keywords": ["company", "team"],
+            "body_json": {},
+            "body_html": "<p>About us</p>",
+            "body_text": "About us",
+            "locale": "en-US",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 201);
+
+    let vl = response.json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(vl["slug"], "about-us");
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "pages"))]
+async fn test_translation_of_must_be_another_page_of_the_account(pool: sqlx::PgPool) {
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let page_id = "0186d5b4-7c2e-4f7a-8d3e-2a1c0b9e4d03";
+    let change = |translation_of: &str| {
+        client
+            .patch(format!("{address}/{page_id}"))
+            .header("Authorization", format!("Bearer {token}"))
+            .json(&serde_json::json!({ "translation_of": translation_of }))
+            .send()
+    };
+
+    assert_eq!(change(page_id).await.unwrap().status(), 400);
+    let unknown_page_id = "0186d5b4-7c2e-4f7a-8d3e-2a1c0b9e4dff";
+    assert_eq!(change(unknown_page_id).await.unwrap().status(), 400);
+
+    let response = change("0186d5b4-7c2e-4f7a-8d3e-2a1c0b9e4d01")
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client
+        .get(format!("{address}/{unknown_page_id}"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "pages"))]
+async fn test_list_page_translations(pool: sqlx::PgPool) {
+    let token = test_token(&pool).await;
+
+    let address = test_app(pool, routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let url = format!(
+        "{}/{}/translations",
+        address, "0186d5b4-7c2e-4f7a-8d3e-2a1c0b9e4d02"
+    );
+    let response = client
+        .get(url)
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+
+    let vl = response.json::<Vec<serde_json::Value>>().await.unwrap();
+
+    assert_eq!(vl.len(), 1);
+    assert_eq!(vl[0]["slug"], "about");
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "pages"))]
+async fn test_public_page_by_slug(pool: sqlx::PgPool) {
+    let address = test_app(pool, public_routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{address}/about?locale=en-US"))
+        .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let vl = response.json::<serde_json::Value>().await.unwrap();
+
+    assert_eq!(vl["title"], "About");
+    assert_eq!(vl["translations"][0]["locale"], "pt-BR");
+    assert_eq!(vl["translations"][0]["slug"], "sobre");
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account", "pages"))]
+async fn test_unpublished_page_is_not_found(pool: sqlx::PgPool) {
+    let address = test_app(pool, public_routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{address}/pricing"))
+        .header("X-API-KEY", "e6af50e6-0ef3-4908-80c0-a83622d96d03")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 404);
+}
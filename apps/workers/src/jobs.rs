@@ -0,0 +1,241 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use shared::{pagination::CursorPagination, AppState};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+/// A running job whose lock is older than this is considered abandoned
+/// (e.g. the runner process died) and is picked again. Runners refresh the lock of
+/// the jobs they run well before, see `Runner::heartbeat`.
+pub(crate) const STALE_LOCK_SECONDS: f64 = 15.0 * 60.0;
+
+/// The longest a failed job waits for its next attempt.
+const MAX_BACKOFF_SECONDS: f64 = 60.0 * 60.0;
+
+/// Job is implemented by anything that should run in the background.
+/// The job itself is the payload, it's stored as JSON and deserialized by the runner.
+#[axum::async_trait]
+pub trait Job: Serialize + DeserializeOwned + Send + Sync + 'static {
+    /// Identifies the job in the queue, must be unique across apps.
+    const NAME: &'static str;
+    /// Attempts before the job is moved to the dead letter.
+    const MAX_ATTEMPTS: i32 = 5;
+
+    /// The account the job belongs to, account admins can only inspect their own jobs.
+    fn account_id(&self) -> Option<Uuid> {
+        None
+    }
+
+    async fn run(self, state: &AppState) -> Result<(), anyhow::Error>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct QueuedJob {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub account_id: Option<Uuid>,
+    pub name: String,
+    pub payload: serde_json::Value,
+    /// `pending` while waiting for its run_at (retries included), `running` while locked
+    /// by a runner and `dead` once it failed max_attempts times.
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: NaiveDateTime,
+    pub locked_at: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct FailedJobQuery {
+    /// Either `pending` for jobs waiting for a retry or `dead`, both are listed when empty.
+    pub status: Option<String>,
+    pub name: Option<String>,
+
+    #[serde(default, flatten)]
+    pub pagination: CursorPagination,
+}
+
+/// Adds a job to the queue to run as soon as a runner is available.
+/// Takes a connection so jobs can be enqueued within the caller's transaction.
+pub async fn enqueue<J: Job>(conn: &mut PgConnection, job: &J) -> Result<QueuedJob, anyhow::Error> {
+    insert_job(conn, job, None).await
+}
+
+/// Adds a job to the queue to run at the given time, or later.
+pub async fn enqueue_at<J: Job>(
+    conn: &mut PgConnection, job: &J, run_at: DateTime<Utc>,
+) -> Result<QueuedJob, anyhow::Error> {
+    insert_job(conn, job, Some(run_at)).await
+}
+
+/// The run_at column is a timestamp in the time zone of the database, like `NOW()` it's
+/// compared with, so the time is bound with its time zone and converted by the database.
+async fn insert_job<J: Job>(
+    conn: &mut PgConnection, job: &J, run_at: Option<DateTime<Utc>>,
+) -> Result<QueuedJob, anyhow::Error> {
+    let payload = serde_json::to_value(job)?;
+    let job = sqlx::query_as!(
+        QueuedJob,
+        r#"
+        INSERT INTO workers.jobs (account_id, name, payload, max_attempts, run_at)
+        VALUES ($1, $2, $3, $4, COALESCE($5::timestamptz, NOW()))
+        RETURNING *
+        "#,
+        job.account_id(),
+        J::NAME,
+        payload,
+        J::MAX_ATTEMPTS,
+        run_at,
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(job)
+}
+
+/// Locks the next due job among the given names, skipping rows locked by other runners.
+pub async fn dequeue(
+    conn: &mut PgConnection, names: &[String],
+) -> Result<Option<QueuedJob>, sqlx::Error> {
+    sqlx::query_as!(
+        QueuedJob,
+        r#"
+        UPDATE workers.jobs
+        SET status = 'running', locked_at = NOW(), attempts = attempts + 1
+        WHERE id = (
+            SELECT id FROM workers.jobs
+            WHERE name = ANY($1)
+            AND (
+                (status = 'pending' AND run_at <= NOW())
+                OR (status = 'running' AND locked_at < NOW() - make_interval(secs => $2))
+            )
+            ORDER BY run_at
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING *
+        "#,
+        names,
+        STALE_LOCK_SECONDS,
+    )
+    .fetch_optional(conn)
+    .await
+}
+
+/// Refreshes the lock of a running job, so a long job isn't taken as abandoned.
+pub async fn refresh_lock(conn: &mut PgConnection, id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE workers.jobs
+        SET locked_at = NOW()
+        WHERE id = $1 AND status = 'running'
+        "#,
+        id
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Completed jobs are removed, the table only keeps what is due or failed.
+pub async fn complete_job(conn: &mut PgConnection, id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM workers.jobs
+        WHERE id = $1
+        "#,
+        id
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Schedules the next attempt with an exponential backoff,
+/// or moves the job to the dead letter when it has no attempts left.
+pub async fn fail_job(
+    conn: &mut PgConnection, id: Uuid, error: String, backoff_seconds: f64,
+) -> Result<QueuedJob, sqlx::Error> {
+    sqlx::query_as!(
+        QueuedJob,
+        r#"
+        UPDATE workers.jobs
+        SET status = CASE WHEN attempts >= max_attempts THEN 'dead' ELSE 'pending' END,
+            run_at = NOW() + make_interval(secs => LEAST($3 * power(2, attempts - 1), $4)),
+            locked_at = NULL,
+            last_error = $2
+        WHERE id = $1
+        RETURNING *
+        "#,
+        id,
+        error,
+        backoff_seconds,
+        MAX_BACKOFF_SECONDS,
+    )
+    .fetch_one(conn)
+    .await
+}
+
+pub async fn list_failed_jobs(
+    conn: &mut PgConnection, account_id: Uuid, query: FailedJobQuery,
+) -> Result<Vec<QueuedJob>, sqlx::Error> {
+    sqlx::query_as!(
+        QueuedJob,
+        r#"
+        SELECT * FROM workers.jobs
+        WHERE account_id = $1
+        AND last_error IS NOT NULL
+        AND status <> 'running'
+        AND ($2::text IS NULL OR status = $2)
+        AND ($3::text IS NULL OR name = $3)
+        AND ($4::text IS NULL OR id > $4::uuid)
+        ORDER BY id LIMIT $5
+        "#,
+        account_id,
+        query.status,
+        query.name,
+        query.pagination.after,
+        query.pagination.take
+    )
+    .fetch_all(conn)
+    .await
+}
+
+pub async fn get_job(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid,
+) -> Result<QueuedJob, sqlx::Error> {
+    sqlx::query_as!(
+        QueuedJob,
+        r#"
+        SELECT * FROM workers.jobs
+        WHERE account_id = $1 AND id = $2
+        "#,
+        account_id,
+        id
+    )
+    .fetch_one(conn)
+    .await
+}
+
+/// Moves a dead job back to the queue with a fresh set of attempts.
+pub async fn retry_job(
+    conn: &mut PgConnection, account_id: Uuid, id: Uuid,
+) -> Result<QueuedJob, sqlx::Error> {
+    sqlx::query_as!(
+        QueuedJob,
+        r#"
+        UPDATE workers.jobs
+        SET status = 'pending', attempts = 0, run_at = NOW(), locked_at = NULL
+        WHERE account_id = $1 AND id = $2 AND status = 'dead'
+        RETURNING *
+        "#,
+        account_id,
+        id
+    )
+    .fetch_one(conn)
+    .await
+}
@@ -0,0 +1,7 @@
+mod jobs;
+mod router;
+mod runner;
+
+pub use jobs::{enqueue, enqueue_at, Job, QueuedJob};
+pub use router::routes;
+pub use runner::Runner;
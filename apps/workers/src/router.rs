@@ -0,0 +1,91 @@
+use accounts::{permissions::ManageAccount, Authorized};
+use aide::axum::routing::{get_with, post_with};
+use aide::axum::{ApiRouter, IntoApiResponse};
+use aide::transform::TransformOperation;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::{debug_handler, Json};
+use axum_extra::extract::Query;
+use schemars::JsonSchema;
+use shared::{AppError, AppState};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::jobs::{FailedJobQuery, QueuedJob};
+
+#[derive(Debug, serde::Deserialize, JsonSchema)]
+pub struct PathJob {
+    pub job_id: Uuid,
+}
+
+#[debug_handler]
+pub async fn list_failed_jobs(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>,
+    Query(query): Query<FailedJobQuery>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let jobs = crate::jobs::list_failed_jobs(&mut conn, user.account_id, query).await?;
+    Ok((StatusCode::OK, Json(jobs)))
+}
+
+pub fn list_failed_jobs_docs(op: TransformOperation) -> TransformOperation {
+    op.id("list_failed_jobs")
+        .description("List jobs waiting for a retry or in the dead letter")
+        .response::<200, Json<Vec<QueuedJob>>>()
+        .security_requirement("Bearer")
+        .tag("workers")
+}
+
+#[debug_handler]
+pub async fn get_job(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>, Path(path): Path<PathJob>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let job = crate::jobs::get_job(&mut conn, user.account_id, path.job_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("Job not found".into()),
+            e => e.into(),
+        })?;
+    Ok((StatusCode::OK, Json(job)))
+}
+
+pub fn get_job_docs(op: TransformOperation) -> TransformOperation {
+    op.id("get_job")
+        .description("Get a single job")
+        .response::<200, Json<QueuedJob>>()
+        .security_requirement("Bearer")
+        .tag("workers")
+}
+
+#[debug_handler]
+pub async fn retry_job(
+    State(pool): State<PgPool>, user: Authorized<ManageAccount>, Path(path): Path<PathJob>,
+) -> Result<impl IntoApiResponse, AppError> {
+    let mut conn = pool.acquire().await?;
+    let job = crate::jobs::retry_job(&mut conn, user.account_id, path.job_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("Dead job not found".into()),
+            e => e.into(),
+        })?;
+    Ok((StatusCode::OK, Json(job)))
+}
+
+pub fn retry_job_docs(op: TransformOperation) -> TransformOperation {
+    op.id("retry_job")
+        .description("Move a dead job back to the queue")
+        .response::<200, Json<QueuedJob>>()
+        .security_requirement("Bearer")
+        .tag("workers")
+}
+
+pub fn routes() -> ApiRouter<AppState> {
+    ApiRouter::new()
+        .api_route(
+            "/jobs/failed",
+            get_with(list_failed_jobs, list_failed_jobs_docs),
+        )
+        .api_route("/jobs/:job_id", get_with(get_job, get_job_docs))
+        .api_route("/jobs/:job_id/retry", post_with(retry_job, retry_job_docs))
+}
@@ -0,0 +1,196 @@
+use std::{any::Any, collections::HashMap, future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use shared::AppState;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::jobs::{complete_job, dequeue, fail_job, refresh_lock, Job, STALE_LOCK_SECONDS};
+
+type JobFuture = Pin<Box<dyn Future<Output = Result<(), anyhow::Error>> + Send>>;
+type JobHandler = Box<dyn Fn(AppState, serde_json::Value) -> JobFuture + Send + Sync>;
+
+/// Runner polls the jobs table and runs the registered jobs.
+/// Only registered job names are dequeued, so runners with different
+/// registrations can share the same queue.
+pub struct Runner {
+    state: AppState,
+    handlers: HashMap<&'static str, JobHandler>,
+    concurrency: usize,
+    poll_interval: Duration,
+    backoff: Duration,
+    heartbeat: Duration,
+}
+
+impl Runner {
+    pub fn new(state: AppState) -> Self {
+        Self {
+            state,
+            handlers: HashMap::new(),
+            concurrency: 4,
+            poll_interval: Duration::from_secs(1),
+            backoff: Duration::from_secs(10),
+            heartbeat: Duration::from_secs(60),
+        }
+    }
+
+    pub fn register<J: Job>(mut self) -> Self {
+        let handler: JobHandler = Box::new(|state, payload| {
+            Box::pin(async move {
+                let job: J = serde_json::from_value(payload)?;
+                job.run(&state).await
+            })
+        });
+        self.handlers.insert(J::NAME, handler);
+        self
+    }
+
+    /// How many jobs run at the same time.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// How long a worker waits before polling again when the queue is empty.
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// The wait before the first retry, doubled on each subsequent attempt.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// How often the lock of a running job is refreshed, it must be well below the
+    /// 15 minutes after which a locked job is considered abandoned.
+    pub fn heartbeat(mut self, heartbeat: Duration) -> Self {
+        self.heartbeat = heartbeat.min(Duration::from_secs_f64(STALE_LOCK_SECONDS / 3.0));
+        self
+    }
+
+    /// Dequeues and runs a single job, returns false when no job was due.
+    pub async fn run_next(&self) -> Result<bool, anyhow::Error> {
+        let names: Vec<String> = self.handlers.keys().map(|name| name.to_string()).collect();
+
+        let job = {
+            let mut conn = self.state.db_pool.acquire().await?;
+            match dequeue(&mut conn, &names).await? {
+                Some(job) => job,
+                None => return Ok(false),
+            }
+        };
+
+        let result = match self.handlers.get(job.name.as_str()) {
+            Some(handler) => {
+                self.run_job(job.id, handler(self.state.clone(), job.payload.clone()))
+                    .await
+            }
+            None => Err(anyhow::anyhow!("no handler registered for {}", job.name)),
+        };
+
+        let mut conn = self.state.db_pool.acquire().await?;
+        match result {
+            Ok(()) => complete_job(&mut conn, job.id).await?,
+            Err(e) => {
+                let job =
+                    fail_job(&mut conn, job.id, e.to_string(), self.backoff.as_secs_f64()).await?;
+                warn!(
+                    job_id = %job.id,
+                    job_name = %job.name,
+                    attempts = job.attempts,
+                    status = %job.status,
+                    "job failed: {}",
+                    e
+                );
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Runs the job in its own task, so a panic fails the job instead of stopping the worker,
+    /// and refreshes its lock until it finishes.
+    async fn run_job(&self, job_id: Uuid, job: JobFuture) -> Result<(), anyhow::Error> {
+        let mut handle = tokio::spawn(job);
+        let mut heartbeat = tokio::time::interval(self.heartbeat);
+        // The first tick is immediate, the lock was just taken.
+        heartbeat.tick().await;
+
+        loop {
+            tokio::select! {
+                result = &mut handle => {
+                    return match result {
+                        Ok(result) => result,
+                        Err(e) if e.is_panic() => Err(anyhow::anyhow!(
+                            "job panicked: {}",
+                            panic_message(e.into_panic())
+                        )),
+                        Err(e) => Err(e.into()),
+                    };
+                }
+                _ = heartbeat.tick() => {
+                    let refreshed = match self.state.db_pool.acquire().await {
+                        Ok(mut conn) => refresh_lock(&mut conn, job_id).await,
+                        Err(e) => Err(e),
+                    };
+                    if let Err(e) = refreshed {
+                        warn!(job_id = %job_id, "failed to refresh the job lock: {}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs the workers until the process stops.
+    pub async fn run(self) {
+        info!(
+            jobs = ?self.handlers.keys().collect::<Vec<_>>(),
+            concurrency = self.concurrency,
+            "Starting job runner"
+        );
+
+        let runner = Arc::new(self);
+        let workers: Vec<JoinHandle<()>> = (0..runner.concurrency)
+            .map(|_| {
+                let runner = runner.clone();
+                tokio::spawn(async move { runner.work().await })
+            })
+            .collect();
+
+        for worker in workers {
+            if let Err(e) = worker.await {
+                error!("job worker stopped: {}", e);
+            }
+        }
+    }
+
+    /// Runs the workers in the background, e.g. next to the http server.
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(self.run())
+    }
+
+    async fn work(&self) {
+        loop {
+            match self.run_next().await {
+                Ok(true) => continue,
+                Ok(false) => tokio::time::sleep(self.poll_interval).await,
+                Err(e) => {
+                    error!("job runner error: {}", e);
+                    tokio::time::sleep(self.poll_interval).await;
+                }
+            }
+        }
+    }
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use accounts::authenticate_user_with_password;
+use serde::{Deserialize, Serialize};
+use shared::{testing::test_app, AppState};
+use uuid::Uuid;
+use workers::{enqueue, routes, Job, Runner};
+
+const ACCOUNT_ID: Uuid = uuid::uuid!("263b6188-aac6-45e9-9a2c-4728fdfd7ea1");
+
+#[derive(Serialize, Deserialize)]
+struct SucceedingJob;
+
+#[axum::async_trait]
+impl Job for SucceedingJob {
+    const NAME: &'static str = "test.succeeding";
+
+    async fn run(self, _state: &AppState) -> Result<(), anyhow::Error> {
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct FailingJob;
+
+#[axum::async_trait]
+impl Job for FailingJob {
+    const NAME: &'static str = "test.failing";
+    const MAX_ATTEMPTS: i32 = 2;
+
+    fn account_id(&self) -> Option<Uuid> {
+        Some(ACCOUNT_ID)
+    }
+
+    async fn run(self, _state: &AppState) -> Result<(), anyhow::Error> {
+        Err(anyhow::anyhow!("boom"))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PanickingJob;
+
+#[axum::async_trait]
+impl Job for PanickingJob {
+    const NAME: &'static str = "test.panicking";
+
+    async fn run(self, _state: &AppState) -> Result<(), anyhow::Error> {
+        panic!("kaboom")
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SlowJob;
+
+#[axum::async_trait]
+impl Job for SlowJob {
+    const NAME: &'static str = "test.slow";
+
+    async fn run(self, _state: &AppState) -> Result<(), anyhow::Error> {
+        tokio::time::sleep(Duration::from_millis(800)).await;
+        Ok(())
+    }
+}
+
+fn test_runner(pool: &sqlx::PgPool) -> Runner {
+    let state = AppState {
+        db_pool: pool.clone(),
+        opendal: shared::testing::test_opendal_uploader(),
+        mailer: shared::testing::test_mailer(),
+        dns: shared::testing::test_dns_resolver(),
+    };
+    Runner::new(state)
+        .register::<SucceedingJob>()
+        .register::<FailingJob>()
+        .register::<PanickingJob>()
+        .register::<SlowJob>()
+        .backoff(Duration::ZERO)
+}
+
+async fn job_status(pool: &sqlx::PgPool, id: Uuid) -> Option<(String, i32)> {
+    sqlx::query_as("SELECT status, attempts FROM workers.jobs WHERE id = $1")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .unwrap()
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_completed_job_is_removed(pool: sqlx::PgPool) {
+    let runner = test_runner(&pool);
+
+    let job = {
+        let mut conn = pool.acquire().await.unwrap();
+        enqueue(&mut conn, &SucceedingJob).await.unwrap()
+    };
+
+    assert!(runner.run_next().await.unwrap());
+    assert!(!runner.run_next().await.unwrap());
+    assert_eq!(job_status(&pool, job.id).await, None);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_failed_job_is_retried_then_dead(pool: sqlx::PgPool) {
+    let runner = test_runner(&pool);
+
+    let job = {
+        let mut conn = pool.acquire().await.unwrap();
+        enqueue(&mut conn, &FailingJob).await.unwrap()
+    };
+
+    assert!(runner.run_next().await.unwrap());
+    assert_eq!(
+        job_status(&pool, job.id).await,
+        Some(("pending".to_string(), 1))
+    );
+
+    assert!(runner.run_next().await.unwrap());
+    assert_eq!(
+        job_status(&pool, job.id).await,
+        Some(("dead".to_string(), 2))
+    );
+
+    // Dead jobs aren't picked anymore.
+    assert!(!runner.run_next().await.unwrap());
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_panicking_job_is_failed(pool: sqlx::PgPool) {
+    let runner = test_runner(&pool);
+
+    let job = {
+        let mut conn = pool.acquire().await.unwrap();
+        enqueue(&mut conn, &PanickingJob).await.unwrap()
+    };
+
+    assert!(runner.run_next().await.unwrap());
+    assert_eq!(
+        job_status(&pool, job.id).await,
+        Some(("pending".to_string(), 1))
+    );
+
+    let last_error: String =
+        sqlx::query_scalar("SELECT last_error FROM workers.jobs WHERE id = $1")
+            .bind(job.id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(last_error, "job panicked: kaboom");
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_running_job_lock_is_refreshed(pool: sqlx::PgPool) {
+    let runner = test_runner(&pool).heartbeat(Duration::from_millis(100));
+
+    let job = {
+        let mut conn = pool.acquire().await.unwrap();
+        enqueue(&mut conn, &SlowJob).await.unwrap()
+    };
+
+    let running = tokio::spawn(async move { runner.run_next().await.unwrap() });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // As if the lock was taken long ago, the next heartbeat refreshes it.
+    sqlx::query("UPDATE workers.jobs SET locked_at = NOW() - interval '1 hour' WHERE id = $1")
+        .bind(job.id)
+        .execute(&pool)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let stale: bool = sqlx::query_scalar(
+        "SELECT locked_at < NOW() - interval '1 minute' FROM workers.jobs WHERE id = $1",
+    )
+    .bind(job.id)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert!(!stale);
+
+    assert!(running.await.unwrap());
+    assert_eq!(job_status(&pool, job.id).await, None);
+}
+
+#[sqlx::test(migrations = "../../migrations", fixtures("account"))]
+async fn test_list_and_retry_dead_jobs(pool: sqlx::PgPool) {
+    let runner = test_runner(&pool);
+
+    let job = {
+        let mut conn = pool.acquire().await.unwrap();
+        enqueue(&mut conn, &FailingJob).await.unwrap()
+    };
+    runner.run_next().await.unwrap();
+    runner.run_next().await.unwrap();
+
+    let token = authenticate_user_with_password(
+        &pool,
+        ACCOUNT_ID,
+        "thiagovarela@consub.io".into(),
+        "123456".into(),
+    )
+    .await
+    .unwrap()
+    .token;
+
+    let address = test_app(pool.clone(), routes()).await;
+
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{address}/jobs/failed?status=dead"))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+
+    let vl = response.json::<Vec<serde_json::Value>>().await.unwrap();
+
+    assert_eq!(vl.len(), 1);
+    assert_eq!(vl[0]["name"], "test.failing");
+    assert_eq!(vl[0]["last_error"], "boom");
+
+    let response = client
+        .post(format!("{address}/jobs/{}/retry", job.id))
+        .header("Authorization", format!("Bearer {token}"))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        job_status(&pool, job.id).await,
+        Some(("pending".to_string(), 0))
+    );
+}
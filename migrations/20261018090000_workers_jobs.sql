@@ -0,0 +1,22 @@
+CREATE SCHEMA IF NOT EXISTS workers;
+
+-- Jobs are picked by workers using SELECT ... FOR UPDATE SKIP LOCKED,
+-- so several runners can share the same table without stepping on each other.
+-- A job that keeps failing until max_attempts is moved to the 'dead' status (dead letter).
+CREATE TABLE workers.jobs (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    account_id UUID NULL REFERENCES accounts.accounts (id),
+    name VARCHAR(255) NOT NULL,
+    payload JSONB NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 5,
+    run_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    locked_at TIMESTAMP NULL,
+    last_error TEXT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX jobs_status_run_at_idx ON workers.jobs (status, run_at);
+CREATE INDEX jobs_account_id_status_idx ON workers.jobs (account_id, status);
+SELECT setup_tgr_updated_at('workers.jobs');
@@ -0,0 +1,7 @@
+-- Roles are checked in code (accounts::Role), the constraint only guards against typos.
+ALTER TABLE accounts.users
+    ADD COLUMN role TEXT NOT NULL DEFAULT 'viewer'
+    CHECK (role IN ('owner', 'admin', 'editor', 'author', 'viewer'));
+
+-- Every existing user was able to do anything, so they keep doing so.
+UPDATE accounts.users SET role = 'owner';
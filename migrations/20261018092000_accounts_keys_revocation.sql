@@ -0,0 +1,6 @@
+-- account_keys was created with the updated_at trigger but without the column,
+-- so any UPDATE on it failed. revoked_at invalidates a key (and its tokens) right away.
+ALTER TABLE accounts.account_keys
+    ADD COLUMN revoked_at TIMESTAMP NULL,
+    ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    ADD COLUMN updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP;
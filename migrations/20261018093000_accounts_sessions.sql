@@ -0,0 +1,20 @@
+-- A session is created on login and holds the refresh token (hashed), which is rotated on every refresh.
+-- The previous hash is kept to detect a refresh token being reused, which revokes the session.
+CREATE TABLE accounts.sessions (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    account_id UUID NOT NULL REFERENCES accounts.accounts (id),
+    user_id UUID NOT NULL REFERENCES accounts.users (id),
+    refresh_token_hash TEXT NOT NULL,
+    previous_refresh_token_hash TEXT NULL,
+    user_agent TEXT NULL,
+    ip_address TEXT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    last_used_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    revoked_at TIMESTAMP NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX sessions_refresh_token_hash_idx ON accounts.sessions (refresh_token_hash);
+CREATE INDEX sessions_previous_refresh_token_hash_idx ON accounts.sessions (previous_refresh_token_hash);
+CREATE INDEX sessions_user_id_idx ON accounts.sessions (user_id);
+SELECT setup_tgr_updated_at('accounts.sessions');
@@ -0,0 +1,22 @@
+-- API keys identify an account on the public API (X-Api-Key header).
+-- Only a sha256 hash of the key is stored, the prefix is kept to tell keys apart.
+CREATE TABLE accounts.api_keys (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    account_id UUID NOT NULL REFERENCES accounts.accounts (id),
+    name VARCHAR(100) NOT NULL,
+    prefix VARCHAR(20) NOT NULL,
+    key_hash TEXT NOT NULL,
+    scopes TEXT[] NOT NULL DEFAULT '{}',
+    -- Keys with the admin scope call the admin api as the user who created them, with its role.
+    user_id UUID NULL REFERENCES accounts.users (id),
+    last_used_at TIMESTAMP NULL,
+    revoked_at TIMESTAMP NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX api_keys_key_hash_idx ON accounts.api_keys (key_hash);
+CREATE INDEX api_keys_account_id_idx ON accounts.api_keys (account_id);
+SELECT setup_tgr_updated_at('accounts.api_keys');
+
+-- Account key ids used to be the api keys, they aren't carried over: they're in the header
+-- of every access token. Owners issue new keys at /accounts/api-keys.
@@ -0,0 +1,14 @@
+-- Tokens sent by email to reset a forgotten password, only their hash is stored.
+-- A token can be used once and expires shortly after being issued.
+CREATE TABLE accounts.password_resets (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    user_id UUID NOT NULL REFERENCES accounts.users (id),
+    token_hash TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX password_resets_token_hash_idx ON accounts.password_resets (token_hash);
+CREATE INDEX password_resets_user_id_idx ON accounts.password_resets (user_id);
+SELECT setup_tgr_updated_at('accounts.password_resets');
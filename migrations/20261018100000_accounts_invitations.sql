@@ -0,0 +1,20 @@
+-- Removed users are kept for the content they authored, they can't sign in anymore.
+ALTER TABLE accounts.users ADD COLUMN removed_at TIMESTAMP NULL;
+
+-- An invitation is emailed with a token (only its hash is stored), the invitee accepts it by choosing a password.
+CREATE TABLE accounts.invitations (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    account_id UUID NOT NULL REFERENCES accounts.accounts (id),
+    email VARCHAR(255) NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('owner', 'admin', 'editor', 'author', 'viewer')),
+    token_hash TEXT NOT NULL,
+    invited_by_id UUID NOT NULL REFERENCES accounts.users (id),
+    expires_at TIMESTAMP NOT NULL,
+    accepted_at TIMESTAMP NULL,
+    revoked_at TIMESTAMP NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX invitations_token_hash_idx ON accounts.invitations (token_hash);
+CREATE INDEX invitations_account_id_email_idx ON accounts.invitations (account_id, email);
+SELECT setup_tgr_updated_at('accounts.invitations');
@@ -0,0 +1,13 @@
+-- Every password login attempt, failures slow down and then lock further attempts
+-- for the same email or ip address, owners can review them.
+CREATE TABLE accounts.login_attempts (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    account_id UUID NOT NULL REFERENCES accounts.accounts (id),
+    email VARCHAR(255) NOT NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    succeeded BOOLEAN NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX login_attempts_account_id_email_idx ON accounts.login_attempts (account_id, email, created_at);
+CREATE INDEX login_attempts_ip_address_idx ON accounts.login_attempts (ip_address, created_at);
@@ -0,0 +1,37 @@
+-- TOTP secret of a user, two-factor is on once enabled_at is set (after a first code is verified).
+-- last_used_step keeps a code from being used twice.
+CREATE TABLE accounts.user_totps (
+    user_id UUID NOT NULL REFERENCES accounts.users (id),
+    secret TEXT NOT NULL,
+    enabled_at TIMESTAMP NULL,
+    last_used_step BIGINT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (user_id)
+);
+SELECT setup_tgr_updated_at('accounts.user_totps');
+
+-- Single use codes replacing a TOTP code when the device is lost, only their hash is stored.
+CREATE TABLE accounts.recovery_codes (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    user_id UUID NOT NULL REFERENCES accounts.users (id),
+    code_hash TEXT NOT NULL,
+    used_at TIMESTAMP NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX recovery_codes_user_id_idx ON accounts.recovery_codes (user_id);
+
+-- Issued when the password of a user with two-factor is verified, exchanged for a session with a code.
+CREATE TABLE accounts.login_challenges (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    account_id UUID NOT NULL REFERENCES accounts.accounts (id),
+    user_id UUID NOT NULL REFERENCES accounts.users (id),
+    token_hash TEXT NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP NOT NULL,
+    completed_at TIMESTAMP NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX login_challenges_token_hash_idx ON accounts.login_challenges (token_hash);
+SELECT setup_tgr_updated_at('accounts.login_challenges');
@@ -0,0 +1,47 @@
+-- OpenID Connect providers an account lets its users sign in with (authorization code + PKCE).
+-- The endpoints are discovered from the issuer when the provider is added.
+CREATE TABLE accounts.oidc_providers (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    account_id UUID NOT NULL REFERENCES accounts.accounts (id),
+    name VARCHAR(100) NOT NULL,
+    slug VARCHAR(100) NOT NULL,
+    issuer TEXT NOT NULL,
+    client_id TEXT NOT NULL,
+    client_secret TEXT NULL,
+    redirect_uri TEXT NOT NULL,
+    scopes TEXT NOT NULL DEFAULT 'openid email profile',
+    authorization_endpoint TEXT NOT NULL,
+    token_endpoint TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX oidc_providers_account_id_slug_idx ON accounts.oidc_providers (account_id, slug);
+SELECT setup_tgr_updated_at('accounts.oidc_providers');
+
+-- Identities of a user at the providers, besides accounts.passwords.
+CREATE TABLE accounts.identities (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    user_id UUID NOT NULL REFERENCES accounts.users (id),
+    provider_id UUID NOT NULL REFERENCES accounts.oidc_providers (id) ON DELETE CASCADE,
+    subject TEXT NOT NULL,
+    email VARCHAR(255) NULL,
+    last_login_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX identities_provider_id_subject_idx ON accounts.identities (provider_id, subject);
+CREATE INDEX identities_user_id_idx ON accounts.identities (user_id);
+SELECT setup_tgr_updated_at('accounts.identities');
+
+-- A login started with a provider, the state comes back in the callback (only its hash is stored).
+CREATE TABLE accounts.oidc_states (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    provider_id UUID NOT NULL REFERENCES accounts.oidc_providers (id) ON DELETE CASCADE,
+    state_hash TEXT NOT NULL,
+    code_verifier TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX oidc_states_state_hash_idx ON accounts.oidc_states (state_hash);
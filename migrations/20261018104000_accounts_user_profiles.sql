@@ -0,0 +1,6 @@
+-- What's shown about a user on the content they author, the avatar is an image of media.images.
+ALTER TABLE accounts.users
+    ADD COLUMN display_name VARCHAR(100) NULL,
+    ADD COLUMN bio TEXT NULL,
+    ADD COLUMN avatar_media_id UUID NULL,
+    ADD COLUMN social_links JSONB NOT NULL DEFAULT '[]';
@@ -0,0 +1,4 @@
+-- Clippings are now behind their feature flag, accounts which never set it keep them.
+UPDATE accounts.accounts
+SET feature_flags = feature_flags || '{"clipping_enabled": true}'
+WHERE NOT feature_flags ? 'clipping_enabled';
@@ -0,0 +1,15 @@
+-- Domains an account serves its public content from, e.g. blog.customer.com.
+-- A domain is used once verified with a DNS TXT record holding the verification token.
+CREATE TABLE accounts.custom_domains (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    account_id UUID NOT NULL REFERENCES accounts.accounts (id),
+    domain VARCHAR(253) NOT NULL,
+    verification_token TEXT NOT NULL,
+    verified_at TIMESTAMP NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX custom_domains_account_id_domain_idx ON accounts.custom_domains (account_id, domain);
+-- Any account can add a domain, only one can verify it.
+CREATE UNIQUE INDEX custom_domains_verified_domain_idx ON accounts.custom_domains (domain) WHERE verified_at IS NOT NULL;
+SELECT setup_tgr_updated_at('accounts.custom_domains');
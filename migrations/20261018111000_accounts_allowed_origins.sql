@@ -0,0 +1,3 @@
+-- Origins allowed to call the public api from a browser besides the account origin,
+-- e.g. https://*.customer.com for its subdomains or * for any origin.
+ALTER TABLE accounts.accounts ADD COLUMN allowed_origins TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,30 @@
+-- Accounts are on a plan, which sets the rate limits of their clients.
+ALTER TABLE accounts.accounts ADD COLUMN plan TEXT NOT NULL DEFAULT 'free';
+
+-- Requests a client can do on a group of routes: `capacity` at once, refilled at `refill_per_minute`.
+-- Clients without an account (e.g. logging in) are on the `anonymous` plan,
+-- groups without a row for the plan aren't limited.
+CREATE TABLE accounts.plan_rate_limits (
+    plan TEXT NOT NULL,
+    route_group TEXT NOT NULL,
+    capacity INTEGER NOT NULL CHECK (capacity > 0),
+    refill_per_minute INTEGER NOT NULL CHECK (refill_per_minute > 0),
+    PRIMARY KEY (plan, route_group)
+);
+INSERT INTO accounts.plan_rate_limits (plan, route_group, capacity, refill_per_minute) VALUES
+    ('anonymous', 'admin', 30, 30),
+    ('anonymous', 'public', 60, 60),
+    ('anonymous', 'analytics', 60, 60),
+    ('free', 'admin', 300, 300),
+    ('free', 'public', 600, 600),
+    ('free', 'analytics', 600, 600),
+    ('pro', 'admin', 1200, 1200),
+    ('pro', 'public', 3000, 3000),
+    ('pro', 'analytics', 6000, 6000);
+
+-- Token buckets of the postgres rate limit backend, losing them in a crash only resets the limits.
+CREATE UNLOGGED TABLE public.rate_limit_buckets (
+    key TEXT PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,21 @@
+-- Administrative changes of the accounts, recorded by the admin routes of every app.
+CREATE SCHEMA audit;
+
+CREATE TABLE audit.events (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    account_id UUID NOT NULL REFERENCES accounts.accounts (id),
+    -- Not a foreign key, the events outlive the users who did them.
+    actor_id UUID NULL,
+    entity_type TEXT NOT NULL,
+    entity_id UUID NOT NULL,
+    action TEXT NOT NULL,
+    -- The fields that changed, or the whole entity when it's created or deleted.
+    before JSONB NULL,
+    after JSONB NULL,
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+-- The ids are ulids, so they sort the events of an account from the newest.
+CREATE INDEX events_account_id_id_idx ON audit.events (account_id, id DESC);
+CREATE INDEX events_account_id_entity_idx ON audit.events (account_id, entity_type, entity_id);
@@ -0,0 +1,5 @@
+-- The variants generated from the uploaded originals, null on the images resized by the admin.
+ALTER TABLE media.images_set
+    ADD COLUMN format TEXT NULL,
+    ADD COLUMN width INT NULL,
+    ADD COLUMN height INT NULL;
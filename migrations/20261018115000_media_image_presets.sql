@@ -0,0 +1,17 @@
+-- The transformations an account allows on its images, any other size is refused.
+-- A missing width or height keeps the aspect ratio. Presets also set the format and the quality,
+-- otherwise every format and quality of an allowed size could be asked and stored. A missing
+-- format keeps the format of the original, a missing quality uses the default one.
+CREATE TABLE media.image_presets (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    account_id UUID NOT NULL REFERENCES accounts.accounts (id),
+    width INT NULL,
+    height INT NULL,
+    fit TEXT NOT NULL DEFAULT 'contain',
+    format TEXT NULL,
+    quality INT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX image_presets_account_id_size_idx ON media.image_presets (account_id, COALESCE(width, 0), COALESCE(height, 0), fit, COALESCE(format, ''), COALESCE(quality, 0));
+SELECT setup_tgr_updated_at('media.image_presets');
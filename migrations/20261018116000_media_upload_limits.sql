@@ -0,0 +1,14 @@
+-- The largest original an account on the plan can upload, in bytes and in pixels, and the
+-- largest document and audio or video file it can upload, in bytes. Images uploaded as files
+-- keep the image limit. Plans without a row get the limits of the free plan.
+CREATE TABLE media.plan_upload_limits (
+    plan TEXT PRIMARY KEY,
+    max_file_size BIGINT NOT NULL CHECK (max_file_size > 0),
+    max_width INTEGER NOT NULL CHECK (max_width > 0),
+    max_height INTEGER NOT NULL CHECK (max_height > 0),
+    max_document_size BIGINT NOT NULL CHECK (max_document_size > 0),
+    max_media_size BIGINT NOT NULL CHECK (max_media_size > 0)
+);
+INSERT INTO media.plan_upload_limits (plan, max_file_size, max_width, max_height, max_document_size, max_media_size) VALUES
+    ('free', 10000000, 6000, 6000, 25000000, 250000000),
+    ('pro', 40000000, 12000, 12000, 100000000, 2000000000);
@@ -0,0 +1,16 @@
+-- Documents, audio and video of the media library, anything uploaded which isn't an image.
+-- The path is where the file is stored, its public url is the CDN path followed by it.
+CREATE TABLE media.files (
+    id UUID PRIMARY KEY DEFAULT generate_ulid_uuid(),
+    account_id UUID NOT NULL REFERENCES accounts.accounts (id),
+    file_name TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    checksum TEXT NOT NULL,
+    path TEXT NOT NULL,
+    metadata JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX files_account_id_idx ON media.files (account_id);
+SELECT setup_tgr_updated_at('media.files');
@@ -0,0 +1,148 @@
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{client::ClientInfo, AppError};
+
+/// Fields left out of the diffs, they change with every write.
+const IGNORED_FIELDS: &[&str] = &["updated_at"];
+
+/// Who did a change and from where, the accounts app builds it from the user (`user.actor(client)`).
+#[derive(Debug, Clone)]
+pub struct Actor {
+    pub account_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub client: ClientInfo,
+}
+
+/// What was done to an entity, stored lowercase in `audit.events.action`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Created,
+    Changed,
+    Deleted,
+    Revoked,
+    Expired,
+    Verified,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Created => "created",
+            Action::Changed => "changed",
+            Action::Deleted => "deleted",
+            Action::Revoked => "revoked",
+            Action::Expired => "expired",
+            Action::Verified => "verified",
+        }
+    }
+}
+
+/// Keeps the top level fields which differ between the two versions of an entity,
+/// when there's only one (created or deleted) it's kept whole.
+fn diff(before: Option<Value>, after: Option<Value>) -> (Option<Value>, Option<Value>) {
+    match (before, after) {
+        (Some(Value::Object(before)), Some(Value::Object(after))) => {
+            let fields: BTreeSet<&String> = before
+                .keys()
+                .chain(after.keys())
+                .filter(|field| !IGNORED_FIELDS.contains(&field.as_str()))
+                .filter(|field| before.get(*field) != after.get(*field))
+                .collect();
+            let pick = |object: &Map<String, Value>| {
+                let changed: Map<String, Value> = fields
+                    .iter()
+                    .filter_map(|field| Some(((*field).clone(), object.get(*field)?.clone())))
+                    .collect();
+                Some(Value::Object(changed))
+            };
+            (pick(&before), pick(&after))
+        }
+        (before, after) => (before, after),
+    }
+}
+
+/// Records an action of the actor on an entity with the diff of its versions.
+/// The admin routes call it on each mutation, within their transaction when they have one.
+pub async fn record<T: Serialize>(
+    conn: &mut PgConnection, actor: &Actor, action: Action, entity_type: &str, entity_id: Uuid,
+    before: Option<&T>, after: Option<&T>,
+) -> Result<(), AppError> {
+    let (before, after) = diff(
+        before.map(serde_json::to_value).transpose()?,
+        after.map(serde_json::to_value).transpose()?,
+    );
+
+    sqlx::query!(
+        r#"
+        INSERT INTO audit.events (
+            account_id, actor_id, entity_type, entity_id, action, before, after,
+            ip_address, user_agent
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        "#,
+        actor.account_id,
+        actor.user_id,
+        entity_type,
+        entity_id,
+        action.as_str(),
+        before,
+        after,
+        actor.client.ip_address,
+        actor.client.user_agent,
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn created<T: Serialize>(
+    conn: &mut PgConnection, actor: &Actor, entity_type: &str, entity_id: Uuid, entity: &T,
+) -> Result<(), AppError> {
+    record(
+        conn,
+        actor,
+        Action::Created,
+        entity_type,
+        entity_id,
+        None,
+        Some(entity),
+    )
+    .await
+}
+
+pub async fn changed<T: Serialize>(
+    conn: &mut PgConnection, actor: &Actor, entity_type: &str, entity_id: Uuid, before: &T,
+    after: &T,
+) -> Result<(), AppError> {
+    record(
+        conn,
+        actor,
+        Action::Changed,
+        entity_type,
+        entity_id,
+        Some(before),
+        Some(after),
+    )
+    .await
+}
+
+pub async fn deleted<T: Serialize>(
+    conn: &mut PgConnection, actor: &Actor, entity_type: &str, entity_id: Uuid, entity: &T,
+) -> Result<(), AppError> {
+    record(
+        conn,
+        actor,
+        Action::Deleted,
+        entity_type,
+        entity_id,
+        Some(entity),
+        None,
+    )
+    .await
+}
@@ -0,0 +1,60 @@
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::request::Parts,
+};
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+/// Information about the client doing the request.
+///
+/// The address is only taken from a header when `CLIENT_IP_HEADER` names the header the proxy
+/// in front of the api sets (fly.io sets `Fly-Client-IP`), anything else can be sent by the client.
+/// Otherwise it's the address of the connection.
+#[derive(Debug, Clone, Default, aide::OperationIo)]
+pub struct ClientInfo {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+fn header_value(parts: &Parts, name: &str) -> Option<String> {
+    parts
+        .headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// The address in the header of the trusted proxy. A proxy appends the address it saw to the ones
+/// the client sent (e.g. `X-Forwarded-For`), so only the right most one is used.
+fn proxy_ip_address(parts: &Parts) -> Option<IpAddr> {
+    let name = std::env::var("CLIENT_IP_HEADER").ok()?;
+    header_value(parts, name.trim())?
+        .rsplit(',')
+        .next()
+        .and_then(|ip| ip.trim().parse().ok())
+}
+
+#[axum::async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ip_address = proxy_ip_address(parts).or_else(|| {
+            parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(address)| address.ip())
+        });
+
+        Ok(ClientInfo {
+            ip_address: ip_address.map(|ip| ip.to_string()),
+            user_agent: header_value(parts, "User-Agent"),
+        })
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+use serde::Deserialize;
+
+/// Looks up DNS TXT records, the resolver is picked when the api starts (see `DNS_RESOLVER_URL`).
+#[axum::async_trait]
+pub trait TxtResolver: Send + Sync {
+    /// The values of the TXT records of the name, empty when there's none.
+    async fn txt_records(&self, name: &str) -> Result<Vec<String>, anyhow::Error>;
+}
+
+#[derive(Clone)]
+pub struct DnsResolver(pub Arc<dyn TxtResolver>);
+
+impl Deref for DnsResolver {
+    type Target = Arc<dyn TxtResolver>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Resolves with DNS over HTTPS, using the JSON api of Cloudflare or Google.
+#[derive(Debug, Clone)]
+pub struct DohResolver {
+    pub url: String,
+    client: reqwest::Client,
+}
+
+impl DohResolver {
+    pub fn new(url: impl Into<String>) -> Self {
+        DohResolver {
+            url: url.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+impl Default for DohResolver {
+    fn default() -> Self {
+        DohResolver::new("https://cloudflare-dns.com/dns-query")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DohResponse {
+    #[serde(rename = "Answer", default)]
+    answer: Vec<DohAnswer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DohAnswer {
+    #[serde(rename = "type")]
+    record_type: u16,
+    data: String,
+}
+
+const TXT_RECORD_TYPE: u16 = 16;
+
+#[axum::async_trait]
+impl TxtResolver for DohResolver {
+    async fn txt_records(&self, name: &str) -> Result<Vec<String>, anyhow::Error> {
+        let response: DohResponse = self
+            .client
+            .get(&self.url)
+            .query(&[("name", name), ("type", "TXT")])
+            .header("Accept", "application/dns-json")
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Ok(response
+            .answer
+            .into_iter()
+            .filter(|answer| answer.record_type == TXT_RECORD_TYPE)
+            .map(|answer| txt_value(&answer.data))
+            .collect())
+    }
+}
+
+/// Long values are split in quoted strings of 255 characters, e.g. `"abc" "def"`.
+fn txt_value(data: &str) -> String {
+    if data.contains('"') {
+        data.split('"').skip(1).step_by(2).collect()
+    } else {
+        data.to_string()
+    }
+}
+
+/// Answers with the records it was given, for tests.
+#[derive(Debug, Clone, Default)]
+pub struct StaticResolver {
+    records: Arc<Mutex<HashMap<String, Vec<String>>>>,
+}
+
+impl StaticResolver {
+    pub fn set_txt_records(&self, name: &str, values: Vec<String>) {
+        self.records.lock().unwrap().insert(name.into(), values);
+    }
+}
+
+#[axum::async_trait]
+impl TxtResolver for StaticResolver {
+    async fn txt_records(&self, name: &str) -> Result<Vec<String>, anyhow::Error> {
+        Ok(self
+            .records
+            .lock()
+            .unwrap()
+            .get(name)
+            .cloned()
+            .unwrap_or_default())
+    }
+}
@@ -1,5 +1,5 @@
 use axum::extract::FromRef;
-use axum::http::StatusCode;
+use axum::http::{header, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use serde_json::json;
@@ -7,7 +7,12 @@ use sqlx::{postgres::PgPoolOptions, Executor, PgPool};
 use std::ops::Deref;
 use std::sync::Arc;
 
+pub mod audit;
+pub mod client;
+pub mod dns;
+pub mod mail;
 pub mod pagination;
+pub mod rate_limit;
 
 pub async fn database_pool(db_url: &str) -> PgPool {
     PgPoolOptions::new()
@@ -39,6 +44,8 @@ impl Deref for OpendalUploader {
 pub struct AppState {
     pub db_pool: PgPool,
     pub opendal: OpendalUploader,
+    pub mailer: mail::Mailer,
+    pub dns: dns::DnsResolver,
 }
 
 #[derive(Debug, aide::OperationIo)]
@@ -49,10 +56,37 @@ pub enum AppError {
     Unauthorized(String),
     NotFound(String),
     ValidationError(String),
+    /// Refused by the rate limits or the login throttling, the response tells when to retry
+    /// with `Retry-After`, and the state of the rate limit when it's the one refusing.
+    TooManyRequests {
+        message: String,
+        retry_after_seconds: u64,
+        rate_limit: Option<rate_limit::RateLimit>,
+    },
+}
+
+impl AppError {
+    /// Refused by a rate limit which ran out, see [`rate_limit::RateLimit::is_allowed`].
+    pub fn rate_limited(rate_limit: rate_limit::RateLimit) -> Self {
+        AppError::TooManyRequests {
+            message: "Too many requests, slow down".into(),
+            retry_after_seconds: rate_limit.retry_after_seconds.unwrap_or_default(),
+            rate_limit: Some(rate_limit),
+        }
+    }
 }
 
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
+        let (retry_after_seconds, rate_limit) = match &self {
+            AppError::TooManyRequests {
+                retry_after_seconds,
+                rate_limit,
+                ..
+            } => (Some(*retry_after_seconds), rate_limit.clone()),
+            _ => (None, None),
+        };
+
         let (status, error_message) = match self {
             AppError::InternalServerError(_inner) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -66,13 +100,23 @@ impl IntoResponse for AppError {
             AppError::Forbidden(message) => (StatusCode::FORBIDDEN, message),
             AppError::Unauthorized(message) => (StatusCode::UNAUTHORIZED, message),
             AppError::NotFound(message) => (StatusCode::NOT_FOUND, message),
+            AppError::TooManyRequests { message, .. } => (StatusCode::TOO_MANY_REQUESTS, message),
         };
 
         let body = Json(json!({
             "error": error_message,
         }));
 
-        (status, body).into_response()
+        let mut response = (status, body).into_response();
+        if let Some(rate_limit) = rate_limit {
+            rate_limit.set_headers(response.headers_mut());
+        }
+        if let Some(retry_after_seconds) = retry_after_seconds {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_seconds));
+        }
+        response
     }
 }
 
@@ -86,12 +130,14 @@ where
 }
 
 pub mod testing {
-    use std::net::TcpListener;
+    use std::net::{SocketAddr, TcpListener};
 
     use aide::axum::ApiRouter;
     use axum::Router;
     use tracing_subscriber::EnvFilter;
 
+    use crate::dns::{DnsResolver, StaticResolver};
+    use crate::mail::{Mailer, MemoryTransport};
     use crate::{AppState, OpendalUploader};
 
     pub fn test_opendal_uploader() -> OpendalUploader {
@@ -101,7 +147,41 @@ pub mod testing {
         crate::OpendalUploader(std::sync::Arc::new(op))
     }
 
+    pub fn test_mailer() -> Mailer {
+        Mailer(std::sync::Arc::new(MemoryTransport::default()))
+    }
+
+    pub fn test_dns_resolver() -> DnsResolver {
+        DnsResolver(std::sync::Arc::new(StaticResolver::default()))
+    }
+
     pub async fn test_app(pool: sqlx::PgPool, routes: ApiRouter<AppState>) -> String {
+        let (address, _) = test_app_with_mailbox(pool, routes).await;
+        address
+    }
+
+    /// Same as [`test_app`], also returning the mailbox of the emails sent by the app.
+    pub async fn test_app_with_mailbox(
+        pool: sqlx::PgPool, routes: ApiRouter<AppState>,
+    ) -> (String, MemoryTransport) {
+        let mailbox = MemoryTransport::default();
+        let address = spawn_test_app(pool, routes, mailbox.clone(), StaticResolver::default());
+        (address, mailbox)
+    }
+
+    /// Same as [`test_app`], also returning the resolver answering the DNS lookups of the app.
+    pub async fn test_app_with_dns(
+        pool: sqlx::PgPool, routes: ApiRouter<AppState>,
+    ) -> (String, StaticResolver) {
+        let dns = StaticResolver::default();
+        let address = spawn_test_app(pool, routes, MemoryTransport::default(), dns.clone());
+        (address, dns)
+    }
+
+    fn spawn_test_app(
+        pool: sqlx::PgPool, routes: ApiRouter<AppState>, mailbox: MemoryTransport,
+        dns: StaticResolver,
+    ) -> String {
         let listener = TcpListener::bind("127.0.0.1:0").expect("Unable to bind to address");
         let port = listener.local_addr().unwrap().port();
         let env_filter =
@@ -115,6 +195,8 @@ pub mod testing {
         let router: Router = Router::new().merge(routes).with_state(AppState {
             db_pool: pool,
             opendal: test_opendal_uploader(),
+            mailer: Mailer(std::sync::Arc::new(mailbox)),
+            dns: DnsResolver(std::sync::Arc::new(dns)),
         });
 
         tracing::subscriber::set_global_default(subscriber).unwrap_or_default();
@@ -122,7 +204,7 @@ pub mod testing {
         tokio::spawn(async move {
             axum::Server::from_tcp(listener)
                 .unwrap()
-                .serve(router.into_make_service())
+                .serve(router.into_make_service_with_connect_info::<SocketAddr>())
                 .await
                 .expect("failed to start")
         });
@@ -0,0 +1,134 @@
+use std::{
+    ops::Deref,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use serde::Serialize;
+
+/// A plain text email.
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers emails, the transport is picked when the api starts (see `MAIL_TRANSPORT`).
+#[axum::async_trait]
+pub trait MailTransport: Send + Sync {
+    async fn send(&self, mail: Mail) -> Result<(), anyhow::Error>;
+}
+
+#[derive(Clone)]
+pub struct Mailer(pub Arc<dyn MailTransport>);
+
+impl Deref for Mailer {
+    type Target = Arc<dyn MailTransport>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Only logs who the emails are for, their body holds tokens and never reaches the logs.
+#[derive(Debug, Default)]
+pub struct LogTransport;
+
+#[axum::async_trait]
+impl MailTransport for LogTransport {
+    async fn send(&self, mail: Mail) -> Result<(), anyhow::Error> {
+        tracing::info!(to = %mail.to, subject = %mail.subject, "mail not sent, only logged");
+        Ok(())
+    }
+}
+
+/// Sends the emails with the Postmark api, `from` must be a sender signature of the server.
+#[derive(Debug, Clone)]
+pub struct PostmarkTransport {
+    pub server_token: String,
+    pub from: String,
+    client: reqwest::Client,
+}
+
+impl PostmarkTransport {
+    pub fn new(server_token: impl Into<String>, from: impl Into<String>) -> Self {
+        PostmarkTransport {
+            server_token: server_token.into(),
+            from: from.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkEmail<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    text_body: &'a str,
+}
+
+#[axum::async_trait]
+impl MailTransport for PostmarkTransport {
+    async fn send(&self, mail: Mail) -> Result<(), anyhow::Error> {
+        self.client
+            .post("https://api.postmarkapp.com/email")
+            .header("Accept", "application/json")
+            .header("X-Postmark-Server-Token", &self.server_token)
+            .json(&PostmarkEmail {
+                from: &self.from,
+                to: &mail.to,
+                subject: &mail.subject,
+                text_body: &mail.body,
+            })
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Writes each email to a file in a directory, for local testing.
+#[derive(Debug)]
+pub struct FileTransport {
+    pub dir: PathBuf,
+}
+
+#[axum::async_trait]
+impl MailTransport for FileTransport {
+    async fn send(&self, mail: Mail) -> Result<(), anyhow::Error> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_nanos();
+        let path = self.dir.join(format!("{nanos}.eml"));
+        let content = format!(
+            "To: {}\nSubject: {}\n\n{}\n",
+            mail.to, mail.subject, mail.body
+        );
+        tokio::fs::write(path, content).await?;
+        Ok(())
+    }
+}
+
+/// Keeps the emails in memory, tests read them back with [`MemoryTransport::sent`].
+#[derive(Debug, Clone, Default)]
+pub struct MemoryTransport {
+    mails: Arc<Mutex<Vec<Mail>>>,
+}
+
+impl MemoryTransport {
+    pub fn sent(&self) -> Vec<Mail> {
+        self.mails.lock().unwrap().clone()
+    }
+}
+
+#[axum::async_trait]
+impl MailTransport for MemoryTransport {
+    async fn send(&self, mail: Mail) -> Result<(), anyhow::Error> {
+        self.mails.lock().unwrap().push(mail);
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use axum::http::{HeaderMap, HeaderValue};
+use sqlx::PgPool;
+
+/// The requests a client can do: `capacity` at once, refilled at `refill_per_minute`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub capacity: u32,
+    pub refill_per_minute: u32,
+}
+
+impl Quota {
+    /// Refills a token bucket for the elapsed seconds, then takes a token when there's one.
+    /// Returns the tokens left in the bucket.
+    pub fn take(&self, tokens: f64, elapsed_seconds: f64) -> (f64, RateLimit) {
+        let capacity = self.capacity as f64;
+        let per_second = self.refill_per_minute as f64 / 60.0;
+        let tokens = (tokens + elapsed_seconds.max(0.0) * per_second).min(capacity);
+
+        let allowed = tokens >= 1.0;
+        let tokens = if allowed { tokens - 1.0 } else { tokens };
+
+        let rate_limit = RateLimit {
+            limit: self.capacity,
+            remaining: tokens.floor() as u32,
+            reset_seconds: ((capacity - tokens) / per_second).ceil() as u64,
+            retry_after_seconds: (!allowed)
+                .then(|| ((1.0 - tokens) / per_second).ceil().max(1.0) as u64),
+        };
+        (tokens, rate_limit)
+    }
+}
+
+/// The state of a client's limit after a request, sent back in the `RateLimit-*` headers.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the limit is back to full.
+    pub reset_seconds: u64,
+    /// Set when the request was refused, seconds until the next one is allowed.
+    pub retry_after_seconds: Option<u64>,
+}
+
+impl RateLimit {
+    pub fn is_allowed(&self) -> bool {
+        self.retry_after_seconds.is_none()
+    }
+
+    pub fn set_headers(&self, headers: &mut HeaderMap) {
+        headers.insert("RateLimit-Limit", HeaderValue::from(self.limit));
+        headers.insert("RateLimit-Remaining", HeaderValue::from(self.remaining));
+        headers.insert("RateLimit-Reset", HeaderValue::from(self.reset_seconds));
+        if let Some(retry_after) = self.retry_after_seconds {
+            headers.insert("Retry-After", HeaderValue::from(retry_after));
+        }
+    }
+}
+
+/// Keeps the token buckets of the clients, picked when the api starts (see `RATE_LIMIT_BACKEND`).
+#[axum::async_trait]
+pub trait RateLimitBackend: Send + Sync {
+    /// Takes a token from the bucket of the key, which starts full.
+    async fn take(&self, key: &str, quota: Quota) -> Result<RateLimit, anyhow::Error>;
+}
+
+#[derive(Clone)]
+pub struct RateLimiter(pub Arc<dyn RateLimitBackend>);
+
+impl Deref for RateLimiter {
+    type Target = Arc<dyn RateLimitBackend>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Past this many buckets, the least recently used ones are dropped.
+const MEMORY_BACKEND_CAPACITY: usize = 100_000;
+
+/// How many buckets are dropped at once, so they aren't sorted for every new client.
+const MEMORY_BACKEND_EVICTIONS: usize = MEMORY_BACKEND_CAPACITY / 10;
+
+/// Buckets kept in the process, each instance of the api limits on its own.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    buckets: Mutex<HashMap<String, (f64, Instant)>>,
+}
+
+/// Drops the buckets used the longest ago, removing one starts it full again.
+fn evict_least_recently_used(buckets: &mut HashMap<String, (f64, Instant)>) {
+    let mut used_at = buckets
+        .values()
+        .map(|(_, updated_at)| *updated_at)
+        .collect::<Vec<_>>();
+    let (_, cutoff, _) = used_at.select_nth_unstable(MEMORY_BACKEND_EVICTIONS);
+    let cutoff = *cutoff;
+    buckets.retain(|_, (_, updated_at)| *updated_at > cutoff);
+}
+
+#[axum::async_trait]
+impl RateLimitBackend for MemoryBackend {
+    async fn take(&self, key: &str, quota: Quota) -> Result<RateLimit, anyhow::Error> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+
+        if buckets.len() >= MEMORY_BACKEND_CAPACITY && !buckets.contains_key(key) {
+            evict_least_recently_used(&mut buckets);
+        }
+
+        let (tokens, elapsed) = match buckets.get(key) {
+            Some((tokens, updated_at)) => (*tokens, (now - *updated_at).as_secs_f64()),
+            None => (quota.capacity as f64, 0.0),
+        };
+        let (tokens, rate_limit) = quota.take(tokens, elapsed);
+        buckets.insert(key.to_string(), (tokens, now));
+        Ok(rate_limit)
+    }
+}
+
+/// Removes the idle buckets once every this many requests.
+const POSTGRES_BACKEND_CLEANUP_EVERY: u64 = 1_000;
+
+/// Buckets kept in `public.rate_limit_buckets`, shared by every instance of the api.
+#[derive(Debug)]
+pub struct PostgresBackend {
+    pool: PgPool,
+    requests: AtomicU64,
+}
+
+impl PostgresBackend {
+    pub fn new(pool: PgPool) -> Self {
+        PostgresBackend {
+            pool,
+            requests: AtomicU64::new(0),
+        }
+    }
+}
+
+#[axum::async_trait]
+impl RateLimitBackend for PostgresBackend {
+    async fn take(&self, key: &str, quota: Quota) -> Result<RateLimit, anyhow::Error> {
+        if self.requests.fetch_add(1, Ordering::Relaxed) % POSTGRES_BACKEND_CLEANUP_EVERY == 0 {
+            sqlx::query!(
+                r#"
+                DELETE FROM public.rate_limit_buckets
+                WHERE updated_at < NOW() - INTERVAL '1 hour'
+                "#
+            )
+            .execute(&self.pool)
+            .await?;
+        }
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+            INSERT INTO public.rate_limit_buckets (key, tokens, updated_at)
+            VALUES ($1, $2, clock_timestamp())
+            ON CONFLICT (key) DO NOTHING
+            "#,
+            key,
+            quota.capacity as f64,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        let bucket = sqlx::query!(
+            r#"
+            SELECT tokens, EXTRACT(EPOCH FROM clock_timestamp() - updated_at)::float8 as "elapsed!"
+            FROM public.rate_limit_buckets
+            WHERE key = $1
+            FOR UPDATE
+            "#,
+            key,
+        )
+        .fetch_one(&mut tx)
+        .await?;
+
+        let (tokens, rate_limit) = quota.take(bucket.tokens, bucket.elapsed);
+
+        sqlx::query!(
+            r#"
+            UPDATE public.rate_limit_buckets
+            SET tokens = $2, updated_at = clock_timestamp()
+            WHERE key = $1
+            "#,
+            key,
+            tokens,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(rate_limit)
+    }
+}